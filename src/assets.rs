@@ -0,0 +1,417 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::digest::SHA256_LEN;
+
+/// Environment variable that lists the published assets, one
+/// `<fd> <name>` line per file.
+pub const ENV_ASSETS: &str = "XSFX_ASSETS";
+/// Longest accepted asset name (relative path inside the bundle).
+pub const ASSET_NAME_MAX: usize = 1024;
+
+/// Bytes of an archive entry header without the name.
+const ENTRY_HEADER_SIZE: usize = 2 + 4 + 8;
+
+fn asset_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Asset archive summary recorded in the metadata block. The compressed
+/// archive sits between the payload and the metadata block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssetsInfo {
+    pub count: u32,
+    pub compressed_len: u64,
+    /// Size of the uncompressed archive, used to bound decompression.
+    pub archive_len: u64,
+    pub sha256: [u8; SHA256_LEN],
+}
+
+impl AssetsInfo {
+    const ENCODED_LEN: usize = 4 + 8 + 8 + SHA256_LEN;
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::ENCODED_LEN);
+        out.extend_from_slice(&self.count.to_le_bytes());
+        out.extend_from_slice(&self.compressed_len.to_le_bytes());
+        out.extend_from_slice(&self.archive_len.to_le_bytes());
+        out.extend_from_slice(&self.sha256);
+        out
+    }
+
+    pub fn from_bytes(value: &[u8]) -> io::Result<Self> {
+        if value.len() != Self::ENCODED_LEN {
+            return Err(asset_err("Invalid assets record"));
+        }
+        let u64_at = |i: usize| u64::from_le_bytes(value[i..i + 8].try_into().unwrap());
+        Ok(Self {
+            count: u32::from_le_bytes(value[..4].try_into().unwrap()),
+            compressed_len: u64_at(4),
+            archive_len: u64_at(12),
+            sha256: value[20..].try_into().unwrap(),
+        })
+    }
+}
+
+/// A file to bundle next to the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Asset {
+    pub name: String,
+    /// Permission bits (`0o777` at most).
+    pub mode: u32,
+    pub data: Vec<u8>,
+}
+
+/// An asset inside a decoded archive.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AssetRef<'a> {
+    pub name: &'a str,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+/// Parse `--add <path>[=<dest>]`. Without `=dest` the file or directory
+/// keeps its own name.
+pub fn parse_add(s: &str) -> Option<(String, String)> {
+    let (src, dest) = match s.split_once('=') {
+        Some((src, dest)) => (src, dest.trim_matches('/').to_string()),
+        None => (s, Path::new(s).file_name()?.to_str()?.to_string()),
+    };
+    if src.is_empty() || validate_name(&dest).is_err() {
+        return None;
+    }
+    Some((src.to_string(), dest))
+}
+
+/// Asset names are relative paths of plain components: no `.`/`..`, no
+/// empty components, and no NUL, newline or backslash (the manifest is
+/// line-based).
+pub fn validate_name(name: &str) -> io::Result<()> {
+    let ok = !name.is_empty()
+        && name.len() <= ASSET_NAME_MAX
+        && !name.contains(['\0', '\n', '\\'])
+        && name
+            .split('/')
+            .all(|c| !c.is_empty() && c != "." && c != "..");
+    if !ok {
+        return Err(asset_err("Invalid asset name"));
+    }
+    Ok(())
+}
+
+/// Read the `(source, dest)` pairs from disk. Directories are added
+/// recursively, in name order, below their destination.
+pub fn collect_assets(specs: &[(String, String)]) -> io::Result<Vec<Asset>> {
+    let mut assets = Vec::new();
+    for (src, dest) in specs {
+        add_path(Path::new(src), dest, &mut assets)?;
+    }
+    let mut seen = HashSet::new();
+    for asset in &assets {
+        if !seen.insert(asset.name.as_str()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate asset '{}'", asset.name),
+            ));
+        }
+    }
+    Ok(assets)
+}
+
+fn add_path(src: &Path, dest: &str, out: &mut Vec<Asset>) -> io::Result<()> {
+    validate_name(dest)?;
+    let meta = fs::metadata(src)?;
+    if meta.is_dir() {
+        let mut entries = fs::read_dir(src)?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name
+                .to_str()
+                .ok_or_else(|| asset_err("Asset file name is not valid UTF-8"))?;
+            add_path(&entry.path(), &format!("{}/{}", dest, name), out)?;
+        }
+        return Ok(());
+    }
+    if !meta.is_file() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Not a regular file: {}", src.display()),
+        ));
+    }
+    #[cfg(unix)]
+    let mode = std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o777;
+    #[cfg(not(unix))]
+    let mode = 0o644;
+    out.push(Asset {
+        name: dest.to_string(),
+        mode,
+        data: fs::read(src)?,
+    });
+    Ok(())
+}
+
+/// Encode assets as `[count u32]` followed by one `[name_len u16][name]
+/// [mode u32][len u64]` header per asset, then all contents in order.
+pub fn encode_archive(assets: &[Asset]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(assets.len() as u32).to_le_bytes());
+    for a in assets {
+        out.extend_from_slice(&(a.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(a.name.as_bytes());
+        out.extend_from_slice(&a.mode.to_le_bytes());
+        out.extend_from_slice(&(a.data.len() as u64).to_le_bytes());
+    }
+    for a in assets {
+        out.extend_from_slice(&a.data);
+    }
+    out
+}
+
+/// Decode an archive written by `encode_archive`. The contents must fill
+/// the rest of the archive exactly.
+pub fn parse_archive(data: &[u8]) -> io::Result<Vec<AssetRef<'_>>> {
+    let truncated = || asset_err("Truncated asset archive");
+    let take = |pos: &mut usize, n: usize| -> io::Result<&[u8]> {
+        let end = pos.checked_add(n).ok_or_else(truncated)?;
+        let bytes = data.get(*pos..end).ok_or_else(truncated)?;
+        *pos = end;
+        Ok(bytes)
+    };
+    let mut pos = 0;
+    let count = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap()) as usize;
+    if count > (data.len() - pos) / ENTRY_HEADER_SIZE {
+        return Err(truncated());
+    }
+    let mut headers = Vec::with_capacity(count);
+    for _ in 0..count {
+        let name_len = u16::from_le_bytes(take(&mut pos, 2)?.try_into().unwrap()) as usize;
+        let name = std::str::from_utf8(take(&mut pos, name_len)?)
+            .map_err(|_| asset_err("Asset name is not valid UTF-8"))?;
+        validate_name(name)?;
+        let mode = u32::from_le_bytes(take(&mut pos, 4)?.try_into().unwrap());
+        if mode & !0o777 != 0 {
+            return Err(asset_err("Invalid asset mode"));
+        }
+        let len = u64::from_le_bytes(take(&mut pos, 8)?.try_into().unwrap());
+        headers.push((name, mode, usize::try_from(len).map_err(|_| truncated())?));
+    }
+    let mut assets = Vec::with_capacity(count);
+    for (name, mode, len) in headers {
+        let data = take(&mut pos, len)?;
+        assets.push(AssetRef { name, mode, data });
+    }
+    if pos != data.len() {
+        return Err(asset_err("Trailing data in asset archive"));
+    }
+    Ok(assets)
+}
+
+/// `XSFX_ASSETS` value for published `(fd, name)` pairs.
+pub fn format_manifest(entries: &[(i32, &str)]) -> String {
+    entries
+        .iter()
+        .map(|(fd, name)| format!("{} {}", fd, name))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// `(fd, name)` pairs of an `XSFX_ASSETS` value; malformed lines are skipped.
+pub fn parse_manifest(manifest: &str) -> Vec<(i32, &str)> {
+    manifest
+        .lines()
+        .filter_map(|line| {
+            let (fd, name) = line.split_once(' ')?;
+            Some((fd.parse().ok()?, name))
+        })
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{publish, rewind};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{format_manifest, parse_archive, parse_manifest};
+    use crate::memfd::{create_sealed_memfd, into_inherited_fd};
+    use std::ffi::CString;
+    use std::io;
+
+    /// Copy every asset into its own sealed memfd that stays open across
+    /// `exec`, and return the manifest for `XSFX_ASSETS`.
+    pub fn publish(archive: &[u8]) -> io::Result<String> {
+        let assets = parse_archive(archive)?;
+        let mut entries = Vec::with_capacity(assets.len());
+        for asset in &assets {
+            // The memfd name only shows up in /proc; keep it short.
+            let base: String = asset
+                .name
+                .rsplit('/')
+                .next()
+                .unwrap_or("")
+                .chars()
+                .take(64)
+                .collect();
+            let name = CString::new(format!("xsfx:{}", base))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            let fd = into_inherited_fd(create_sealed_memfd(&name, asset.data, asset.mode)?.file)?;
+            entries.push((fd, asset.name));
+        }
+        let manifest = format_manifest(&entries);
+        rewind(&manifest);
+        Ok(manifest)
+    }
+
+    /// Move every published fd back to offset 0. Inherited fds share their
+    /// offset, so a restarted payload would otherwise read from the end.
+    pub fn rewind(manifest: &str) {
+        for (fd, _) in parse_manifest(manifest) {
+            unsafe { libc::lseek(fd, 0, libc::SEEK_SET) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str, data: &[u8]) -> Asset {
+        Asset {
+            name: name.into(),
+            mode: 0o644,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_archive_roundtrip() {
+        let assets = [asset("conf/app.toml", b"port = 80"), asset("empty", b"")];
+        let archive = encode_archive(&assets);
+        let parsed = parse_archive(&archive).unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(parsed[0].name, "conf/app.toml");
+        assert_eq!(parsed[0].data, b"port = 80");
+        assert_eq!(parsed[0].mode, 0o644);
+        assert_eq!(parsed[1].data, b"");
+        assert!(parse_archive(&encode_archive(&[])).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_assets_info_roundtrip() {
+        let info = AssetsInfo {
+            count: 3,
+            compressed_len: 100,
+            archive_len: 400,
+            sha256: [7; SHA256_LEN],
+        };
+        assert_eq!(AssetsInfo::from_bytes(&info.to_bytes()).unwrap(), info);
+        assert!(AssetsInfo::from_bytes(&[0; 10]).is_err());
+    }
+
+    #[test]
+    fn test_parse_add() {
+        assert_eq!(
+            parse_add("build/app.toml=conf/app.toml"),
+            Some(("build/app.toml".into(), "conf/app.toml".into()))
+        );
+        assert_eq!(
+            parse_add("./data/"),
+            Some(("./data/".into(), "data".into()))
+        );
+        assert_eq!(
+            parse_add("plugins=lib/"),
+            Some(("plugins".into(), "lib".into()))
+        );
+        assert_eq!(parse_add("x=../etc"), None);
+        assert_eq!(parse_add("=dest"), None);
+        assert_eq!(parse_add("x="), None);
+    }
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let m = format_manifest(&[(3, "a.txt"), (4, "dir/b c.bin")]);
+        assert_eq!(m, "3 a.txt\n4 dir/b c.bin");
+        assert_eq!(parse_manifest(&m), vec![(3, "a.txt"), (4, "dir/b c.bin")]);
+        assert_eq!(parse_manifest("x y\n5\n6 ok"), vec![(6, "ok")]);
+    }
+
+    #[test]
+    fn test_collect_assets_from_directory() {
+        let dir = std::env::temp_dir().join(format!("xsfx-assets-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.txt"), b"b").unwrap();
+        fs::write(dir.join("sub/a.txt"), b"a").unwrap();
+        let src = dir.to_str().unwrap().to_string();
+        let assets = collect_assets(&[(src.clone(), "data".into())]).unwrap();
+        let names: Vec<_> = assets.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, ["data/b.txt", "data/sub/a.txt"]);
+        let dup = collect_assets(&[(src.clone(), "d".into()), (src, "d".into())]);
+        assert_eq!(dup.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sec_asset_names() {
+        for bad in [
+            "",
+            "/etc/passwd",
+            "a/../b",
+            "./a",
+            "a//b",
+            "a\nb",
+            "a\\b",
+            "a/",
+        ] {
+            assert!(validate_name(bad).is_err(), "{:?}", bad);
+        }
+        assert!(validate_name(&"a".repeat(ASSET_NAME_MAX + 1)).is_err());
+        assert!(validate_name("conf/app.toml").is_ok());
+    }
+
+    #[test]
+    fn test_sec_archive_malformed() {
+        let archive = encode_archive(&[asset("a", b"abc")]);
+        // Truncated contents, trailing bytes, bogus count, traversal name.
+        assert!(parse_archive(&archive[..archive.len() - 1]).is_err());
+        let mut long = archive.clone();
+        long.push(0);
+        assert!(parse_archive(&long).is_err());
+        let mut count = archive.clone();
+        count[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_archive(&count).is_err());
+        let evil = encode_archive(&[asset("..", b"x")]);
+        assert!(parse_archive(&evil).is_err());
+        let mut mode = archive;
+        mode[7..11].copy_from_slice(&0o4755u32.to_le_bytes());
+        assert!(parse_archive(&mode).is_err());
+        assert!(parse_archive(&[]).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_publish_assets() {
+        use std::io::Read;
+        use std::os::unix::io::FromRawFd;
+        let archive = encode_archive(&[asset("conf/app.toml", b"port = 80")]);
+        let manifest = publish(&archive).unwrap();
+        let entries = parse_manifest(&manifest);
+        assert_eq!(entries.len(), 1);
+        let (fd, name) = entries[0];
+        assert_eq!(name, "conf/app.toml");
+        assert_eq!(
+            unsafe { libc::fcntl(fd, libc::F_GETFD) } & libc::FD_CLOEXEC,
+            0
+        );
+        let mut f = unsafe { fs::File::from_raw_fd(fd) };
+        let mut buf = String::new();
+        f.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "port = 80");
+        rewind(&manifest);
+        buf.clear();
+        f.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "port = 80");
+    }
+}
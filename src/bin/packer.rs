@@ -1,50 +1,830 @@
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
+use std::path::Path;
 
+use xsfx::assets::{collect_assets, encode_archive, parse_add, Asset, AssetsInfo};
+use xsfx::cache::{parse_size, DEFAULT_CACHE_MAX_SIZE};
 use xsfx::common::Trailer;
-use xsfx::compress::compress_lzma;
+use xsfx::compress::{compress_deflate, compress_lzma};
+use xsfx::crypto::{file_secret, parse_raw_key, Encryption, Key};
+use xsfx::digest::sha256;
+use xsfx::dynlink::bundle_libs;
+use xsfx::elf::{check_payload, is_elf, parse_elf};
+use xsfx::elf_section::{add_section, SECTION_NAME};
+use xsfx::install::{collect_tree, encode_tree, InstallSpec};
+use xsfx::isolate::{parse_namespaces, Namespaces};
+use xsfx::launch::{parse_nice, parse_rlimit, parse_umask, parse_workdir, LaunchSettings};
+use xsfx::metadata::{Codec, Metadata};
+use xsfx::multiarch::{build_launcher, LAUNCHER_ARCHES};
+use xsfx::multicall::parse_tool;
+use xsfx::platform::{check_target, detect_platforms, pick_target};
+use xsfx::sandbox::{parse_deny_list, SandboxPolicy};
+use xsfx::strip::{strip_elf, StripMode};
+use xsfx::supervise::{parse_backoff, SupervisorPolicy};
+use xsfx::validity::{now_unix, parse_time};
+use xsfx::variant::{baseline_level, parse_variant, same_arch};
+use xsfx::zip::{end_of_central_directory, ZipEntry, LOCAL_HEADER_SIZE};
 
 mod stub_catalog {
     include!(concat!(env!("OUT_DIR"), "/stub_catalog.rs"));
 }
 
+#[derive(Default)]
 struct PackerArgs {
     payload_path: String,
     output_path: String,
     target: String,
+    expose_env: bool,
+    payload_version: Option<String>,
+    debug: bool,
+    cache_max_size: Option<u64>,
+    runtime_flags: bool,
+    encrypt: Option<EncryptSource>,
+    not_before: Option<u64>,
+    not_after: Option<u64>,
+    sandbox: SandboxPolicy,
+    launch: LaunchSettings,
+    supervise: Option<SupervisorPolicy>,
+    isolate: Namespaces,
+    /// ZIP entry name when writing a ZIP-compatible SFX (`--zip`).
+    zip_entry: Option<String>,
+    /// `(source, dest)` pairs from `--add`.
+    assets: Vec<(String, String)>,
+    /// Installer mode: the input is a directory tree to extract.
+    install: Option<InstallSpec>,
+    /// `(path, name)` of every tool of a multi-call SFX, the input first.
+    tools: Vec<(String, String)>,
+    /// `(path, level)` of every CPU variant, the input (baseline) first.
+    variants: Vec<(String, String)>,
+    /// Sysroot to take the loader and shared libraries of a dynamically
+    /// linked payload from (`--bundle-libs`).
+    bundle_libs: Option<String>,
+    /// Write a multi-architecture launcher (`--multiarch`).
+    multiarch: bool,
+    /// `(arch, path)` pairs from `--arch-payload`.
+    arch_payloads: Vec<(String, String)>,
+    /// Pack payloads detected as built for another OS or architecture.
+    allow_target_mismatch: bool,
+    /// Remove unloaded sections from ELF payloads (`--strip`, `--strip-debug`).
+    strip: Option<StripMode>,
+    /// Store the SFX data in an ELF section of the stub (`--elf-section`).
+    elf_section: bool,
+}
+
+/// Where the packer reads the payload encryption secret from.
+enum EncryptSource {
+    KeyFile(String),
+    PassphraseFile(String),
 }
 
 fn print_usage(prog: &str) {
-    eprintln!("Usage: {} <input> <output> [--target <triple>]", prog);
+    eprintln!(
+        "Usage: {} <input> <output> [--target <triple>] [options]",
+        prog
+    );
     eprintln!("  Use '-' for input to read from stdin, '-' for output to write to stdout.");
+    eprintln!("Options:");
+    eprintln!(
+        "  --target auto             Use the stub matching the payload (default without --target)"
+    );
+    eprintln!("  --allow-target-mismatch   Pack a payload built for another OS or architecture");
+    eprintln!("  --expose-env              Export XSFX_SFX_* / XSFX_PAYLOAD_* vars to the payload");
+    eprintln!("  --payload-version <text>  Version string recorded in the SFX metadata");
+    eprintln!("  --debug                   Always print stub diagnostics and phase timings");
+    eprintln!(
+        "  --cache                   Cache the decompressed payload per user for fast relaunch"
+    );
+    eprintln!("  --cache-max-size <size>   Cache size cap, e.g. 256M or 2G (default 512M)");
+    eprintln!("  --runtime-flags           Let the SFX handle --xsfx-info/-verify/-extract itself");
+    eprintln!("  --encrypt-key-file <path> Encrypt with a 32-byte key (raw or 64 hex digits)");
+    eprintln!("  --encrypt-passphrase-file <path>");
+    eprintln!("                            Encrypt with an Argon2id-stretched passphrase");
+    eprintln!("  --not-before <time>       Refuse to run before <time> (UTC)");
+    eprintln!("  --not-after <time>        Refuse to run at or after <time> (UTC)");
+    eprintln!(
+        "                            <time>: 2026-01-31[T12:00[:00]][Z], epoch seconds, +30d/+12h"
+    );
+    eprintln!(
+        "  --add <path>[=<dest>]     Bundle a file or directory as an asset (Linux, repeatable)"
+    );
+    eprintln!(
+        "  --zip                     Also make the SFX a valid ZIP archive (Deflate payload)"
+    );
+    eprintln!("  --tool <path>[=<name>]    Add another tool: multi-call SFX picking by argv[0] or");
+    eprintln!("                            first argument (repeatable; <input> is the first tool)");
+    eprintln!(
+        "  --variant <level>=<path>  CPU-specific build (x86-64-v2..v4, aarch64-lse/-sve/-sve2);"
+    );
+    eprintln!(
+        "                            the best one for the host runs (<input> is the baseline)"
+    );
+    eprintln!(
+        "  --bundle-libs             Pack a dynamically linked ELF with its ld.so and libraries"
+    );
+    eprintln!("  --sysroot <dir>           Where --bundle-libs finds them (default /)");
+    eprintln!(
+        "  --multiarch               Write an sh launcher holding x86_64 and aarch64 Linux SFXs"
+    );
+    eprintln!(
+        "  --arch-payload <arch>=<path>  Payload for one --multiarch architecture (default: <input>)"
+    );
+    eprintln!(
+        "  --strip                   Remove symbols, debug info and other unloaded ELF sections"
+    );
+    eprintln!("  --strip-debug             Remove only the debug sections of ELF payloads");
+    eprintln!(
+        "  --elf-section             Store the payload in an .xsfx ELF section (survives strip)"
+    );
+    eprintln!("Installer mode (<input> is a directory):");
+    eprintln!("  --install-dir <dir>       Default --target-dir (a leading ~/ is the home dir)");
+    eprintln!("  --post-extract <cmd>      Shell command run in the extracted tree");
+}
+
+fn usage_exit(prog: &str) -> ! {
+    print_usage(prog);
+    list_available_stubs();
+    std::process::exit(1);
+}
+
+fn flag_value(args: &[String], idx: &mut usize) -> String {
+    *idx += 1;
+    match args.get(*idx) {
+        Some(v) => v.clone(),
+        None => usage_exit(&args[0]),
+    }
+}
+
+/// Options that only shape `PackerArgs` and are resolved in `finish_args`.
+#[derive(Default)]
+struct ArgFlags {
+    target: Option<String>,
+    sysroot: Option<String>,
+    install_dir: Option<String>,
+    post_extract: Option<String>,
 }
 
 fn parse_args() -> PackerArgs {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 3 || args.len() > 5 {
-        print_usage(&args[0]);
-        list_available_stubs();
+    let argv: Vec<String> = env::args().collect();
+    if argv.len() < 3 {
+        usage_exit(&argv[0]);
+    }
+    let mut args = PackerArgs {
+        payload_path: argv[1].clone(),
+        output_path: argv[2].clone(),
+        ..PackerArgs::default()
+    };
+    let mut flags = ArgFlags::default();
+    let mut idx = 3;
+    while idx < argv.len() {
+        match argv[idx].as_str() {
+            "--target" => flags.target = Some(flag_value(&argv, &mut idx)),
+            "--allow-target-mismatch" => args.allow_target_mismatch = true,
+            "--expose-env" => args.expose_env = true,
+            "--payload-version" => args.payload_version = Some(flag_value(&argv, &mut idx)),
+            "--debug" => args.debug = true,
+            "--cache" => {
+                args.cache_max_size.get_or_insert(DEFAULT_CACHE_MAX_SIZE);
+            }
+            "--cache-max-size" => match parse_size(&flag_value(&argv, &mut idx)) {
+                Some(n) => args.cache_max_size = Some(n),
+                None => usage_exit(&argv[0]),
+            },
+            "--runtime-flags" => args.runtime_flags = true,
+            "--zip" => args.zip_entry = Some(zip_entry_name(&argv[1])),
+            "--add" => match parse_add(&flag_value(&argv, &mut idx)) {
+                Some(spec) => args.assets.push(spec),
+                None => usage_exit(&argv[0]),
+            },
+            "--tool" => match parse_tool(&flag_value(&argv, &mut idx)) {
+                Some(tool) => args.tools.push(tool),
+                None => usage_exit(&argv[0]),
+            },
+            "--variant" => match parse_variant(&flag_value(&argv, &mut idx)) {
+                Some((level, path)) => args.variants.push((path, level)),
+                None => usage_exit(&argv[0]),
+            },
+            "--bundle-libs" => {
+                args.bundle_libs.get_or_insert_with(|| "/".to_string());
+            }
+            "--strip" => args.strip = Some(StripMode::All),
+            "--elf-section" => args.elf_section = true,
+            "--strip-debug" => {
+                args.strip.get_or_insert(StripMode::Debug);
+            }
+            "--sysroot" => flags.sysroot = Some(flag_value(&argv, &mut idx)),
+            "--multiarch" => args.multiarch = true,
+            "--arch-payload" => {
+                let value = flag_value(&argv, &mut idx);
+                match value.split_once('=') {
+                    Some((arch, path))
+                        if !path.is_empty()
+                            && LAUNCHER_ARCHES.iter().any(|a| a.name == arch)
+                            && !args.arch_payloads.iter().any(|(a, _)| a == arch) =>
+                    {
+                        args.arch_payloads
+                            .push((arch.to_string(), path.to_string()))
+                    }
+                    _ => usage_exit(&argv[0]),
+                }
+            }
+            "--install-dir" | "--post-extract" => {
+                let value = flag_value(&argv, &mut idx);
+                if value.is_empty() || value.contains('\0') {
+                    usage_exit(&argv[0]);
+                }
+                if argv[idx - 1] == "--install-dir" {
+                    flags.install_dir = Some(value);
+                } else {
+                    flags.post_extract = Some(value);
+                }
+            }
+            "--no-new-privs" => args.sandbox.no_new_privs = true,
+            "--sandbox-strict" => args.sandbox.strict = true,
+            "--landlock-ro" => args.sandbox.fs_read.push(flag_value(&argv, &mut idx)),
+            "--landlock-rw" => args.sandbox.fs_write.push(flag_value(&argv, &mut idx)),
+            "--rlimit" => match parse_rlimit(&flag_value(&argv, &mut idx)) {
+                Some(l) => {
+                    args.launch.rlimits.retain(|o| o.resource != l.resource);
+                    args.launch.rlimits.push(l);
+                }
+                None => usage_exit(&argv[0]),
+            },
+            "--umask" => match parse_umask(&flag_value(&argv, &mut idx)) {
+                Some(m) => args.launch.umask = Some(m),
+                None => usage_exit(&argv[0]),
+            },
+            "--workdir" => match parse_workdir(&flag_value(&argv, &mut idx)) {
+                Some(w) => args.launch.workdir = Some(w),
+                None => usage_exit(&argv[0]),
+            },
+            "--nice" => match parse_nice(&flag_value(&argv, &mut idx)) {
+                Some(n) => args.launch.nice = Some(n),
+                None => usage_exit(&argv[0]),
+            },
+            "--supervise" => {
+                args.supervise.get_or_insert_with(SupervisorPolicy::default);
+            }
+            "--pre-start" | "--post-exit" => {
+                let cmd = flag_value(&argv, &mut idx);
+                if cmd.is_empty() || cmd.contains('\0') {
+                    usage_exit(&argv[0]);
+                }
+                let policy = args.supervise.get_or_insert_with(SupervisorPolicy::default);
+                if argv[idx - 1] == "--pre-start" {
+                    policy.pre_start = Some(cmd);
+                } else {
+                    policy.post_exit = Some(cmd);
+                }
+            }
+            "--restart-on-crash" => match flag_value(&argv, &mut idx).parse() {
+                Ok(n) => {
+                    args.supervise
+                        .get_or_insert_with(SupervisorPolicy::default)
+                        .max_restarts = n
+                }
+                Err(_) => usage_exit(&argv[0]),
+            },
+            "--restart-backoff" => match parse_backoff(&flag_value(&argv, &mut idx)) {
+                Some((initial, max)) => {
+                    let policy = args.supervise.get_or_insert_with(SupervisorPolicy::default);
+                    policy.backoff_initial_ms = initial;
+                    policy.backoff_max_ms = max;
+                }
+                None => usage_exit(&argv[0]),
+            },
+            "--isolate" => match parse_namespaces(&flag_value(&argv, &mut idx)) {
+                Ok(ns) => args.isolate = ns,
+                Err(msg) => {
+                    eprintln!("{}", msg);
+                    usage_exit(&argv[0]);
+                }
+            },
+            "--seccomp-deny" => match parse_deny_list(&flag_value(&argv, &mut idx)) {
+                Ok(names) => args.sandbox.seccomp_deny.extend(names),
+                Err(msg) => {
+                    eprintln!("{}", msg);
+                    usage_exit(&argv[0]);
+                }
+            },
+            "--not-before" | "--not-after" => {
+                let value = flag_value(&argv, &mut idx);
+                let Some(t) = parse_time(&value, now_unix()) else {
+                    eprintln!("Invalid time: {}", value);
+                    usage_exit(&argv[0]);
+                };
+                if argv[idx - 1] == "--not-before" {
+                    args.not_before = Some(t);
+                } else {
+                    args.not_after = Some(t);
+                }
+            }
+            "--encrypt-key-file" | "--encrypt-passphrase-file" => {
+                let path = flag_value(&argv, &mut idx);
+                let source = if argv[idx - 1] == "--encrypt-key-file" {
+                    EncryptSource::KeyFile(path)
+                } else {
+                    EncryptSource::PassphraseFile(path)
+                };
+                if args.encrypt.replace(source).is_some() {
+                    usage_exit(&argv[0]);
+                }
+            }
+            _ => usage_exit(&argv[0]),
+        }
+        idx += 1;
+    }
+    if let Err(msg) = finish_args(&mut args, flags) {
+        eprintln!("{}", msg);
         std::process::exit(1);
     }
-    let mut selected_target: Option<String> = None;
-    if args.len() > 3 {
-        if args.len() == 5 && args[3] == "--target" {
-            selected_target = Some(args[4].clone());
-        } else {
-            print_usage(&args[0]);
-            list_available_stubs();
+    args
+}
+
+/// Resolve the target and installer mode, then check option combinations.
+fn finish_args(args: &mut PackerArgs, flags: ArgFlags) -> Result<(), String> {
+    validate_multiarch(args, &flags)?;
+    args.target = resolve_target(args, flags.target.clone());
+    args.install = Path::new(&args.payload_path).is_dir().then(|| InstallSpec {
+        default_dir: flags.install_dir.clone(),
+        post_extract: flags.post_extract.clone(),
+    });
+    validate_encryption(args)?;
+    validate_assets(args)?;
+    validate_install(args, &flags)?;
+    validate_tools(args)?;
+    validate_validity(args)?;
+    validate_launch(args)?;
+    validate_supervise(args)?;
+    validate_isolate(args)?;
+    validate_variants(args)?;
+    validate_bundle_libs(args, flags.sysroot)?;
+    validate_elf_section(args)?;
+    validate_strip(args)
+}
+
+/// The stub target: fixed for `--multiarch`, else `--target`,
+/// `XSFX_OUT_TARGET` or the one detected from the payload.
+fn resolve_target(args: &PackerArgs, selected: Option<String>) -> String {
+    if args.multiarch {
+        // Every launcher architecture is a Linux target.
+        return LAUNCHER_ARCHES[0].target.to_string();
+    }
+    let (path, mismatch) = (&args.payload_path, args.allow_target_mismatch);
+    match selected.or_else(|| env::var("XSFX_OUT_TARGET").ok()) {
+        Some(t) if t == "auto" => detect_target(path, true, mismatch),
+        Some(t) => t,
+        None => detect_target(path, false, mismatch),
+    }
+}
+
+fn validate_multiarch(args: &PackerArgs, flags: &ArgFlags) -> Result<(), String> {
+    if !args.arch_payloads.is_empty() && !args.multiarch {
+        return Err("--arch-payload requires --multiarch.".into());
+    }
+    let arch_payloads = !args.arch_payloads.is_empty();
+    let install = Path::new(&args.payload_path).is_dir();
+    if args.multiarch
+        && (flags.target.is_some()
+            || args.zip_entry.is_some()
+            || (arch_payloads && (install || !args.tools.is_empty())))
+    {
+        return Err(
+            "--multiarch cannot be combined with --target or --zip, and --arch-payload\n\
+                    not with --tool or a directory input."
+                .into(),
+        );
+    }
+    Ok(())
+}
+
+fn validate_encryption(args: &PackerArgs) -> Result<(), String> {
+    if args.encrypt.is_none() {
+        return Ok(());
+    }
+    if args.cache_max_size.is_some() {
+        return Err(
+            "--cache cannot be combined with encryption: the cache stores plaintext.".into(),
+        );
+    }
+    if args.zip_entry.is_some() {
+        return Err(
+            "--zip cannot be combined with encryption: ZIP readers could not open it.".into(),
+        );
+    }
+    Ok(())
+}
+
+fn validate_assets(args: &PackerArgs) -> Result<(), String> {
+    if args.assets.is_empty() {
+        return Ok(());
+    }
+    if args.zip_entry.is_some() || args.encrypt.is_some() {
+        return Err("--add cannot be combined with --zip or encryption.".into());
+    }
+    if !args.target.contains("linux") {
+        return Err("--add is only supported for Linux targets.".into());
+    }
+    Ok(())
+}
+
+fn validate_install(args: &PackerArgs, flags: &ArgFlags) -> Result<(), String> {
+    if args.install.is_none() {
+        if flags.install_dir.is_some() || flags.post_extract.is_some() {
+            return Err("--install-dir and --post-extract require a directory as input.".into());
+        }
+        return Ok(());
+    }
+    if args.zip_entry.is_some()
+        || args.cache_max_size.is_some()
+        || !args.assets.is_empty()
+        || args.supervise.is_some()
+        || !args.isolate.is_empty()
+        || !args.sandbox.is_empty()
+        || !args.launch.is_empty()
+    {
+        return Err(
+            "A directory input (installer mode) cannot be combined with --zip, --add, --cache,\n\
+             supervisor, isolation, sandbox or launch options."
+                .into(),
+        );
+    }
+    if args.target.contains("windows") {
+        return Err("Installer mode is not supported for Windows targets.".into());
+    }
+    Ok(())
+}
+
+/// Check `--tool` and put the input first as the default tool.
+fn validate_tools(args: &mut PackerArgs) -> Result<(), String> {
+    if args.tools.is_empty() {
+        return Ok(());
+    }
+    if args.zip_entry.is_some() || args.install.is_some() {
+        return Err("--tool cannot be combined with --zip or a directory input.".into());
+    }
+    match parse_tool(&args.payload_path) {
+        Some(first) if args.payload_path != "-" => args.tools.insert(0, first),
+        _ => return Err("A multi-call SFX needs a named input file, not stdin.".into()),
+    }
+    Ok(())
+}
+
+fn validate_validity(args: &PackerArgs) -> Result<(), String> {
+    if let (Some(nb), Some(na)) = (args.not_before, args.not_after) {
+        if nb >= na {
+            return Err("--not-before must be earlier than --not-after.".into());
+        }
+    }
+    if (args.not_before.is_some() || args.not_after.is_some()) && args.encrypt.is_none() {
+        eprintln!("Note: without encryption the validity window is not tamper-proof.");
+    }
+    Ok(())
+}
+
+fn validate_launch(args: &PackerArgs) -> Result<(), String> {
+    let launch = &args.launch;
+    if (!launch.rlimits.is_empty() || launch.umask.is_some() || launch.nice.is_some())
+        && args.target.contains("windows")
+    {
+        return Err("--rlimit, --umask and --nice are not supported for Windows targets.".into());
+    }
+    Ok(())
+}
+
+fn validate_supervise(args: &PackerArgs) -> Result<(), String> {
+    if args.supervise.is_some() && !args.target.contains("linux") {
+        return Err("Supervisor mode is only supported for Linux targets.".into());
+    }
+    Ok(())
+}
+
+fn validate_isolate(args: &PackerArgs) -> Result<(), String> {
+    if !args.isolate.is_empty() && !args.target.contains("linux") {
+        return Err("--isolate is only supported for Linux targets.".into());
+    }
+    Ok(())
+}
+
+/// Check `--variant` and put the input first as the baseline build.
+fn validate_variants(args: &mut PackerArgs) -> Result<(), String> {
+    if args.variants.is_empty() {
+        return Ok(());
+    }
+    if args.zip_entry.is_some()
+        || args.multiarch
+        || args.install.is_some()
+        || !args.tools.is_empty()
+    {
+        return Err(
+            "--variant cannot be combined with --zip, --multiarch, --tool or a directory input."
+                .into(),
+        );
+    }
+    let Some(baseline) = baseline_level(&args.target) else {
+        return Err("CPU variants need an x86_64 or aarch64 target.".into());
+    };
+    if let Some((_, level)) = args.variants.iter().find(|(_, l)| !same_arch(l, baseline)) {
+        return Err(format!(
+            "Variant {} does not match target {}.",
+            level, args.target
+        ));
+    }
+    args.variants
+        .insert(0, (args.payload_path.clone(), baseline.to_string()));
+    Ok(())
+}
+
+/// Check `--bundle-libs` and apply `--sysroot`.
+fn validate_bundle_libs(args: &mut PackerArgs, sysroot: Option<String>) -> Result<(), String> {
+    let Some(bundle_sysroot) = &mut args.bundle_libs else {
+        return match sysroot {
+            Some(_) => Err("--sysroot requires --bundle-libs.".into()),
+            None => Ok(()),
+        };
+    };
+    if let Some(dir) = sysroot {
+        *bundle_sysroot = dir;
+    }
+    if args.zip_entry.is_some()
+        || args.multiarch
+        || args.install.is_some()
+        || !args.tools.is_empty()
+        || !args.variants.is_empty()
+    {
+        return Err(
+            "--bundle-libs cannot be combined with --zip, --multiarch, --tool, --variant or a directory input."
+                .into(),
+        );
+    }
+    if !args.target.contains("linux") {
+        return Err("--bundle-libs is only supported for Linux targets.".into());
+    }
+    Ok(())
+}
+
+fn validate_elf_section(args: &PackerArgs) -> Result<(), String> {
+    if !args.elf_section {
+        return Ok(());
+    }
+    if args.zip_entry.is_some() || args.multiarch {
+        return Err("--elf-section cannot be combined with --zip or --multiarch.".into());
+    }
+    if !args.target.contains("linux") {
+        return Err("--elf-section is only supported for Linux targets.".into());
+    }
+    Ok(())
+}
+
+fn validate_strip(args: &PackerArgs) -> Result<(), String> {
+    if args.strip.is_some() && args.install.is_some() {
+        return Err("--strip and --strip-debug cannot be combined with a directory input.".into());
+    }
+    Ok(())
+}
+
+/// Bytes read to detect the payload's platform; enough for every header
+/// `detect_platforms` looks at in practice.
+const DETECT_HEAD_LEN: u64 = 64 * 1024;
+
+/// Pick the catalog target for the payload at `path`. If the payload is
+/// not recognised, `--target auto` (`required`) fails and an omitted
+/// `--target` falls back to the default target.
+fn detect_target(path: &str, required: bool, allow_mismatch: bool) -> String {
+    let default = stub_catalog::DEFAULT_TARGET;
+    let mut head = Vec::new();
+    if path != "-" && Path::new(path).is_file() {
+        if let Ok(f) = File::open(path) {
+            let _ = f.take(DETECT_HEAD_LEN).read_to_end(&mut head);
+        }
+    }
+    let platforms = detect_platforms(&head);
+    if platforms.is_empty() {
+        if required {
+            eprintln!(
+                "--target auto: cannot detect the OS and architecture of '{}'.",
+                path
+            );
             std::process::exit(1);
         }
+        return default.to_string();
+    }
+    let available: Vec<&str> = stub_catalog::STUBS.iter().map(|e| e.target).collect();
+    match pick_target(&platforms, &available, default) {
+        Ok(target) => {
+            if target != default {
+                eprintln!(
+                    "Detected a {} payload; using target {}.",
+                    platforms[0], target
+                );
+            }
+            target.to_string()
+        }
+        Err(_) if allow_mismatch => default.to_string(),
+        Err(e) => {
+            eprintln!("{}.", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Name of the payload inside a `--zip` SFX or a `--bundle-libs` archive:
+/// the input's file name.
+fn zip_entry_name(payload_path: &str) -> String {
+    Path::new(payload_path)
+        .file_name()
+        .filter(|_| payload_path != "-")
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "payload".to_string())
+}
+
+/// Metadata to record in the SFX. Always written: the recorded payload
+/// offset lets the stub find its trailer behind appended data.
+fn build_metadata(
+    args: &PackerArgs,
+    stub: &[u8],
+    payload: &[u8],
+    build_ids: &[(String, Vec<u8>)],
+    encryption: Option<Encryption>,
+    assets: Option<AssetsInfo>,
+) -> Metadata {
+    Metadata {
+        expose_env: args.expose_env,
+        payload_version: args.payload_version.clone(),
+        payload_sha256: Some(sha256(payload)),
+        debug: args.debug,
+        payload_len: Some(payload.len() as u64),
+        cache_max_size: args.cache_max_size,
+        runtime_flags: args.runtime_flags,
+        encryption,
+        not_before: args.not_before,
+        not_after: args.not_after,
+        sandbox: (!args.sandbox.is_empty()).then(|| args.sandbox.clone()),
+        launch: (!args.launch.is_empty()).then(|| args.launch.clone()),
+        supervise: args.supervise.clone(),
+        isolate: (!args.isolate.is_empty()).then_some(args.isolate),
+        payload_offset: Some(if args.elf_section {
+            0
+        } else {
+            stub.len() as u64
+                + args
+                    .zip_entry
+                    .as_ref()
+                    .map_or(0, |n| LOCAL_HEADER_SIZE + n.len() as u64)
+        }),
+        codec: if args.zip_entry.is_some() {
+            Codec::Deflate
+        } else {
+            Codec::Xz
+        },
+        assets,
+        install: args.install.clone(),
+        multi_call: !args.tools.is_empty(),
+        variants: !args.variants.is_empty(),
+        bundled_libs: args.bundle_libs.is_some(),
+        build_ids: build_ids.to_vec(),
+    }
+}
+
+/// Read the secret and derive the payload key plus fresh parameters.
+fn prepare_encryption(source: &EncryptSource) -> io::Result<(Encryption, Key)> {
+    let (path, passphrase) = match source {
+        EncryptSource::KeyFile(p) => (p, false),
+        EncryptSource::PassphraseFile(p) => (p, true),
+    };
+    let secret = fs::read(path).map_err(|e| {
+        eprintln!("Failed to read secret {}: {}", path, e);
+        e
+    })?;
+    let secret = file_secret(secret, passphrase);
+    let enc = Encryption::generate(passphrase)?;
+    let key = if passphrase {
+        if secret.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Empty passphrase",
+            ));
+        }
+        enc.derive_key(&secret)?
+    } else {
+        parse_raw_key(&secret)?
+    };
+    Ok((enc, key))
+}
+
+/// Read the `--add` files and compress them into one asset archive.
+fn pack_assets(specs: &[(String, String)]) -> io::Result<(AssetsInfo, Vec<u8>)> {
+    let assets = collect_assets(specs).map_err(|e| {
+        eprintln!("Failed to read assets: {}", e);
+        e
+    })?;
+    let archive = encode_archive(&assets);
+    let compressed = compress_lzma(&archive)?;
+    let info = AssetsInfo {
+        count: assets.len() as u32,
+        compressed_len: compressed.len() as u64,
+        archive_len: archive.len() as u64,
+        sha256: sha256(&archive),
+    };
+    Ok((info, compressed))
+}
+
+/// Archive the input directory of an installer SFX.
+fn read_tree(path: &str) -> io::Result<(Vec<u8>, usize)> {
+    let entries = collect_tree(Path::new(path)).map_err(|e| {
+        eprintln!("Failed to read directory {}: {}", path, e);
+        e
+    })?;
+    Ok((encode_tree(&entries), entries.len()))
+}
+
+/// Read the `(path, name)` executables of a multi-call or CPU-variant SFX
+/// into one archive, so they are compressed together and share a
+/// dictionary.
+fn read_executables(
+    args: &PackerArgs,
+    files: &[(String, String)],
+    build_ids: &mut Vec<(String, Vec<u8>)>,
+) -> io::Result<Vec<u8>> {
+    let mut entries: Vec<Asset> = Vec::with_capacity(files.len());
+    for (path, name) in files {
+        if entries.iter().any(|e| e.name == *name) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Duplicate entry '{}'", name),
+            ));
+        }
+        let data = read_payload(path)?;
+        check_payload_file(args, path, &data, &args.target)?;
+        let data = strip_payload(args, path, name, data, build_ids)?;
+        entries.push(Asset {
+            name: name.clone(),
+            mode: 0o755,
+            data,
+        });
+    }
+    Ok(encode_archive(&entries))
+}
+
+/// Refuse a payload detected as built for another platform than `target`
+/// (unless `--allow-target-mismatch`), then one that is no runnable ELF
+/// for a Linux `target`. Prints warnings for ELF payloads that may not run.
+fn check_payload_file(args: &PackerArgs, path: &str, data: &[u8], target: &str) -> io::Result<()> {
+    let label = if path == "-" { "stdin" } else { path };
+    let context =
+        |e: io::Error| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", label, e));
+    if !args.allow_target_mismatch {
+        check_target(&detect_platforms(data), target).map_err(context)?;
+    }
+    if !target.contains("linux") || !is_elf(data) {
+        return Ok(());
+    }
+    let info = parse_elf(data).map_err(context)?;
+    let bundled_libs = args.bundle_libs.is_some();
+    for warning in check_payload(&info, target, bundled_libs).map_err(context)? {
+        eprintln!("Warning: {}: {}", label, warning);
+    }
+    Ok(())
+}
+
+/// Apply `--strip` / `--strip-debug` to an ELF payload and record the
+/// original's build-id under the archive entry `name`. Other formats are
+/// packed as they are.
+fn strip_payload(
+    args: &PackerArgs,
+    path: &str,
+    name: &str,
+    data: Vec<u8>,
+    build_ids: &mut Vec<(String, Vec<u8>)>,
+) -> io::Result<Vec<u8>> {
+    let Some(mode) = args.strip else {
+        return Ok(data);
+    };
+    let label = if path == "-" { "stdin" } else { path };
+    if !is_elf(&data) {
+        eprintln!(
+            "Note: {} is not an ELF image and is packed unstripped.",
+            label
+        );
+        return Ok(data);
     }
-    let target = selected_target
-        .or_else(|| env::var("XSFX_OUT_TARGET").ok())
-        .unwrap_or_else(|| stub_catalog::DEFAULT_TARGET.to_string());
-    PackerArgs {
-        payload_path: args[1].clone(),
-        output_path: args[2].clone(),
-        target,
+    let stripped = strip_elf(&data, mode)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", label, e)))?;
+    match parse_elf(&data).ok().and_then(|info| info.build_id) {
+        Some(id) => build_ids.push((name.to_string(), id)),
+        None => eprintln!(
+            "Warning: {} has no GNU build-id; crash reports cannot be matched to its debug info.",
+            label
+        ),
     }
+    eprintln!(
+        "Stripped {}: {} -> {} bytes ({} sections removed)",
+        label,
+        data.len(),
+        stripped.data.len(),
+        stripped.removed.len()
+    );
+    Ok(stripped.data)
 }
 
 fn read_payload(path: &str) -> io::Result<Vec<u8>> {
@@ -72,45 +852,307 @@ fn open_output(path: &str) -> io::Result<Box<dyn Write>> {
     }
 }
 
-fn write_sfx(stub: &[u8], payload: &[u8], writer: &mut dyn Write) -> io::Result<u64> {
-    let compressed = compress_lzma(payload)?;
+fn write_sfx(
+    stub: &[u8],
+    payload: &[u8],
+    meta: &Metadata,
+    key: Option<&Key>,
+    zip_entry: Option<&str>,
+    assets: Option<&[u8]>,
+    writer: &mut dyn Write,
+) -> io::Result<u64> {
+    if let Some(name) = zip_entry {
+        return write_zip_sfx(stub, payload, meta, name, writer);
+    }
+    let mut compressed = compress_lzma(payload)?;
+    if let (Some(enc), Some(key)) = (&meta.encryption, key) {
+        compressed = enc.encrypt(key, &meta.aad()?, &compressed)?;
+    }
+    let compressed_len = compressed.len() as u64;
+    writer.write_all(stub)?;
+    writer.write_all(&compressed)?;
+    if let Some(assets) = assets {
+        writer.write_all(assets)?;
+    }
+    writer.write_all(&meta.to_bytes()?)?;
+    writer.write_all(&Trailer::with_metadata(compressed_len).to_bytes())?;
+    writer.flush()?;
+    Ok(compressed_len)
+}
+
+/// `[stub][local header][deflate payload][central directory][EOCD]`, with
+/// the metadata and trailer as the EOCD comment so the file still ends in
+/// the SFX trailer.
+fn write_zip_sfx(
+    stub: &[u8],
+    payload: &[u8],
+    meta: &Metadata,
+    name: &str,
+    writer: &mut dyn Write,
+) -> io::Result<u64> {
+    let compressed = compress_deflate(payload);
+    let entry = ZipEntry::new(name, payload, compressed.len())?;
+    let local = entry.local_header();
+    let offset = |n: usize| {
+        u32::try_from(n).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SFX too large for a ZIP archive",
+            )
+        })
+    };
+    let central = entry.central_header(offset(stub.len())?);
+    let cd_offset = offset(stub.len() + local.len() + compressed.len())?;
     let compressed_len = compressed.len() as u64;
-    let trailer = Trailer::new(compressed_len);
+    let meta_bytes = meta.to_bytes()?;
+    let trailer = Trailer::with_metadata(compressed_len).to_bytes();
+    let eocd = end_of_central_directory(
+        central.len() as u32,
+        cd_offset,
+        meta_bytes.len() + trailer.len(),
+    )?;
     writer.write_all(stub)?;
+    writer.write_all(&local)?;
     writer.write_all(&compressed)?;
-    writer.write_all(&trailer.to_bytes())?;
+    writer.write_all(&central)?;
+    writer.write_all(&eocd)?;
+    writer.write_all(&meta_bytes)?;
+    writer.write_all(&trailer)?;
     writer.flush()?;
     Ok(compressed_len)
 }
 
 fn main() -> io::Result<()> {
     let args = parse_args();
-    let stub_bytes = match find_stub(&args.target) {
-        Some(bytes) => bytes,
-        None => {
-            eprintln!(
-                "Requested target '{}' not available in this build.",
-                args.target
-            );
-            list_available_stubs();
-            std::process::exit(2);
+    // Fail on a missing stub before reading the input or creating the output.
+    let stub_bytes = require_stub(&args.target);
+    if args.multiarch {
+        for arch in LAUNCHER_ARCHES {
+            require_stub(arch.target);
         }
+    }
+    let mut bundled = Vec::new();
+    let mut build_ids = Vec::new();
+    let (payload_bytes, tree_entries) = if args.install.is_some() {
+        let (archive, count) = read_tree(&args.payload_path)?;
+        (archive, Some(count))
+    } else if let Some(sysroot) = &args.bundle_libs {
+        let program = read_payload(&args.payload_path)?;
+        check_payload_file(&args, &args.payload_path, &program, &args.target)?;
+        let name = zip_entry_name(&args.payload_path);
+        let program = strip_payload(&args, &args.payload_path, &name, program, &mut build_ids)?;
+        let (archive, libs) = bundle_libs(
+            &program,
+            Path::new(&args.payload_path),
+            &name,
+            Path::new(sysroot),
+        )?;
+        bundled = libs;
+        (archive, None)
+    } else if !args.tools.is_empty() {
+        (read_executables(&args, &args.tools, &mut build_ids)?, None)
+    } else if !args.variants.is_empty() {
+        (
+            read_executables(&args, &args.variants, &mut build_ids)?,
+            None,
+        )
+    } else {
+        let payload = read_payload(&args.payload_path)?;
+        // A --multiarch payload is checked per architecture.
+        if !args.multiarch {
+            check_payload_file(&args, &args.payload_path, &payload, &args.target)?;
+        }
+        let payload = strip_payload(&args, &args.payload_path, "", payload, &mut build_ids)?;
+        (payload, None)
+    };
+    let (assets_info, assets) = if args.assets.is_empty() {
+        (None, None)
+    } else {
+        let (info, compressed) = pack_assets(&args.assets)?;
+        (Some(info), Some(compressed))
+    };
+    let assets = assets_info.clone().zip(assets);
+    // A launcher is built in memory, so nothing is written if any image fails.
+    let launcher = if args.multiarch {
+        Some(build_multiarch(
+            &args,
+            &payload_bytes,
+            &build_ids,
+            assets.as_ref(),
+        )?)
+    } else {
+        None
     };
-    let payload_bytes = read_payload(&args.payload_path)?;
     let mut out = open_output(&args.output_path)?;
-    let compressed_len = write_sfx(stub_bytes, &payload_bytes, &mut *out)?;
+    if let Some((launcher, sizes)) = launcher {
+        out.write_all(&launcher)?;
+        out.flush()?;
+        if args.output_path != "-" {
+            eprintln!(
+                "Created multi-arch SFX: {} ({})",
+                args.output_path,
+                sizes.join(", ")
+            );
+        }
+    } else {
+        let compressed_len = pack_sfx(
+            &args,
+            stub_bytes,
+            &payload_bytes,
+            &build_ids,
+            assets.as_ref(),
+            &mut *out,
+        )?;
+        if args.output_path != "-" {
+            eprintln!(
+                "Created SFX: {} (target: {}, stub: {} bytes, payload: {} bytes compressed)",
+                args.output_path,
+                args.target,
+                stub_bytes.len(),
+                compressed_len
+            );
+        }
+    }
     if args.output_path != "-" {
-        eprintln!(
-            "Created SFX: {} (target: {}, stub: {} bytes, payload: {} bytes compressed)",
-            args.output_path,
-            args.target,
-            stub_bytes.len(),
-            compressed_len
-        );
+        if let Some(info) = &assets_info {
+            eprintln!(
+                "Bundled {} asset(s): {} bytes compressed",
+                info.count, info.compressed_len
+            );
+        }
+        if !args.tools.is_empty() {
+            let names: Vec<&str> = args.tools.iter().map(|(_, n)| n.as_str()).collect();
+            eprintln!("Multi-call tools: {}", names.join(", "));
+        }
+        if !args.variants.is_empty() {
+            let levels: Vec<&str> = args.variants.iter().map(|(_, l)| l.as_str()).collect();
+            eprintln!("CPU variants: {}", levels.join(", "));
+        }
+        if args.bundle_libs.is_some() {
+            eprintln!(
+                "Bundled loader and {} libraries: {}",
+                bundled.len(),
+                bundled.join(", ")
+            );
+        }
+        if let Some(count) = tree_entries {
+            eprintln!("Installer: {} entries", count);
+        }
     }
     Ok(())
 }
 
+/// Write one SFX for `stub`. Encryption parameters are generated per call,
+/// so a nonce is never reused across the images of a launcher.
+fn pack_sfx(
+    args: &PackerArgs,
+    stub: &[u8],
+    payload: &[u8],
+    build_ids: &[(String, Vec<u8>)],
+    assets: Option<&(AssetsInfo, Vec<u8>)>,
+    writer: &mut dyn Write,
+) -> io::Result<u64> {
+    let (encryption, key) = match &args.encrypt {
+        Some(source) => {
+            let (enc, key) = prepare_encryption(source)?;
+            (Some(enc), Some(key))
+        }
+        None => (None, None),
+    };
+    let metadata = build_metadata(
+        args,
+        stub,
+        payload,
+        build_ids,
+        encryption,
+        assets.map(|(info, _)| info.clone()),
+    );
+    if args.elf_section {
+        // The section holds exactly what would otherwise follow the stub.
+        let mut section = Vec::new();
+        let compressed_len = write_sfx(
+            &[],
+            payload,
+            &metadata,
+            key.as_ref(),
+            None,
+            assets.map(|(_, data)| data.as_slice()),
+            &mut section,
+        )?;
+        let image = add_section(stub, SECTION_NAME, &section).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("--elf-section: stub: {}", e),
+            )
+        })?;
+        writer.write_all(&image)?;
+        writer.flush()?;
+        return Ok(compressed_len);
+    }
+    write_sfx(
+        stub,
+        payload,
+        &metadata,
+        key.as_ref(),
+        args.zip_entry.as_deref(),
+        assets.map(|(_, data)| data.as_slice()),
+        writer,
+    )
+}
+
+/// `--multiarch`: one complete SFX per launcher architecture behind an
+/// `sh` launcher that picks one by `uname -m`. Returns the launcher and a
+/// size summary per architecture.
+fn build_multiarch(
+    args: &PackerArgs,
+    payload: &[u8],
+    build_ids: &[(String, Vec<u8>)],
+    assets: Option<&(AssetsInfo, Vec<u8>)>,
+) -> io::Result<(Vec<u8>, Vec<String>)> {
+    let mut images = Vec::with_capacity(LAUNCHER_ARCHES.len());
+    for arch in LAUNCHER_ARCHES {
+        let stub = require_stub(arch.target);
+        let own_path = args.arch_payloads.iter().find(|(a, _)| a == arch.name);
+        let mut own_ids = Vec::new();
+        let own_payload = match own_path {
+            Some((_, path)) => {
+                let data = read_payload(path)?;
+                check_payload_file(args, path, &data, arch.target)?;
+                Some(strip_payload(args, path, "", data, &mut own_ids)?)
+            }
+            None => None,
+        };
+        let (payload, build_ids) = match &own_payload {
+            Some(own) => (own.as_slice(), own_ids.as_slice()),
+            None => (payload, build_ids),
+        };
+        if own_path.is_none() && args.install.is_none() && args.tools.is_empty() {
+            check_payload_file(args, &args.payload_path, payload, arch.target)?;
+        }
+        let mut image = Vec::new();
+        pack_sfx(args, stub, payload, build_ids, assets, &mut image)?;
+        images.push((arch, image));
+    }
+    let parts: Vec<_> = images.iter().map(|(a, i)| (*a, i.as_slice())).collect();
+    let sizes = images
+        .iter()
+        .map(|(a, i)| format!("{}: {} bytes", a.name, i.len()))
+        .collect();
+    Ok((build_launcher(&parts)?, sizes))
+}
+
+/// The catalog stub for `target`, or exit listing the available ones.
+fn require_stub(target: &str) -> &'static [u8] {
+    match find_stub(target) {
+        Some(bytes) => bytes,
+        None => {
+            eprintln!("Requested target '{}' not available in this build.", target);
+            list_available_stubs();
+            std::process::exit(2);
+        }
+    }
+}
+
 fn find_stub(target: &str) -> Option<&'static [u8]> {
     for entry in stub_catalog::STUBS {
         if entry.target == target {
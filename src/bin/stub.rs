@@ -1,77 +1,486 @@
 use std::env;
 #[cfg(target_os = "linux")]
 use std::ffi::CString;
-use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::ffi::OsStr;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
 use std::path::Path;
 
-use xsfx::common::{Trailer, MAGIC, TRAILER_SIZE};
-use xsfx::decompress::decompress_payload;
+#[cfg(target_os = "linux")]
+use xsfx::assets;
+use xsfx::cache::PayloadCache;
+use xsfx::decompress::{decompress_payload, decompress_payload_exact, inflate_payload_exact};
+use xsfx::diag::{Diag, Phase, PhaseExt, StubError};
+use xsfx::digest::{to_hex, verify_sha256};
+use xsfx::exit_code::StubExit;
+use xsfx::install::{install_tree, parse_install_args, InstallSpec, ENV_TARGET_DIR};
+#[cfg(target_os = "linux")]
+use xsfx::isolate::{self, Namespaces};
+use xsfx::layout::{read_layout, SfxLayout};
+use xsfx::metadata::{Codec, Metadata};
+#[cfg(unix)]
+use xsfx::multiarch::adopt_launcher_copy;
+use xsfx::multicall::{select_tool, unknown_tool_message};
+use xsfx::runtime_flags::{format_info, split_runtime_flags, RuntimeAction};
+use xsfx::sandbox;
+use xsfx::self_exe::{argv0, locate_self, SelfSource};
+#[cfg(target_os = "linux")]
+use xsfx::supervise::{self, ChildStatus, SupervisorPolicy};
+use xsfx::validity::{check_window, now_unix};
+use xsfx::variant::{choose_variant, host_levels, ENV_VARIANT};
+use zeroize::Zeroizing;
 
 fn main() {
-    if run_stub().is_err() {
+    let mut diag = Diag::from_env();
+    if let Err(err) = run_stub(&mut diag) {
+        std::process::exit(report_failure(&err, &mut diag));
+    }
+}
+
+/// Print a stub failure and return the exit code for it.
+fn report_failure(err: &StubError, diag: &mut Diag) -> i32 {
+    diag.report_error(err);
+    let class = StubExit::classify(err);
+    if class.is_user_facing() {
+        eprintln!("SFX stub error: {}", err.source);
+    } else {
         let _ = io::Write::write_all(&mut io::stderr(), b"SFX stub error\n");
-        std::process::exit(1);
     }
+    class.code()
 }
 
-fn read_and_validate_trailer(file: &mut std::fs::File) -> io::Result<(u64, u64)> {
-    let total_len = file.metadata()?.len();
-    if total_len < TRAILER_SIZE {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "File too small to contain trailer",
-        ));
+fn run_stub(diag: &mut Diag) -> Result<(), StubError> {
+    let invoked = argv0();
+    let me = locate_self(invoked.as_deref()).phase(Phase::Open)?;
+    let (mut file, exe_path) = (me.file, me.path);
+    // Under a multi-arch launcher argv[0] is the image copy; the name the
+    // user invoked is the launcher's.
+    #[cfg(unix)]
+    let (exe_path, invoked) = match adopt_launcher_copy(&file).phase(Phase::Open)? {
+        Some(launcher) => {
+            diag.note("started by a multi-arch launcher; released its copy of the image");
+            let name = launcher.clone().into_os_string();
+            (launcher, Some(name))
+        }
+        None => (exe_path, invoked),
+    };
+    diag.mark(Phase::Open);
+    let layout = read_layout(&mut file).phase(Phase::Trailer)?;
+    if layout.metadata.debug {
+        diag.enable();
     }
-    file.seek(SeekFrom::Start(total_len - TRAILER_SIZE))?;
-    let trailer = Trailer::from_reader(file)?;
-    if trailer.magic != MAGIC {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Invalid SFX magic marker",
+    if layout.overlay_len > 0 {
+        diag.note(&format!(
+            "ignoring {} bytes appended after the SFX",
+            layout.overlay_len
         ));
     }
-    if trailer.payload_len == 0 || trailer.payload_len > total_len {
-        return Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Invalid payload length in trailer",
-        ));
+    if me.source != SelfSource::ProcSelfExe {
+        diag.note(&format!("located self via {}", me.source.name()));
+    }
+    diag.mark(Phase::Trailer);
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut action = None;
+    if layout.metadata.runtime_flags {
+        (action, args) = split_runtime_flags(&args).phase(Phase::Args)?;
+    }
+    // --xsfx-info stays available outside the window so users can see why.
+    if action != Some(RuntimeAction::Info) {
+        check_window(&layout.metadata, now_unix()).phase(Phase::Validity)?;
+        diag.mark(Phase::Validity);
+    }
+    if let Some(action) = action {
+        return run_action(&action, &mut file, &layout, diag);
+    }
+    if let Some(spec) = &layout.metadata.install {
+        let exit_code = run_installer(spec, &args, &mut file, &layout, &exe_path, diag)?;
+        std::process::exit(exit_code);
+    }
+    let mut payload = Zeroizing::new(load_payload(&mut file, &layout, diag)?);
+    let mut payload_argv0 = exe_path.clone();
+    if layout.metadata.multi_call {
+        let tool;
+        (payload, tool) = pick_tool(&payload, invoked.as_deref(), &mut args)?;
+        payload_argv0 = tool.into();
     }
-    Ok((trailer.payload_len, total_len))
+    if layout.metadata.variants {
+        payload = pick_variant(&payload, diag)?;
+    }
+    if layout.metadata.bundled_libs {
+        (payload, args) = launch_with_libs(&payload, args, &exe_path, diag)?;
+    }
+    for (name, value) in layout.metadata.runtime_env(&exe_path) {
+        env::set_var(name, value);
+    }
+    if let Some(archive) = load_assets(&mut file, &layout, diag)? {
+        publish_assets(&archive, diag)?;
+    }
+    let exit_code = exec_payload(
+        payload,
+        &payload_argv0,
+        &args,
+        &exe_path,
+        &layout.metadata,
+        diag,
+    )?;
+    std::process::exit(exit_code);
 }
 
-fn run_stub() -> io::Result<()> {
-    let exe_path = env::current_exe()?;
-    #[cfg(target_os = "linux")]
-    let mut file = std::fs::File::open("/proc/self/exe")?;
-    #[cfg(not(target_os = "linux"))]
-    let mut file = std::fs::File::open(&exe_path)?;
-    let (payload_len, total_len) = read_and_validate_trailer(&mut file)?;
-    file.seek(SeekFrom::Start(total_len - TRAILER_SIZE - payload_len))?;
-    let mut limited_reader = BufReader::new(file.take(payload_len));
-    let payload = decompress_payload(&mut limited_reader)?;
-    let args: Vec<String> = env::args().skip(1).collect();
-    let exit_code = exec_payload(&payload, &args, &exe_path)?;
-    std::process::exit(exit_code);
+/// Handle a reserved `--xsfx-*` action instead of running the payload.
+/// Verify and extract always read the embedded payload, never the cache.
+fn run_action(
+    action: &RuntimeAction,
+    file: &mut std::fs::File,
+    layout: &SfxLayout,
+    diag: &mut Diag,
+) -> Result<(), StubError> {
+    match action {
+        RuntimeAction::Info => {
+            let info = format_info(layout.payload_offset, layout.payload_len, &layout.metadata);
+            print!("{}", info);
+        }
+        RuntimeAction::Verify => {
+            extract_payload(file, layout, diag)?;
+            match &layout.metadata.payload_sha256 {
+                Some(d) => println!("payload OK (sha256 {})", to_hex(d)),
+                None => println!("payload OK (decompressed; no digest recorded)"),
+            }
+            if let (Some(_), Some(info)) =
+                (load_assets(file, layout, diag)?, &layout.metadata.assets)
+            {
+                println!("assets OK ({} files)", info.count);
+            }
+        }
+        RuntimeAction::Extract(path) => {
+            let payload = extract_payload(file, layout, diag)?;
+            write_extracted(path, &payload).phase(Phase::Extract)?;
+            diag.mark(Phase::Extract);
+        }
+    }
+    diag.report_timings();
+    Ok(())
 }
 
+/// Multi-call SFX: take the tool named by `invoked` (`argv[0]`, or the
+/// launcher path) or by the first argument out of the payload archive,
+/// dropping that argument. Also
+/// returns the tool name, which the tool gets as its `argv[0]`, as with
+/// BusyBox.
+fn pick_tool(
+    archive: &[u8],
+    invoked: Option<&OsStr>,
+    args: &mut Vec<String>,
+) -> Result<(Zeroizing<Vec<u8>>, String), StubError> {
+    let tools = xsfx::assets::parse_archive(archive).phase(Phase::Verify)?;
+    let names: Vec<&str> = tools.iter().map(|t| t.name).collect();
+    let Some((i, by_arg)) = select_tool(&names, invoked, args) else {
+        let msg = unknown_tool_message(&names, args);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, msg)).phase(Phase::Args);
+    };
+    if by_arg {
+        args.remove(0);
+    }
+    Ok((
+        Zeroizing::new(tools[i].data.to_vec()),
+        tools[i].name.to_string(),
+    ))
+}
+
+/// CPU-variant SFX: take the best variant for this CPU, or the one named
+/// by `XSFX_VARIANT`, out of the payload archive. `XSFX_VARIANT` is removed
+/// so the payload never sees it.
+fn pick_variant(archive: &[u8], diag: &mut Diag) -> Result<Zeroizing<Vec<u8>>, StubError> {
+    let variants = xsfx::assets::parse_archive(archive).phase(Phase::Verify)?;
+    let names: Vec<&str> = variants.iter().map(|v| v.name).collect();
+    let forced = env::var(ENV_VARIANT).ok().filter(|v| !v.is_empty());
+    env::remove_var(ENV_VARIANT);
+    let i = choose_variant(&names, &host_levels(), forced.as_deref()).map_err(|e| {
+        let phase = if forced.is_some() {
+            Phase::Args
+        } else {
+            Phase::Exec
+        };
+        StubError::new(phase, e)
+    })?;
+    diag.note(&format!("running variant {}", names[i]));
+    Ok(Zeroizing::new(variants[i].data.to_vec()))
+}
+
+/// Dynamically linked payload: publish the program and its libraries as
+/// memfds and run the bundled loader on them instead.
 #[cfg(target_os = "linux")]
-fn write_memfd(data: &[u8]) -> io::Result<std::fs::File> {
+fn launch_with_libs(
+    archive: &[u8],
+    args: Vec<String>,
+    exe_path: &Path,
+    diag: &mut Diag,
+) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), StubError> {
+    let libs = xsfx::assets::parse_archive(archive)
+        .phase(Phase::Verify)?
+        .len()
+        .saturating_sub(2);
+    let (loader, args) = xsfx::dynlink::prepare_launch(archive, &exe_path.to_string_lossy(), args)
+        .phase(Phase::Memfd)?;
+    diag.note(&format!(
+        "running the bundled loader with {} libraries",
+        libs
+    ));
+    Ok((Zeroizing::new(loader), args))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn launch_with_libs(
+    _archive: &[u8],
+    _args: Vec<String>,
+    _exe_path: &Path,
+    _diag: &mut Diag,
+) -> Result<(Zeroizing<Vec<u8>>, Vec<String>), StubError> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Bundled shared libraries require Linux",
+    ))
+    .phase(Phase::Exec)
+}
+
+/// Installer mode: extract the tree and run the post-extract command,
+/// whose exit status becomes the SFX's.
+fn run_installer(
+    spec: &InstallSpec,
+    args: &[String],
+    file: &mut std::fs::File,
+    layout: &SfxLayout,
+    sfx_path: &Path,
+    diag: &mut Diag,
+) -> Result<i32, StubError> {
+    let target = parse_install_args(args)
+        .map_err(|msg| io::Error::new(io::ErrorKind::InvalidInput, msg))
+        .phase(Phase::Args)?;
+    let target = spec.target_dir(target).phase(Phase::Install)?;
+    let archive = Zeroizing::new(extract_payload(file, layout, diag)?);
+    let count = install_tree(&archive, &target).phase(Phase::Install)?;
+    let target = std::fs::canonicalize(&target).phase(Phase::Install)?;
+    diag.mark(Phase::Install);
+    diag.report_timings();
+    let Some(cmd) = &spec.post_extract else {
+        println!("Extracted {} entries to {}", count, target.display());
+        return Ok(0);
+    };
+    let status = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(cmd)
+        .current_dir(&target)
+        .envs(layout.metadata.runtime_env(sfx_path))
+        .env(ENV_TARGET_DIR, &target)
+        .status()
+        .phase(Phase::Install)?;
+    Ok(shell_code(status))
+}
+
+/// Shell-style exit code of a finished command: 128+N for signal N.
+fn shell_code(status: std::process::ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(sig) = status.signal() {
+            return 128 + sig;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+/// Write an extracted payload to `path` (`-` for stdout). Existing files
+/// are never overwritten.
+fn write_extracted(path: &Path, payload: &[u8]) -> io::Result<()> {
     use std::io::Write;
-    use std::os::unix::io::{AsRawFd, FromRawFd};
+    if path.as_os_str() == "-" {
+        let mut out = io::stdout().lock();
+        out.write_all(payload)?;
+        return out.flush();
+    }
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o755);
+    }
+    let mut f = opts.open(path)?;
+    f.write_all(payload)?;
+    f.sync_all()
+}
 
-    let fd = unsafe {
-        let r = libc::syscall(libc::SYS_memfd_create, c"rsfx".as_ptr(), libc::MFD_CLOEXEC);
-        if r < 0 {
-            return Err(io::Error::last_os_error());
+/// Payload bytes from the per-user cache when enabled at pack time,
+/// otherwise decompressed from the SFX (and then added to the cache).
+/// Encrypted payloads are never cached.
+fn load_payload(
+    file: &mut std::fs::File,
+    layout: &SfxLayout,
+    diag: &mut Diag,
+) -> Result<Vec<u8>, StubError> {
+    let meta = &layout.metadata;
+    let (Some(max), Some(digest), None) =
+        (meta.cache_max_size, meta.payload_sha256, &meta.encryption)
+    else {
+        return extract_payload(file, layout, diag);
+    };
+    // Cache problems are never fatal: fall back to decompression.
+    let cache = PayloadCache::open_default(max).ok();
+    let hit = cache
+        .as_ref()
+        .and_then(|c| c.lookup(&digest).ok().flatten());
+    diag.mark(Phase::Cache);
+    if let Some(payload) = hit {
+        return Ok(payload);
+    }
+    let payload = extract_payload(file, layout, diag)?;
+    if let Some(c) = &cache {
+        let _ = c.store(&digest, &payload);
+        diag.mark(Phase::Cache);
+    }
+    Ok(payload)
+}
+
+/// Decrypt (if sealed) and decompress the payload from the SFX, then check
+/// it against the recorded size and digest.
+fn extract_payload(
+    file: &mut std::fs::File,
+    layout: &SfxLayout,
+    diag: &mut Diag,
+) -> Result<Vec<u8>, StubError> {
+    file.seek(SeekFrom::Start(layout.payload_offset))
+        .phase(Phase::Decompress)?;
+    let payload = match &layout.metadata.encryption {
+        Some(_) => {
+            let compressed = decrypt_payload(file, layout).phase(Phase::Decrypt)?;
+            diag.mark(Phase::Decrypt);
+            decompress(&mut Cursor::new(&compressed[..]), &layout.metadata)
+        }
+        None => {
+            let mut limited_reader = BufReader::new(file.take(layout.payload_len));
+            decompress(&mut limited_reader, &layout.metadata)
         }
-        r as i32
+    }
+    .phase(Phase::Decompress)?;
+    diag.mark(Phase::Decompress);
+    if let Some(digest) = &layout.metadata.payload_sha256 {
+        verify_sha256(&payload, digest).phase(Phase::Verify)?;
+        diag.mark(Phase::Verify);
+    }
+    Ok(payload)
+}
+
+fn decompress<R: io::BufRead>(reader: &mut R, meta: &Metadata) -> io::Result<Vec<u8>> {
+    match (meta.codec, meta.payload_len) {
+        (Codec::Deflate, Some(len)) => {
+            let mut compressed = Vec::new();
+            reader.read_to_end(&mut compressed)?;
+            inflate_payload_exact(&compressed, len)
+        }
+        (Codec::Deflate, None) => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Deflate payload without recorded size",
+        )),
+        (Codec::Xz, Some(len)) => decompress_payload_exact(reader, len),
+        (Codec::Xz, None) => decompress_payload(reader),
+    }
+}
+
+/// Decompress the bundled asset archive, if any, and check its digest.
+fn load_assets(
+    file: &mut std::fs::File,
+    layout: &SfxLayout,
+    diag: &mut Diag,
+) -> Result<Option<Vec<u8>>, StubError> {
+    let (Some(info), Some(offset)) = (&layout.metadata.assets, layout.assets_offset) else {
+        return Ok(None);
     };
-    let mut f = unsafe { std::fs::File::from_raw_fd(fd) };
-    f.write_all(data)?;
-    if unsafe { libc::fchmod(f.as_raw_fd(), 0o700) } != 0 {
-        return Err(io::Error::last_os_error());
+    file.seek(SeekFrom::Start(offset))
+        .phase(Phase::Decompress)?;
+    let mut reader = BufReader::new(file.take(info.compressed_len));
+    let archive =
+        decompress_payload_exact(&mut reader, info.archive_len).phase(Phase::Decompress)?;
+    verify_sha256(&archive, &info.sha256).phase(Phase::Verify)?;
+    diag.mark(Phase::Verify);
+    Ok(Some(archive))
+}
+
+/// Hand the assets to the payload as inherited memfds listed in
+/// `XSFX_ASSETS`.
+#[cfg(target_os = "linux")]
+fn publish_assets(archive: &[u8], diag: &mut Diag) -> Result<(), StubError> {
+    let manifest = assets::publish(archive).phase(Phase::Memfd)?;
+    env::set_var(assets::ENV_ASSETS, manifest);
+    diag.mark(Phase::Memfd);
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn publish_assets(_archive: &[u8], _diag: &mut Diag) -> Result<(), StubError> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Bundled assets require Linux",
+    ))
+    .phase(Phase::Exec)
+}
+
+/// Read and open the sealed payload; the plaintext is wiped on drop.
+fn decrypt_payload(file: &mut std::fs::File, layout: &SfxLayout) -> io::Result<Zeroizing<Vec<u8>>> {
+    let meta = &layout.metadata;
+    let Some(enc) = &meta.encryption else {
+        return Err(io::Error::other("Payload is not encrypted"));
+    };
+    let mut sealed = Vec::new();
+    sealed.try_reserve_exact(layout.payload_len as usize)?;
+    file.take(layout.payload_len).read_to_end(&mut sealed)?;
+    let key = enc.obtain_key()?;
+    enc.decrypt(&key, &meta.aad()?, &sealed)
+}
+
+/// Apply pack-time launch settings, enter namespaces (Linux), then the
+/// sandbox policy (noting features the kernel lacks). Runs right before
+/// the payload starts.
+fn prepare_process(meta: &Metadata, sfx_path: &Path, diag: &mut Diag) -> Result<(), StubError> {
+    apply_launch(meta, sfx_path, diag)?;
+    #[cfg(target_os = "linux")]
+    if let Some(ns) = &meta.isolate {
+        let ids = isolate::OuterIds::current();
+        isolate::enter_in_process(ns).phase(Phase::Isolate)?;
+        setup_namespaces(ns, ids, diag)?;
+    }
+    apply_sandbox(meta, diag)
+}
+
+fn apply_launch(meta: &Metadata, sfx_path: &Path, diag: &mut Diag) -> Result<(), StubError> {
+    if let Some(launch) = &meta.launch {
+        launch.apply(sfx_path).phase(Phase::Setup)?;
+        diag.mark(Phase::Setup);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn setup_namespaces(
+    ns: &Namespaces,
+    ids: isolate::OuterIds,
+    diag: &mut Diag,
+) -> Result<(), StubError> {
+    let skipped = isolate::setup_inside(ns, ids).phase(Phase::Isolate)?;
+    for (step, err) in &skipped {
+        diag.note(&format!("isolate: {} skipped: {}", step, err));
     }
-    Ok(f)
+    diag.mark(Phase::Isolate);
+    Ok(())
+}
+
+fn apply_sandbox(meta: &Metadata, diag: &mut Diag) -> Result<(), StubError> {
+    let Some(policy) = &meta.sandbox else {
+        return Ok(());
+    };
+    let report = sandbox::apply(policy).phase(Phase::Sandbox)?;
+    for (feature, err) in &report.skipped {
+        diag.note(&format!("sandbox: {} skipped: {}", feature, err));
+    }
+    diag.mark(Phase::Sandbox);
+    Ok(())
 }
 
 #[cfg(target_os = "linux")]
@@ -88,19 +497,52 @@ fn build_c_argv(argv0: &Path, args: &[String]) -> io::Result<(CString, Vec<CStri
 }
 
 #[cfg(target_os = "linux")]
-fn exec_payload(payload: &[u8], args: &[String], argv0: &Path) -> io::Result<i32> {
-    use std::os::unix::io::AsRawFd;
-    extern "C" {
-        static environ: *const *const libc::c_char;
+fn exec_payload(
+    payload: Zeroizing<Vec<u8>>,
+    argv0: &Path,
+    args: &[String],
+    sfx_path: &Path,
+    meta: &Metadata,
+    diag: &mut Diag,
+) -> Result<i32, StubError> {
+    let memfd = xsfx::memfd::create_payload_memfd(c"rsfx", &payload).phase(Phase::Memfd)?;
+    if let Some(err) = &memfd.unsealed {
+        diag.note(&format!("memfd: sealing unavailable: {}", err));
     }
-    let memfd = write_memfd(payload)?;
-    let (c_argv0, c_args) = build_c_argv(argv0, args)?;
+    let memfd = memfd.file;
+    // Wipe the in-process copy; only the memfd is needed for exec.
+    drop(payload);
+    diag.mark(Phase::Memfd);
+    let (c_argv0, c_args) = build_c_argv(argv0, args).phase(Phase::Exec)?;
     let mut argv: Vec<*const libc::c_char> = Vec::with_capacity(args.len() + 2);
     argv.push(c_argv0.as_ptr());
     for a in &c_args {
         argv.push(a.as_ptr());
     }
     argv.push(std::ptr::null());
+    // A new PID namespace only applies to children, so it needs a parent
+    // that stays behind: run it under a plain supervisor.
+    let policy = meta.supervise.clone().or_else(|| {
+        meta.isolate
+            .filter(|ns| ns.pid)
+            .map(|_| SupervisorPolicy::default())
+    });
+    if let Some(policy) = &policy {
+        apply_launch(meta, sfx_path, diag)?;
+        diag.report_timings();
+        return supervise_payload(&memfd, &argv, policy, meta, diag);
+    }
+    prepare_process(meta, sfx_path, diag)?;
+    diag.report_timings();
+    execveat_memfd(&memfd, &argv)
+}
+
+#[cfg(target_os = "linux")]
+fn execveat_memfd(memfd: &std::fs::File, argv: &[*const libc::c_char]) -> Result<i32, StubError> {
+    use std::os::unix::io::AsRawFd;
+    extern "C" {
+        static environ: *const *const libc::c_char;
+    }
     unsafe {
         libc::syscall(
             libc::SYS_execveat,
@@ -111,15 +553,247 @@ fn exec_payload(payload: &[u8], args: &[String], argv0: &Path) -> io::Result<i32
             libc::AT_EMPTY_PATH,
         );
     }
-    Err(io::Error::last_os_error())
+    Err(io::Error::last_os_error()).phase(Phase::Exec)
+}
+
+/// Supervisor mode: run the payload as a child, forward signals to it and
+/// mirror its exit status. Launch settings were applied to the supervisor
+/// (and so to hooks and child); namespaces and the sandbox only apply to
+/// the child, which gets fresh namespaces on every start.
+#[cfg(target_os = "linux")]
+fn supervise_payload(
+    memfd: &std::fs::File,
+    argv: &[*const libc::c_char],
+    policy: &SupervisorPolicy,
+    meta: &Metadata,
+    diag: &mut Diag,
+) -> Result<i32, StubError> {
+    let mut all: libc::sigset_t = unsafe { std::mem::zeroed() };
+    let mut orig: libc::sigset_t = unsafe { std::mem::zeroed() };
+    unsafe {
+        libc::sigfillset(&mut all);
+        if libc::sigprocmask(libc::SIG_BLOCK, &all, &mut orig) != 0 {
+            return Err(io::Error::last_os_error()).phase(Phase::Supervise);
+        }
+    }
+    let ids = isolate::OuterIds::current();
+    let mut restarts = 0;
+    let status = loop {
+        if let Some(cmd) = &policy.pre_start {
+            let hook = run_hook(cmd, restarts, None).phase(Phase::Hook)?;
+            if !hook.success() {
+                let msg = format!("pre-start hook failed ({})", hook);
+                return Err(io::Error::other(msg)).phase(Phase::Hook);
+            }
+        }
+        let pid = match &meta.isolate {
+            Some(ns) => isolate::clone_process(ns).phase(Phase::Isolate)?,
+            None => match unsafe { libc::fork() } {
+                -1 => return Err(io::Error::last_os_error()).phase(Phase::Supervise),
+                pid => pid,
+            },
+        };
+        if pid == 0 {
+            unsafe { libc::sigprocmask(libc::SIG_SETMASK, &orig, std::ptr::null_mut()) };
+            if meta.assets.is_some() {
+                if let Some(manifest) = env::var_os(assets::ENV_ASSETS) {
+                    assets::rewind(&manifest.to_string_lossy());
+                }
+            }
+            let setup = match &meta.isolate {
+                Some(ns) => setup_namespaces(ns, ids, diag),
+                None => Ok(()),
+            };
+            let err = match setup.and_then(|()| apply_sandbox(meta, diag)) {
+                Ok(()) => execveat_memfd(memfd, argv).unwrap_err(),
+                Err(e) => e,
+            };
+            unsafe { libc::_exit(report_failure(&err, diag)) };
+        }
+        let (status, stop) = wait_child(pid, &all).phase(Phase::Supervise)?;
+        diag.note(&format!("supervisor: payload {}", status));
+        if let Some(cmd) = &policy.post_exit {
+            match run_hook(cmd, restarts, Some(status)) {
+                Ok(hook) if !hook.success() => {
+                    diag.note(&format!("supervisor: post-exit hook failed ({})", hook))
+                }
+                Ok(_) => {}
+                Err(e) => diag.note(&format!("supervisor: post-exit hook failed: {}", e)),
+            }
+        }
+        if stop || !status.is_crash() {
+            break status;
+        }
+        let Some(delay) = policy.restart_delay(restarts) else {
+            break status;
+        };
+        diag.note(&format!(
+            "supervisor: restarting in {} ms",
+            delay.as_millis()
+        ));
+        if wait_backoff(delay, &all).phase(Phase::Supervise)? {
+            break status;
+        }
+        restarts += 1;
+    };
+    Ok(exit_like(status))
+}
+
+/// Run a hook through `/bin/sh -c`. The child starts with an empty signal
+/// mask (std resets it), so hooks are not affected by the supervisor's.
+#[cfg(target_os = "linux")]
+fn run_hook(
+    cmd: &str,
+    restarts: u32,
+    status: Option<ChildStatus>,
+) -> io::Result<std::process::ExitStatus> {
+    let mut command = std::process::Command::new("/bin/sh");
+    command
+        .arg("-c")
+        .arg(cmd)
+        .env(supervise::ENV_RESTARTS, restarts.to_string());
+    if let Some(status) = status {
+        let (name, value) = status.hook_env();
+        command.env(name, value);
+    }
+    command.status()
+}
+
+/// Signals that ask the supervisor to shut down rather than restart.
+#[cfg(target_os = "linux")]
+fn is_shutdown_signal(sig: libc::c_int) -> bool {
+    matches!(
+        sig,
+        libc::SIGTERM | libc::SIGINT | libc::SIGQUIT | libc::SIGHUP
+    )
+}
+
+/// Forward signals to `pid` until it exits. Returns its status and whether
+/// a shutdown signal was received meanwhile.
+#[cfg(target_os = "linux")]
+fn wait_child(pid: libc::pid_t, set: &libc::sigset_t) -> io::Result<(ChildStatus, bool)> {
+    let mut stop = false;
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let sig = unsafe { libc::sigwaitinfo(set, &mut info) };
+        if sig < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        if sig != libc::SIGCHLD {
+            stop |= is_shutdown_signal(sig);
+            // Terminal-generated signals already reached the whole
+            // foreground process group, child included.
+            if info.si_code != libc::SI_KERNEL {
+                unsafe { libc::kill(pid, sig) };
+            }
+            continue;
+        }
+        let mut raw = 0;
+        let r = unsafe { libc::waitpid(pid, &mut raw, libc::WNOHANG | libc::WUNTRACED) };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if r == 0 {
+            continue; // SIGCHLD from a hook
+        }
+        if let Some(status) = ChildStatus::from_wait_status(raw) {
+            return Ok((status, stop));
+        }
+        // Job control: stop along with the child so the shell sees the
+        // job as stopped, and resume it when we are continued.
+        if libc::WIFSTOPPED(raw)
+            && matches!(
+                libc::WSTOPSIG(raw),
+                libc::SIGTSTP | libc::SIGTTIN | libc::SIGTTOU
+            )
+        {
+            unsafe {
+                libc::raise(libc::SIGSTOP);
+                libc::kill(pid, libc::SIGCONT);
+            }
+        }
+    }
+}
+
+/// Sleep before a restart; `true` if a shutdown signal cut it short.
+#[cfg(target_os = "linux")]
+fn wait_backoff(delay: std::time::Duration, set: &libc::sigset_t) -> io::Result<bool> {
+    let deadline = std::time::Instant::now() + delay;
+    loop {
+        let left = deadline.saturating_duration_since(std::time::Instant::now());
+        if left.is_zero() {
+            return Ok(false);
+        }
+        let ts = libc::timespec {
+            tv_sec: left.as_secs() as libc::time_t,
+            tv_nsec: left.subsec_nanos() as libc::c_long,
+        };
+        let sig = unsafe { libc::sigtimedwait(set, std::ptr::null_mut(), &ts) };
+        if sig < 0 {
+            let err = io::Error::last_os_error();
+            match err.raw_os_error() {
+                Some(libc::EAGAIN) => return Ok(false),
+                Some(libc::EINTR) => continue,
+                _ => return Err(err),
+            }
+        }
+        if is_shutdown_signal(sig) {
+            return Ok(true);
+        }
+    }
+}
+
+/// Exit code for the supervisor. A payload killed by a signal is mirrored
+/// by re-raising that signal on ourselves (without a core dump), so the
+/// parent sees the same wait status.
+#[cfg(target_os = "linux")]
+fn exit_like(status: ChildStatus) -> i32 {
+    if let ChildStatus::Signaled(sig) = status {
+        unsafe {
+            let mut lim: libc::rlimit = std::mem::zeroed();
+            if libc::getrlimit(libc::RLIMIT_CORE, &mut lim) == 0 {
+                lim.rlim_cur = 0;
+                libc::setrlimit(libc::RLIMIT_CORE, &lim);
+            }
+            libc::signal(sig, libc::SIG_DFL);
+            let mut set: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut set);
+            libc::sigaddset(&mut set, sig);
+            libc::sigprocmask(libc::SIG_UNBLOCK, &set, std::ptr::null_mut());
+            libc::raise(sig);
+        }
+    }
+    status.shell_code()
 }
 
 #[cfg(target_os = "windows")]
-fn exec_payload(payload: &[u8], args: &[String], _argv0: &Path) -> io::Result<i32> {
-    xsfx::pe_loader::load_and_exec_pe(payload, args)
+fn exec_payload(
+    payload: Zeroizing<Vec<u8>>,
+    _argv0: &Path,
+    args: &[String],
+    sfx_path: &Path,
+    meta: &Metadata,
+    diag: &mut Diag,
+) -> Result<i32, StubError> {
+    prepare_process(meta, sfx_path, diag)?;
+    diag.report_timings();
+    xsfx::pe_loader::load_and_exec_pe(&payload, args).phase(Phase::Exec)
 }
 
 #[cfg(target_os = "macos")]
-fn exec_payload(payload: &[u8], args: &[String], _argv0: &Path) -> io::Result<i32> {
-    xsfx::macho_loader::load_and_exec_macho(payload, args)
+fn exec_payload(
+    payload: Zeroizing<Vec<u8>>,
+    _argv0: &Path,
+    args: &[String],
+    sfx_path: &Path,
+    meta: &Metadata,
+    diag: &mut Diag,
+) -> Result<i32, StubError> {
+    prepare_process(meta, sfx_path, diag)?;
+    diag.report_timings();
+    xsfx::macho_loader::load_and_exec_macho(&payload, args).phase(Phase::Exec)
 }
@@ -0,0 +1,380 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::digest::{sha256, to_hex, SHA256_LEN};
+
+pub const ENV_CACHE_DIR: &str = "XSFX_CACHE_DIR";
+/// Default cap on the total size of cached payloads.
+pub const DEFAULT_CACHE_MAX_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Per-user directory for cached payloads: `$XSFX_CACHE_DIR`, else
+/// `$XDG_CACHE_HOME/xsfx`, else `$HOME/.cache/xsfx` (`%LOCALAPPDATA%\xsfx`
+/// on Windows).
+pub fn default_cache_dir() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|v| !v.is_empty());
+    if let Some(dir) = non_empty(ENV_CACHE_DIR) {
+        return Some(PathBuf::from(dir));
+    }
+    if cfg!(windows) {
+        return non_empty("LOCALAPPDATA").map(|d| PathBuf::from(d).join("xsfx"));
+    }
+    if let Some(xdg) = non_empty("XDG_CACHE_HOME") {
+        return Some(PathBuf::from(xdg).join("xsfx"));
+    }
+    non_empty("HOME").map(|h| PathBuf::from(h).join(".cache").join("xsfx"))
+}
+
+/// Parse a size such as `4096`, `64K`, `512M` or `2G` (binary units).
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let (digits, shift) = match s.chars().last()?.to_ascii_uppercase() {
+        'K' => (&s[..s.len() - 1], 10),
+        'M' => (&s[..s.len() - 1], 20),
+        'G' => (&s[..s.len() - 1], 30),
+        _ => (s, 0),
+    };
+    let n: u64 = digits.parse().ok()?;
+    n.checked_mul(1u64 << shift)
+}
+
+fn is_entry_name(name: &str) -> bool {
+    name.len() == SHA256_LEN * 2 && name.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Content-addressed store of decompressed payloads, keyed by SHA-256.
+///
+/// Entries are written to a temporary file and renamed into place, so a
+/// reader never observes a partial entry. On Unix the directory must be
+/// owned by the current user and not accessible to anyone else; entries
+/// are re-hashed on every lookup, so a damaged entry is discarded rather
+/// than executed.
+pub struct PayloadCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl PayloadCache {
+    /// Open (creating if needed) a cache rooted at `dir`.
+    pub fn open(dir: &Path, max_size: u64) -> io::Result<Self> {
+        create_private_dir(dir)?;
+        check_private_dir(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_size,
+        })
+    }
+
+    /// Open the cache in the default per-user location.
+    pub fn open_default(max_size: u64) -> io::Result<Self> {
+        let dir = default_cache_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "No cache directory"))?;
+        Self::open(&dir, max_size)
+    }
+
+    fn entry_path(&self, digest: &[u8; SHA256_LEN]) -> PathBuf {
+        self.dir.join(to_hex(digest))
+    }
+
+    /// Return the cached payload for `digest`, or `None` on a miss.
+    /// An entry whose content does not match its digest is removed.
+    pub fn lookup(&self, digest: &[u8; SHA256_LEN]) -> io::Result<Option<Vec<u8>>> {
+        let path = self.entry_path(digest);
+        let mut file = match open_entry(&path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        check_entry(&file)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        if sha256(&data) != *digest {
+            let _ = fs::remove_file(&path);
+            return Ok(None);
+        }
+        // Bump mtime so eviction drops the least recently used entries.
+        let _ = file.set_modified(SystemTime::now());
+        Ok(Some(data))
+    }
+
+    /// Atomically add `data` under `digest`, then evict old entries until
+    /// the cache fits in `max_size`. Payloads larger than the cap are skipped.
+    pub fn store(&self, digest: &[u8; SHA256_LEN], data: &[u8]) -> io::Result<()> {
+        if data.len() as u64 > self.max_size {
+            return Ok(());
+        }
+        let name = to_hex(digest);
+        let tmp = self
+            .dir
+            .join(format!(".tmp-{}-{}", name, std::process::id()));
+        let result = write_entry(&tmp, data).and_then(|_| fs::rename(&tmp, self.dir.join(&name)));
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result?;
+        self.evict(&name)
+    }
+
+    fn evict(&self, keep: &str) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0u64;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_entry_name(&name) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            total += meta.len();
+            if name != keep {
+                let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                entries.push((mtime, meta.len(), entry.path()));
+            }
+        }
+        entries.sort_by_key(|(mtime, _, _)| *mtime);
+        for (_, len, path) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= len;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::DirBuilderExt;
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)
+}
+
+#[cfg(not(unix))]
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)
+}
+
+#[cfg(unix)]
+fn check_private_dir(dir: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = fs::symlink_metadata(dir)?;
+    let euid = unsafe { libc::geteuid() };
+    if !meta.is_dir() || meta.uid() != euid || meta.mode() & 0o077 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Cache directory is not private to the current user",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private_dir(dir: &Path) -> io::Result<()> {
+    if !fs::symlink_metadata(dir)?.is_dir() {
+        return Err(io::Error::other("Cache path is not a directory"));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn open_entry(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open_entry(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open(path)
+}
+
+#[cfg(unix)]
+fn check_entry(file: &File) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = file.metadata()?;
+    let euid = unsafe { libc::geteuid() };
+    if !meta.is_file() || meta.uid() != euid || meta.mode() & 0o022 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "Cache entry has unsafe ownership or permissions",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_entry(file: &File) -> io::Result<()> {
+    if !file.metadata()?.is_file() {
+        return Err(io::Error::other("Cache entry is not a regular file"));
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_entry(path: &Path, data: &[u8]) -> io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    f.write_all(data)?;
+    f.sync_all()
+}
+
+#[cfg(not(unix))]
+fn write_entry(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut f = OpenOptions::new().write(true).create_new(true).open(path)?;
+    f.write_all(data)?;
+    f.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("xsfx-cache-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("4096"), Some(4096));
+        assert_eq!(parse_size("64K"), Some(64 * 1024));
+        assert_eq!(parse_size("512m"), Some(512 * 1024 * 1024));
+        assert_eq!(parse_size("2G"), Some(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_size(""), None);
+        assert_eq!(parse_size("M"), None);
+        assert_eq!(parse_size("12X"), None);
+        assert_eq!(parse_size("99999999999999G"), None);
+    }
+
+    #[test]
+    fn test_is_entry_name() {
+        assert!(is_entry_name(&"a".repeat(64)));
+        assert!(!is_entry_name(&"a".repeat(63)));
+        assert!(!is_entry_name(&format!(".tmp-{}", "a".repeat(59))));
+    }
+
+    #[test]
+    fn test_cache_miss_then_hit() {
+        let dir = temp_dir("hit");
+        let cache = PayloadCache::open(&dir, 1 << 20).unwrap();
+        let data = b"cached payload".to_vec();
+        let digest = sha256(&data);
+        assert!(cache.lookup(&digest).unwrap().is_none());
+        cache.store(&digest, &data).unwrap();
+        assert_eq!(cache.lookup(&digest).unwrap(), Some(data));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_skips_oversized_payload() {
+        let dir = temp_dir("oversize");
+        let cache = PayloadCache::open(&dir, 4).unwrap();
+        let data = b"too large".to_vec();
+        let digest = sha256(&data);
+        cache.store(&digest, &data).unwrap();
+        assert!(cache.lookup(&digest).unwrap().is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_evicts_oldest() {
+        let dir = temp_dir("evict");
+        let cache = PayloadCache::open(&dir, 25).unwrap();
+        let a = vec![b'a'; 10];
+        let b = vec![b'b'; 10];
+        let c = vec![b'c'; 10];
+        cache.store(&sha256(&a), &a).unwrap();
+        let old = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1);
+        File::options()
+            .write(true)
+            .open(dir.join(to_hex(&sha256(&a))))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        cache.store(&sha256(&b), &b).unwrap();
+        cache.store(&sha256(&c), &c).unwrap();
+        assert!(cache.lookup(&sha256(&a)).unwrap().is_none());
+        assert!(cache.lookup(&sha256(&b)).unwrap().is_some());
+        assert!(cache.lookup(&sha256(&c)).unwrap().is_some());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sec_uc007_corrupt_entry_discarded() {
+        let dir = temp_dir("corrupt");
+        let cache = PayloadCache::open(&dir, 1 << 20).unwrap();
+        let data = b"original".to_vec();
+        let digest = sha256(&data);
+        cache.store(&digest, &data).unwrap();
+        let path = dir.join(to_hex(&digest));
+        fs::write(&path, b"tampered").unwrap();
+        assert!(cache.lookup(&digest).unwrap().is_none());
+        assert!(!path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sec_uc007_rejects_shared_directory() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("shared");
+        fs::create_dir_all(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let err = PayloadCache::open(&dir, 1024).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sec_uc007_rejects_symlinked_entry() {
+        let dir = temp_dir("symlink");
+        let cache = PayloadCache::open(&dir, 1 << 20).unwrap();
+        let target = dir.join("elsewhere");
+        fs::write(&target, b"x").unwrap();
+        let digest = sha256(b"x");
+        std::os::unix::fs::symlink(&target, dir.join(to_hex(&digest))).unwrap();
+        assert!(cache.lookup(&digest).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sec_uc007_rejects_writable_entry() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("writable");
+        let cache = PayloadCache::open(&dir, 1 << 20).unwrap();
+        let data = b"payload".to_vec();
+        let digest = sha256(&data);
+        cache.store(&digest, &data).unwrap();
+        let path = dir.join(to_hex(&digest));
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o666)).unwrap();
+        assert!(cache.lookup(&digest).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_sec_uc007_store_ignores_stale_temp_files() {
+        let dir = temp_dir("stale");
+        let cache = PayloadCache::open(&dir, 1 << 20).unwrap();
+        fs::write(dir.join(".tmp-garbage"), vec![0u8; 100]).unwrap();
+        let data = b"fresh".to_vec();
+        cache.store(&sha256(&data), &data).unwrap();
+        assert!(dir.join(".tmp-garbage").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
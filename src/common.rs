@@ -4,6 +4,9 @@ pub const TRAILER_SIZE: u64 = 16;
 // Just a random constant marker: "SFXLZMA!" in hex-like style
 pub const MAGIC: u64 = 0x5346584C5A4D4121; // "SFXLZMA!"
 
+// Trailer marker for SFX files that carry a metadata block between the payload and the trailer
+pub const MAGIC_META: u64 = 0x5346584C5A4D4132; // "SFXLZMA2"
+
 pub struct Trailer {
     pub payload_len: u64,
     pub magic: u64,
@@ -17,6 +20,24 @@ impl Trailer {
         }
     }
 
+    /// Trailer for an SFX that carries a metadata block (see `metadata`).
+    pub fn with_metadata(payload_len: u64) -> Self {
+        Self {
+            payload_len,
+            magic: MAGIC_META,
+        }
+    }
+
+    /// True if the magic is one of the recognised SFX markers.
+    pub fn is_valid_magic(&self) -> bool {
+        self.magic == MAGIC || self.magic == MAGIC_META
+    }
+
+    /// True if a metadata block precedes the trailer.
+    pub fn has_metadata(&self) -> bool {
+        self.magic == MAGIC_META
+    }
+
     pub fn to_bytes(&self) -> [u8; 16] {
         let mut buf = [0u8; 16];
         buf[..8].copy_from_slice(&self.payload_len.to_le_bytes());
@@ -110,6 +131,37 @@ mod tests {
         assert_eq!(&bytes, b"SFXLZMA!");
     }
 
+    #[test]
+    fn test_magic_meta_constant() {
+        assert_eq!(&MAGIC_META.to_be_bytes(), b"SFXLZMA2");
+    }
+
+    #[test]
+    fn test_trailer_with_metadata_roundtrip() {
+        let t = Trailer::with_metadata(77);
+        let restored = Trailer::from_reader(Cursor::new(t.to_bytes())).unwrap();
+        assert_eq!(restored.payload_len, 77);
+        assert!(restored.has_metadata());
+        assert!(restored.is_valid_magic());
+    }
+
+    #[test]
+    fn test_trailer_plain_has_no_metadata() {
+        let t = Trailer::new(1);
+        assert!(!t.has_metadata());
+        assert!(t.is_valid_magic());
+    }
+
+    #[test]
+    fn test_sec_uc002_trailer_unknown_magic_invalid() {
+        let t = Trailer {
+            payload_len: 1,
+            magic: 0x1234,
+        };
+        assert!(!t.is_valid_magic());
+        assert!(!t.has_metadata());
+    }
+
     #[test]
     fn test_sec_uc001_uc002_trailer_from_reader_truncated() {
         let result = Trailer::from_reader(Cursor::new([0u8; 8]));
@@ -55,6 +55,11 @@ fn compress_ultra(data: &[u8]) -> io::Result<Vec<u8>> {
     encoder.finish()
 }
 
+/// Raw Deflate at the highest level, for payloads stored as a ZIP entry.
+pub fn compress_deflate(data: &[u8]) -> Vec<u8> {
+    miniz_oxide::deflate::compress_to_vec(data, 10)
+}
+
 #[cfg(not(feature = "native-compress"))]
 fn compress_xz_to<R: io::BufRead, W: io::Write>(reader: &mut R, writer: &mut W) -> io::Result<()> {
     xz_compress(reader, writer).map_err(io::Error::other)
@@ -194,6 +199,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_compress_deflate_roundtrip() {
+        let data = b"deflate deflate deflate deflate".repeat(100);
+        let compressed = compress_deflate(&data);
+        assert!(compressed.len() < data.len());
+        let inflated = miniz_oxide::inflate::decompress_to_vec(&compressed).unwrap();
+        assert_eq!(inflated, data);
+    }
+
     #[test]
     fn test_sec_uc001_compress_null_payload() {
         // Payload entirely of null bytes
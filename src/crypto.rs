@@ -0,0 +1,455 @@
+use std::env;
+use std::fs;
+use std::io;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+use zeroize::Zeroizing;
+
+pub const KEY_LEN: usize = 32;
+pub const NONCE_LEN: usize = 12;
+pub const SALT_LEN: usize = 16;
+/// Poly1305 tag appended to the ciphertext.
+pub const TAG_LEN: usize = 16;
+
+pub const ENV_KEY: &str = "XSFX_KEY";
+pub const ENV_KEY_FILE: &str = "XSFX_KEY_FILE";
+
+/// Argon2id defaults used by the packer: 64 MiB, 3 passes, 1 lane.
+pub const DEFAULT_M_COST: u32 = 64 * 1024;
+pub const DEFAULT_T_COST: u32 = 3;
+pub const DEFAULT_P_COST: u32 = 1;
+
+// Upper bounds enforced before deriving a key, so a tampered metadata
+// block cannot make the stub allocate unbounded memory or spin forever.
+const MAX_M_COST: u32 = 1024 * 1024;
+const MAX_T_COST: u32 = 64;
+const MAX_P_COST: u32 = 16;
+
+const KDF_RAW: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+
+pub type Key = Zeroizing<[u8; KEY_LEN]>;
+
+/// How the payload key is obtained from the user's secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kdf {
+    /// The secret is the 32-byte key itself (raw or 64 hex digits).
+    Raw,
+    /// The secret is a passphrase stretched with Argon2id.
+    Argon2id {
+        salt: [u8; SALT_LEN],
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+}
+
+/// Encryption parameters recorded in the metadata block.
+///
+/// The compressed payload is sealed with ChaCha20-Poly1305; the metadata
+/// records are passed as associated data, so editing any recorded setting
+/// makes decryption fail.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encryption {
+    pub kdf: Kdf,
+    pub nonce: [u8; NONCE_LEN],
+}
+
+fn crypto_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl Encryption {
+    /// Fresh parameters with a random nonce (and salt for passphrases).
+    pub fn generate(passphrase: bool) -> io::Result<Self> {
+        let kdf = if passphrase {
+            Kdf::Argon2id {
+                salt: random_bytes()?,
+                m_cost: DEFAULT_M_COST,
+                t_cost: DEFAULT_T_COST,
+                p_cost: DEFAULT_P_COST,
+            }
+        } else {
+            Kdf::Raw
+        };
+        Ok(Self {
+            kdf,
+            nonce: random_bytes()?,
+        })
+    }
+
+    pub fn is_passphrase(&self) -> bool {
+        matches!(self.kdf, Kdf::Argon2id { .. })
+    }
+
+    /// `[kdf u8][nonce]`, plus `[salt][m u32][t u32][p u32]` for Argon2id.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(1 + NONCE_LEN + SALT_LEN + 12);
+        match &self.kdf {
+            Kdf::Raw => {
+                out.push(KDF_RAW);
+                out.extend_from_slice(&self.nonce);
+            }
+            Kdf::Argon2id {
+                salt,
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                out.push(KDF_ARGON2ID);
+                out.extend_from_slice(&self.nonce);
+                out.extend_from_slice(salt);
+                out.extend_from_slice(&m_cost.to_le_bytes());
+                out.extend_from_slice(&t_cost.to_le_bytes());
+                out.extend_from_slice(&p_cost.to_le_bytes());
+            }
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let (&kdf, rest) = data
+            .split_first()
+            .ok_or_else(|| crypto_err("Empty encryption record"))?;
+        if rest.len() < NONCE_LEN {
+            return Err(crypto_err("Truncated encryption record"));
+        }
+        let (nonce, rest) = rest.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().unwrap();
+        let kdf = match (kdf, rest.len()) {
+            (KDF_RAW, 0) => Kdf::Raw,
+            (KDF_ARGON2ID, len) if len == SALT_LEN + 12 => {
+                let u32_at = |i: usize| {
+                    u32::from_le_bytes(rest[SALT_LEN + i..SALT_LEN + i + 4].try_into().unwrap())
+                };
+                let (m_cost, t_cost, p_cost) = (u32_at(0), u32_at(4), u32_at(8));
+                if m_cost > MAX_M_COST || t_cost > MAX_T_COST || p_cost > MAX_P_COST {
+                    return Err(crypto_err("Argon2 parameters out of range"));
+                }
+                Kdf::Argon2id {
+                    salt: rest[..SALT_LEN].try_into().unwrap(),
+                    m_cost,
+                    t_cost,
+                    p_cost,
+                }
+            }
+            _ => return Err(crypto_err("Invalid encryption record")),
+        };
+        Ok(Self { kdf, nonce })
+    }
+
+    /// Turn the user's secret into the payload key.
+    pub fn derive_key(&self, secret: &[u8]) -> io::Result<Key> {
+        match &self.kdf {
+            Kdf::Raw => parse_raw_key(secret),
+            Kdf::Argon2id {
+                salt,
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                let params = Params::new(*m_cost, *t_cost, *p_cost, Some(KEY_LEN))
+                    .map_err(|_| crypto_err("Invalid Argon2 parameters"))?;
+                let mut key = Zeroizing::new([0u8; KEY_LEN]);
+                Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+                    .hash_password_into(secret, salt, &mut key[..])
+                    .map_err(|_| crypto_err("Key derivation failed"))?;
+                Ok(key)
+            }
+        }
+    }
+
+    /// Key for this SFX from `XSFX_KEY` / `XSFX_KEY_FILE`, falling back
+    /// to a terminal prompt for passphrase-protected payloads.
+    pub fn obtain_key(&self) -> io::Result<Key> {
+        let secret = match secret_from_env(self.is_passphrase())? {
+            Some(secret) => secret,
+            None if self.is_passphrase() => prompt_passphrase("xsfx passphrase: ")?,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "No payload key; set XSFX_KEY or XSFX_KEY_FILE",
+                ))
+            }
+        };
+        self.derive_key(&secret)
+    }
+
+    /// Seal `plaintext`, returning ciphertext with the tag appended.
+    pub fn encrypt(&self, key: &Key, aad: &[u8], plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let cipher = ChaCha20Poly1305::new_from_slice(&key[..])
+            .map_err(|_| crypto_err("Invalid key length"))?;
+        let msg = Payload {
+            msg: plaintext,
+            aad,
+        };
+        cipher
+            .encrypt(Nonce::from_slice(&self.nonce), msg)
+            .map_err(|_| crypto_err("Payload encryption failed"))
+    }
+
+    /// Open `ciphertext`; fails on a wrong key or any modification of the
+    /// ciphertext or associated data.
+    pub fn decrypt(
+        &self,
+        key: &Key,
+        aad: &[u8],
+        ciphertext: &[u8],
+    ) -> io::Result<Zeroizing<Vec<u8>>> {
+        let cipher = ChaCha20Poly1305::new_from_slice(&key[..])
+            .map_err(|_| crypto_err("Invalid key length"))?;
+        let msg = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        cipher
+            .decrypt(Nonce::from_slice(&self.nonce), msg)
+            .map(Zeroizing::new)
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "Payload decryption failed (wrong key or modified SFX)",
+                )
+            })
+    }
+}
+
+/// A raw key is exactly 32 bytes, or 64 hex digits (surrounding
+/// whitespace ignored).
+pub fn parse_raw_key(secret: &[u8]) -> io::Result<Key> {
+    let mut key = Zeroizing::new([0u8; KEY_LEN]);
+    if secret.len() == KEY_LEN {
+        key.copy_from_slice(secret);
+        return Ok(key);
+    }
+    let hex = secret.trim_ascii();
+    if hex.len() != KEY_LEN * 2 {
+        return Err(crypto_err("Key must be 32 bytes or 64 hex digits"));
+    }
+    for (i, pair) in hex.chunks(2).enumerate() {
+        let digits = std::str::from_utf8(pair).map_err(|_| crypto_err("Invalid hex key"))?;
+        key[i] = u8::from_str_radix(digits, 16).map_err(|_| crypto_err("Invalid hex key"))?;
+    }
+    Ok(key)
+}
+
+/// Strip one trailing line ending from a passphrase read from a file or tty.
+pub fn trim_passphrase(mut secret: Vec<u8>) -> Zeroizing<Vec<u8>> {
+    if secret.last() == Some(&b'\n') {
+        secret.pop();
+        if secret.last() == Some(&b'\r') {
+            secret.pop();
+        }
+    }
+    Zeroizing::new(secret)
+}
+
+/// Secret read from a key or passphrase file. Only a passphrase loses its
+/// trailing line ending: a binary key may end in `\n`, and
+/// `parse_raw_key` already ignores whitespace around a hex key.
+pub fn file_secret(secret: Vec<u8>, passphrase: bool) -> Zeroizing<Vec<u8>> {
+    if passphrase {
+        trim_passphrase(secret)
+    } else {
+        Zeroizing::new(secret)
+    }
+}
+
+/// Secret from `XSFX_KEY` or the file named by `XSFX_KEY_FILE`, removing
+/// both variables so the payload never sees them. `Ok(None)` if neither
+/// is set.
+pub fn secret_from_env(passphrase: bool) -> io::Result<Option<Zeroizing<Vec<u8>>>> {
+    let inline = env::var_os(ENV_KEY);
+    let file = env::var_os(ENV_KEY_FILE);
+    env::remove_var(ENV_KEY);
+    env::remove_var(ENV_KEY_FILE);
+    if let Some(v) = inline {
+        return Ok(Some(Zeroizing::new(v.into_encoded_bytes())));
+    }
+    match file {
+        Some(path) => Ok(Some(file_secret(fs::read(path)?, passphrase))),
+        None => Ok(None),
+    }
+}
+
+/// Ask for a passphrase on the controlling terminal with echo disabled.
+#[cfg(unix)]
+pub fn prompt_passphrase(prompt: &str) -> io::Result<Zeroizing<Vec<u8>>> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::io::AsRawFd;
+
+    let tty = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut saved: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut quiet = saved;
+    quiet.c_lflag &= !libc::ECHO;
+    quiet.c_lflag |= libc::ECHONL;
+    (&tty).write_all(prompt.as_bytes())?;
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &quiet) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut line = Vec::new();
+    let result = BufReader::new(&tty).read_until(b'\n', &mut line);
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &saved) };
+    result?;
+    Ok(trim_passphrase(line))
+}
+
+#[cfg(not(unix))]
+pub fn prompt_passphrase(_prompt: &str) -> io::Result<Zeroizing<Vec<u8>>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Passphrase prompt not supported; set XSFX_KEY or XSFX_KEY_FILE",
+    ))
+}
+
+fn random_bytes<const N: usize>() -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    getrandom::getrandom(&mut buf).map_err(|e| io::Error::other(e.to_string()))?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_argon() -> Encryption {
+        Encryption {
+            kdf: Kdf::Argon2id {
+                salt: [7; SALT_LEN],
+                m_cost: 64,
+                t_cost: 1,
+                p_cost: 1,
+            },
+            nonce: [1; NONCE_LEN],
+        }
+    }
+
+    #[test]
+    fn test_encryption_record_roundtrip() {
+        let raw = Encryption {
+            kdf: Kdf::Raw,
+            nonce: [9; NONCE_LEN],
+        };
+        assert_eq!(Encryption::from_bytes(&raw.to_bytes()).unwrap(), raw);
+        let argon = fast_argon();
+        assert_eq!(Encryption::from_bytes(&argon.to_bytes()).unwrap(), argon);
+    }
+
+    #[test]
+    fn test_generate_uses_fresh_nonce() {
+        let a = Encryption::generate(true).unwrap();
+        let b = Encryption::generate(true).unwrap();
+        assert_ne!(a.nonce, b.nonce);
+        assert!(a.is_passphrase());
+        assert!(!Encryption::generate(false).unwrap().is_passphrase());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_raw_key() {
+        let enc = Encryption {
+            kdf: Kdf::Raw,
+            nonce: [3; NONCE_LEN],
+        };
+        let key = enc.derive_key(&[0x42; KEY_LEN]).unwrap();
+        let sealed = enc.encrypt(&key, b"aad", b"secret model").unwrap();
+        assert_eq!(sealed.len(), b"secret model".len() + TAG_LEN);
+        let opened = enc.decrypt(&key, b"aad", &sealed).unwrap();
+        assert_eq!(&opened[..], b"secret model");
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_passphrase() {
+        let enc = fast_argon();
+        let key = enc.derive_key(b"correct horse").unwrap();
+        let sealed = enc.encrypt(&key, b"", b"data").unwrap();
+        let again = enc.derive_key(b"correct horse").unwrap();
+        assert_eq!(&enc.decrypt(&again, b"", &sealed).unwrap()[..], b"data");
+    }
+
+    #[test]
+    fn test_parse_raw_key_hex() {
+        let hex = format!("{}\n", "0f".repeat(KEY_LEN));
+        assert_eq!(*parse_raw_key(hex.as_bytes()).unwrap(), [0x0F; KEY_LEN]);
+        assert!(parse_raw_key(b"abcd").is_err());
+        assert!(parse_raw_key("zz".repeat(KEY_LEN).as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_trim_passphrase() {
+        assert_eq!(&trim_passphrase(b"pw\r\n".to_vec())[..], b"pw");
+        assert_eq!(&trim_passphrase(b"pw \n".to_vec())[..], b"pw ");
+        assert_eq!(&trim_passphrase(b"pw".to_vec())[..], b"pw");
+    }
+
+    #[test]
+    fn test_file_secret_keeps_binary_key() {
+        let mut raw = [0x42; KEY_LEN];
+        raw[KEY_LEN - 1] = b'\n';
+        let key = parse_raw_key(&file_secret(raw.to_vec(), false)).unwrap();
+        assert_eq!(*key, raw);
+        raw[KEY_LEN - 2] = b'\r';
+        let key = parse_raw_key(&file_secret(raw.to_vec(), false)).unwrap();
+        assert_eq!(*key, raw);
+        assert_eq!(&file_secret(b"pw\r\n".to_vec(), true)[..], b"pw");
+    }
+
+    #[test]
+    fn test_sec_uc009_wrong_passphrase_rejected() {
+        let enc = fast_argon();
+        let key = enc.derive_key(b"right").unwrap();
+        let sealed = enc.encrypt(&key, b"", b"data").unwrap();
+        let wrong = enc.derive_key(b"wrong").unwrap();
+        let err = enc.decrypt(&wrong, b"", &sealed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_sec_uc009_modified_aad_rejected() {
+        let enc = fast_argon();
+        let key = enc.derive_key(b"pw").unwrap();
+        let sealed = enc.encrypt(&key, b"meta-v1", b"data").unwrap();
+        assert!(enc.decrypt(&key, b"meta-v2", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc009_modified_ciphertext_rejected() {
+        let enc = fast_argon();
+        let key = enc.derive_key(b"pw").unwrap();
+        let mut sealed = enc.encrypt(&key, b"", b"data").unwrap();
+        sealed[0] ^= 1;
+        assert!(enc.decrypt(&key, b"", &sealed).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc009_argon2_cost_bounded() {
+        let enc = Encryption {
+            kdf: Kdf::Argon2id {
+                salt: [0; SALT_LEN],
+                m_cost: u32::MAX,
+                t_cost: 1,
+                p_cost: 1,
+            },
+            nonce: [0; NONCE_LEN],
+        };
+        assert!(Encryption::from_bytes(&enc.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc009_bad_record_rejected() {
+        assert!(Encryption::from_bytes(&[]).is_err());
+        assert!(Encryption::from_bytes(&[KDF_RAW, 1, 2]).is_err());
+        assert!(Encryption::from_bytes(&[9; 1 + NONCE_LEN]).is_err());
+        let mut raw_extra = vec![KDF_RAW];
+        raw_extra.extend_from_slice(&[0; NONCE_LEN + 1]);
+        assert!(Encryption::from_bytes(&raw_extra).is_err());
+    }
+}
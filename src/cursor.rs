@@ -0,0 +1,100 @@
+use std::io;
+
+/// Little-endian reader over a metadata record value. `what` names the
+/// record in error messages ("Truncated launch settings").
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    what: &'static str,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8], what: &'static str) -> Self {
+        Self { data, what }
+    }
+
+    fn err(&self, msg: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("{} {}", msg, self.what))
+    }
+
+    /// Bytes not consumed yet.
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < n {
+            return Err(self.err("Truncated"));
+        }
+        let (head, tail) = self.data.split_at(n);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// `[len u16][utf8]`.
+    pub fn str16(&mut self) -> io::Result<&'a str> {
+        let len = self.u16()? as usize;
+        let bytes = self.take(len)?;
+        std::str::from_utf8(bytes).map_err(|_| self.err("Invalid UTF-8 in"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reader_roundtrip() {
+        let mut data = vec![7];
+        data.extend_from_slice(&0x1234u16.to_le_bytes());
+        data.extend_from_slice(&0xdead_beefu32.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&3u16.to_le_bytes());
+        data.extend_from_slice(b"abc");
+        let mut r = ByteReader::new(&data, "test record");
+        assert_eq!(r.u8().unwrap(), 7);
+        assert_eq!(r.u16().unwrap(), 0x1234);
+        assert_eq!(r.u32().unwrap(), 0xdead_beef);
+        assert_eq!(r.u64().unwrap(), u64::MAX);
+        assert_eq!(r.remaining(), 5);
+        assert_eq!(r.str16().unwrap(), "abc");
+        assert!(r.is_empty());
+    }
+
+    #[test]
+    fn test_sec_reader_truncated_and_bad_utf8() {
+        let err = ByteReader::new(&[1, 2, 3], "test record")
+            .u32()
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Truncated test record");
+
+        // Length prefix past the end.
+        let mut r = ByteReader::new(&[9, 0, b'a'], "test record");
+        assert!(r.str16().is_err());
+
+        let err = ByteReader::new(&[1, 0, 0xff], "test record")
+            .str16()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Invalid UTF-8 in test record");
+    }
+}
@@ -8,10 +8,84 @@ pub fn decompress_payload<R: io::BufRead>(reader: &mut R) -> io::Result<Vec<u8>>
     Ok(payload)
 }
 
+/// Writer into a pre-reserved buffer that refuses to grow past its limit.
+struct BoundedWriter {
+    buf: Vec<u8>,
+    limit: usize,
+}
+
+impl io::Write for BoundedWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.len() > self.limit - self.buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Decompressed payload exceeds recorded size",
+            ));
+        }
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Decompress a payload whose uncompressed size was recorded at pack time.
+///
+/// The output buffer is reserved up front, so an allocation failure is
+/// reported as `ErrorKind::OutOfMemory` instead of aborting, and a stream
+/// that inflates to any other size is rejected.
+pub fn decompress_payload_exact<R: io::BufRead>(
+    reader: &mut R,
+    expected_len: u64,
+) -> io::Result<Vec<u8>> {
+    let oom = || io::Error::new(io::ErrorKind::OutOfMemory, "Cannot allocate payload buffer");
+    let limit = usize::try_from(expected_len).map_err(|_| oom())?;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(limit).map_err(|_| oom())?;
+    let mut writer = BoundedWriter { buf, limit };
+    xz_decompress(reader, &mut writer).map_err(|e| match e {
+        lzma_rs::error::Error::IoError(io) => io,
+        _ => io::Error::other("decompression failed"),
+    })?;
+    if writer.buf.len() != limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Decompressed payload shorter than recorded size",
+        ));
+    }
+    Ok(writer.buf)
+}
+
+/// Inflate a raw Deflate payload (`--zip`) of a recorded uncompressed size.
+pub fn inflate_payload_exact(data: &[u8], expected_len: u64) -> io::Result<Vec<u8>> {
+    let limit = usize::try_from(expected_len).map_err(|_| {
+        io::Error::new(io::ErrorKind::OutOfMemory, "Cannot allocate payload buffer")
+    })?;
+    let out = miniz_oxide::inflate::decompress_to_vec_with_limit(data, limit).map_err(|e| {
+        if e.status == miniz_oxide::inflate::TINFLStatus::HasMoreOutput {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Decompressed payload exceeds recorded size",
+            )
+        } else {
+            io::Error::other("decompression failed")
+        }
+    })?;
+    if out.len() != limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Decompressed payload shorter than recorded size",
+        ));
+    }
+    Ok(out)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::compress::compress_lzma;
+    use crate::compress::{compress_deflate, compress_lzma};
     use std::io::{BufReader, Cursor};
 
     #[test]
@@ -34,6 +108,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decompress_exact_valid() {
+        let original = vec![0x5Au8; 4096];
+        let compressed = compress_lzma(&original).unwrap();
+        let mut reader = BufReader::new(Cursor::new(compressed));
+        let result = decompress_payload_exact(&mut reader, 4096).unwrap();
+        assert_eq!(result, original);
+    }
+
+    #[test]
+    fn test_sec_uc006_decompress_exact_larger_than_recorded() {
+        let compressed = compress_lzma(&[1u8; 1000]).unwrap();
+        let mut reader = BufReader::new(Cursor::new(compressed));
+        let err = decompress_payload_exact(&mut reader, 999).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_sec_uc006_decompress_exact_shorter_than_recorded() {
+        let compressed = compress_lzma(&[1u8; 1000]).unwrap();
+        let mut reader = BufReader::new(Cursor::new(compressed));
+        let err = decompress_payload_exact(&mut reader, 1001).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_sec_uc006_decompress_exact_unallocatable() {
+        let compressed = compress_lzma(b"x").unwrap();
+        let mut reader = BufReader::new(Cursor::new(compressed));
+        let err = decompress_payload_exact(&mut reader, u64::MAX).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::OutOfMemory);
+    }
+
+    #[test]
+    fn test_sec_uc006_decompress_exact_invalid_stream() {
+        let mut reader = BufReader::new(Cursor::new(vec![0xFFu8; 64]));
+        let err = decompress_payload_exact(&mut reader, 10).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_inflate_exact() {
+        let original = vec![0x5Au8; 4096];
+        let compressed = compress_deflate(&original);
+        assert_eq!(inflate_payload_exact(&compressed, 4096).unwrap(), original);
+        let err = inflate_payload_exact(&compressed, 4095).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = inflate_payload_exact(&compressed, 4097).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(inflate_payload_exact(b"\xff\xff\xff", 10).is_err());
+    }
+
     #[test]
     fn test_sec_uc002_decompress_invalid_data() {
         let bad_data = vec![0xFF; 100];
@@ -0,0 +1,374 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+pub const ENV_DEBUG: &str = "XSFX_DEBUG";
+pub const ENV_TRACE_FD: &str = "XSFX_TRACE_FD";
+
+/// Stub startup phases, used for timing and to label errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Args,
+    Open,
+    Trailer,
+    Validity,
+    Cache,
+    Decrypt,
+    Decompress,
+    Verify,
+    Memfd,
+    Setup,
+    Isolate,
+    Sandbox,
+    Hook,
+    Supervise,
+    Exec,
+    Extract,
+    Install,
+}
+
+impl Phase {
+    pub fn name(self) -> &'static str {
+        match self {
+            Phase::Args => "stub arguments",
+            Phase::Open => "open self",
+            Phase::Trailer => "trailer validation",
+            Phase::Validity => "validity window",
+            Phase::Cache => "cache",
+            Phase::Decrypt => "decryption",
+            Phase::Decompress => "decompression",
+            Phase::Verify => "integrity check",
+            Phase::Memfd => "memfd",
+            Phase::Setup => "process setup",
+            Phase::Isolate => "namespace setup",
+            Phase::Sandbox => "sandbox",
+            Phase::Hook => "hook",
+            Phase::Supervise => "supervisor",
+            Phase::Exec => "exec",
+            Phase::Extract => "payload extraction",
+            Phase::Install => "installation",
+        }
+    }
+}
+
+/// An I/O error tagged with the phase in which it happened.
+#[derive(Debug)]
+pub struct StubError {
+    pub phase: Phase,
+    pub source: io::Error,
+}
+
+impl StubError {
+    pub fn new(phase: Phase, source: io::Error) -> Self {
+        Self { phase, source }
+    }
+}
+
+impl fmt::Display for StubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed: {}", self.phase.name(), self.source)
+    }
+}
+
+impl Error for StubError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Attach a phase to an `io::Result`.
+pub trait PhaseExt<T> {
+    fn phase(self, phase: Phase) -> Result<T, StubError>;
+}
+
+impl<T> PhaseExt<T> for io::Result<T> {
+    fn phase(self, phase: Phase) -> Result<T, StubError> {
+        self.map_err(|e| StubError::new(phase, e))
+    }
+}
+
+/// True for the usual "on" spellings of a boolean env var.
+pub fn is_truthy(value: &str) -> bool {
+    matches!(
+        value.trim().to_ascii_lowercase().as_str(),
+        "1" | "true" | "yes" | "on"
+    )
+}
+
+/// Parse `XSFX_TRACE_FD`; stdin and negative values are rejected.
+pub fn parse_trace_fd(value: &str) -> Option<i32> {
+    value.trim().parse::<i32>().ok().filter(|fd| *fd > 0)
+}
+
+/// Diagnostic reporter for the stub.
+///
+/// Phase timings are always collected (two `Instant` reads per phase) but
+/// only written when diagnostics are enabled, either via `XSFX_DEBUG` or
+/// by the pack-time `--debug` flag.
+pub struct Diag {
+    enabled: bool,
+    sink: Box<dyn Write>,
+    start: Instant,
+    mark: Instant,
+    timings: Vec<(Phase, Duration)>,
+}
+
+impl Diag {
+    pub fn new(enabled: bool, sink: Box<dyn Write>) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled,
+            sink,
+            start: now,
+            mark: now,
+            timings: Vec::new(),
+        }
+    }
+
+    /// Build from `XSFX_DEBUG` / `XSFX_TRACE_FD` and remove both variables
+    /// so the payload never sees them.
+    pub fn from_env() -> Self {
+        let enabled = env::var(ENV_DEBUG).map(|v| is_truthy(&v)).unwrap_or(false);
+        let trace_fd = env::var(ENV_TRACE_FD).ok().and_then(|v| parse_trace_fd(&v));
+        env::remove_var(ENV_DEBUG);
+        env::remove_var(ENV_TRACE_FD);
+        Self::new(enabled, open_sink(trace_fd))
+    }
+
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record the time spent since the previous mark as `phase`.
+    pub fn mark(&mut self, phase: Phase) {
+        let now = Instant::now();
+        self.timings.push((phase, now - self.mark));
+        self.mark = now;
+    }
+
+    /// Write a free-form diagnostic line.
+    pub fn note(&mut self, msg: &str) {
+        if self.enabled {
+            let _ = writeln!(self.sink, "xsfx: {}", msg);
+        }
+    }
+
+    /// Write collected phase timings. Called right before handing control
+    /// to the payload, since a successful exec never returns.
+    pub fn report_timings(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        for (phase, elapsed) in &self.timings {
+            let _ = writeln!(self.sink, "xsfx: {:<20} {:>10.3?}", phase.name(), elapsed);
+        }
+        let _ = writeln!(
+            self.sink,
+            "xsfx: {:<20} {:>10.3?}",
+            "total",
+            self.start.elapsed()
+        );
+        let _ = self.sink.flush();
+    }
+
+    /// Write the full error chain for `err`.
+    pub fn report_error(&mut self, err: &StubError) {
+        if !self.enabled {
+            return;
+        }
+        let _ = writeln!(self.sink, "xsfx: error: {}", err);
+        let mut cause = err.source.source();
+        while let Some(c) = cause {
+            let _ = writeln!(self.sink, "xsfx:   caused by: {}", c);
+            cause = c.source();
+        }
+        if let Some(code) = err.source.raw_os_error() {
+            let _ = writeln!(self.sink, "xsfx:   errno: {}", code);
+        }
+        let _ = self.sink.flush();
+    }
+}
+
+#[cfg(unix)]
+fn open_sink(trace_fd: Option<i32>) -> Box<dyn Write> {
+    use std::os::unix::io::FromRawFd;
+    if let Some(fd) = trace_fd {
+        // Duplicate with CLOEXEC so the caller's fd is left untouched and
+        // our copy does not leak into the payload.
+        let dup = unsafe { libc::fcntl(fd, libc::F_DUPFD_CLOEXEC, 3) };
+        if dup >= 0 {
+            return Box::new(unsafe { std::fs::File::from_raw_fd(dup) });
+        }
+    }
+    Box::new(io::stderr())
+}
+
+#[cfg(not(unix))]
+fn open_sink(_trace_fd: Option<i32>) -> Box<dyn Write> {
+    Box::new(io::stderr())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuf {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_is_truthy() {
+        for v in ["1", "true", "YES", " on "] {
+            assert!(is_truthy(v), "{v}");
+        }
+        for v in ["", "0", "false", "no", "2"] {
+            assert!(!is_truthy(v), "{v}");
+        }
+    }
+
+    #[test]
+    fn test_parse_trace_fd() {
+        assert_eq!(parse_trace_fd("3"), Some(3));
+        assert_eq!(parse_trace_fd(" 9 "), Some(9));
+        assert_eq!(parse_trace_fd("0"), None);
+        assert_eq!(parse_trace_fd("-1"), None);
+        assert_eq!(parse_trace_fd("abc"), None);
+    }
+
+    /// The phase after `phase` in declaration order. The `match` has no
+    /// wildcard, so a new variant does not compile until it is chained in
+    /// here and so covered by the tests that walk all phases.
+    fn next_phase(phase: Phase) -> Option<Phase> {
+        match phase {
+            Phase::Args => Some(Phase::Open),
+            Phase::Open => Some(Phase::Trailer),
+            Phase::Trailer => Some(Phase::Validity),
+            Phase::Validity => Some(Phase::Cache),
+            Phase::Cache => Some(Phase::Decrypt),
+            Phase::Decrypt => Some(Phase::Decompress),
+            Phase::Decompress => Some(Phase::Verify),
+            Phase::Verify => Some(Phase::Memfd),
+            Phase::Memfd => Some(Phase::Setup),
+            Phase::Setup => Some(Phase::Isolate),
+            Phase::Isolate => Some(Phase::Sandbox),
+            Phase::Sandbox => Some(Phase::Hook),
+            Phase::Hook => Some(Phase::Supervise),
+            Phase::Supervise => Some(Phase::Exec),
+            Phase::Exec => Some(Phase::Extract),
+            Phase::Extract => Some(Phase::Install),
+            Phase::Install => None,
+        }
+    }
+
+    fn all_phases() -> Vec<Phase> {
+        let mut phases = vec![Phase::Args];
+        while let Some(next) = next_phase(phases[phases.len() - 1]) {
+            assert!(!phases.contains(&next), "phase chain loops at {:?}", next);
+            phases.push(next);
+        }
+        phases
+    }
+
+    #[test]
+    fn test_phase_names_distinct() {
+        let phases = all_phases();
+        assert_eq!(phases.len(), 17);
+        for (i, a) in phases.iter().enumerate() {
+            for b in &phases[i + 1..] {
+                assert_ne!(a.name(), b.name());
+            }
+        }
+    }
+
+    #[test]
+    fn test_stub_error_display() {
+        let err = StubError::new(Phase::Decompress, io::Error::other("bad stream"));
+        assert_eq!(err.to_string(), "decompression failed: bad stream");
+        assert!(err.source().is_some());
+    }
+
+    #[test]
+    fn test_phase_ext_maps_error() {
+        let r: io::Result<()> = Err(io::Error::from_raw_os_error(libc::ENOSYS));
+        let err = r.phase(Phase::Memfd).unwrap_err();
+        assert_eq!(err.phase, Phase::Memfd);
+        assert_eq!(err.source.raw_os_error(), Some(libc::ENOSYS));
+    }
+
+    #[test]
+    fn test_diag_disabled_writes_nothing() {
+        let buf = SharedBuf::default();
+        let mut diag = Diag::new(false, Box::new(buf.clone()));
+        diag.mark(Phase::Open);
+        diag.note("hidden");
+        diag.report_timings();
+        diag.report_error(&StubError::new(Phase::Exec, io::Error::other("x")));
+        assert!(buf.text().is_empty());
+        assert!(!diag.is_enabled());
+    }
+
+    #[test]
+    fn test_diag_reports_timings() {
+        let buf = SharedBuf::default();
+        let mut diag = Diag::new(false, Box::new(buf.clone()));
+        diag.enable();
+        diag.mark(Phase::Open);
+        diag.mark(Phase::Trailer);
+        diag.report_timings();
+        let text = buf.text();
+        assert!(text.contains("open self"));
+        assert!(text.contains("trailer validation"));
+        assert!(text.contains("total"));
+    }
+
+    #[test]
+    fn test_diag_reports_error_chain_and_errno() {
+        let buf = SharedBuf::default();
+        let mut diag = Diag::new(true, Box::new(buf.clone()));
+        let inner = io::Error::from_raw_os_error(libc::EPERM);
+        diag.report_error(&StubError::new(Phase::Exec, inner));
+        let text = buf.text();
+        assert!(text.contains("xsfx: error: exec failed"));
+        assert!(text.contains(&format!("errno: {}", libc::EPERM)));
+    }
+
+    #[test]
+    fn test_diag_reports_nested_cause() {
+        let buf = SharedBuf::default();
+        let mut diag = Diag::new(true, Box::new(buf.clone()));
+        let root = io::Error::new(io::ErrorKind::InvalidData, "root cause");
+        let nested = io::Error::other(StubError::new(Phase::Open, root));
+        diag.report_error(&StubError::new(Phase::Trailer, nested));
+        assert!(buf.text().contains("caused by: root cause"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sec_uc005_invalid_trace_fd_falls_back() {
+        // An fd that is not open must not panic; stderr is used instead.
+        let mut sink = open_sink(Some(987_654));
+        assert!(sink.flush().is_ok());
+    }
+}
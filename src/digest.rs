@@ -0,0 +1,73 @@
+use std::io;
+
+use sha2::{Digest, Sha256};
+
+pub const SHA256_LEN: usize = 32;
+
+/// SHA-256 digest of `data`.
+pub fn sha256(data: &[u8]) -> [u8; SHA256_LEN] {
+    Sha256::digest(data).into()
+}
+
+/// Check `data` against an expected SHA-256 digest.
+pub fn verify_sha256(data: &[u8], expected: &[u8; SHA256_LEN]) -> io::Result<()> {
+    if sha256(data) != *expected {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Payload digest mismatch",
+        ));
+    }
+    Ok(())
+}
+
+/// Lowercase hex encoding of `bytes`.
+pub fn to_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        out.push(DIGITS[(b >> 4) as usize] as char);
+        out.push(DIGITS[(b & 0x0F) as usize] as char);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_empty() {
+        assert_eq!(
+            to_hex(&sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_sha256_abc() {
+        assert_eq!(
+            to_hex(&sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_verify_sha256_match() {
+        assert!(verify_sha256(b"abc", &sha256(b"abc")).is_ok());
+    }
+
+    #[test]
+    fn test_sec_uc006_verify_sha256_mismatch() {
+        let err = verify_sha256(b"abd", &sha256(b"abc")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_to_hex_all_nibbles() {
+        assert_eq!(
+            to_hex(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xAB, 0xCD, 0xEF]),
+            "0123456789abcdef"
+        );
+        assert_eq!(to_hex(&[]), "");
+    }
+}
@@ -0,0 +1,456 @@
+use std::collections::{HashSet, VecDeque};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::assets::{encode_archive, validate_name, Asset};
+use crate::elf::{parse_elf, ElfInfo};
+
+/// Symlinks followed while resolving one path, as in the kernel.
+const MAX_SYMLINKS: u32 = 40;
+
+fn input_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Debian-style multiarch directory name for an `e_machine`.
+fn multiarch_triple(machine: u16) -> Option<&'static str> {
+    match machine {
+        3 => Some("i386-linux-gnu"),
+        40 => Some("arm-linux-gnueabihf"),
+        62 => Some("x86_64-linux-gnu"),
+        183 => Some("aarch64-linux-gnu"),
+        243 => Some("riscv64-linux-gnu"),
+        _ => None,
+    }
+}
+
+/// Directories searched after `DT_RUNPATH`: the multiarch directories,
+/// then the usual `ld.so` defaults.
+fn default_dirs(elf: &ElfInfo) -> Vec<String> {
+    let mut dirs = Vec::new();
+    if let Some(triple) = multiarch_triple(elf.machine) {
+        dirs.push(format!("/lib/{}", triple));
+        dirs.push(format!("/usr/lib/{}", triple));
+    }
+    if elf.is_64 {
+        dirs.extend(["/lib64".to_string(), "/usr/lib64".to_string()]);
+    }
+    dirs.extend(["/lib", "/usr/lib", "/usr/local/lib"].map(String::from));
+    dirs
+}
+
+fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+    for c in path.components().rev() {
+        match c {
+            Component::Normal(name) => pending.push(name.to_os_string()),
+            Component::ParentDir => pending.push("..".into()),
+            _ => {}
+        }
+    }
+}
+
+/// Resolve the absolute `path` as if `root` were `/`: symlinks, including
+/// absolute ones, and `..` never leave `root`.
+pub fn resolve_in_root(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut pending = Vec::new();
+    push_components(&mut pending, path);
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        if name == ".." {
+            resolved.pop();
+            continue;
+        }
+        resolved.push(&name);
+        let full = root.join(&resolved);
+        if !fs::symlink_metadata(&full)?.file_type().is_symlink() {
+            continue;
+        }
+        links += 1;
+        if links > MAX_SYMLINKS {
+            return Err(io::Error::other(format!(
+                "Too many symlinks in {}",
+                path.display()
+            )));
+        }
+        let target = fs::read_link(&full)?;
+        resolved.pop();
+        if target.is_absolute() {
+            resolved.clear();
+        }
+        push_components(&mut pending, &target);
+    }
+    Ok(root.join(resolved))
+}
+
+/// A loaded candidate: where it was found, its bytes and its headers.
+struct Found {
+    path: PathBuf,
+    data: Vec<u8>,
+    elf: ElfInfo,
+}
+
+/// Read an ELF object if `path` is one that fits into the program.
+fn try_candidate(path: &Path, program: &ElfInfo) -> Option<Found> {
+    let data = fs::read(path).ok()?;
+    let elf = parse_elf(&data).ok()?;
+    if !elf.compatible(program) {
+        return None;
+    }
+    Some(Found {
+        path: path.to_path_buf(),
+        data,
+        elf,
+    })
+}
+
+/// Search for `soname` like `ld.so`: the requester's `DT_RUNPATH` (with
+/// `$ORIGIN` being the requester's directory), then the default
+/// directories, all below `root`. Incompatible files are skipped.
+fn find_library(
+    soname: &str,
+    runpath: &[String],
+    origin: &Path,
+    root: &Path,
+    program: &ElfInfo,
+) -> Option<Found> {
+    let origin = origin.to_string_lossy();
+    for dir in runpath {
+        let expanded = dir
+            .replace("${ORIGIN}", &origin)
+            .replace("$ORIGIN", &origin);
+        let candidate = if expanded != *dir {
+            Some(Path::new(&expanded).join(soname))
+        } else {
+            resolve_in_root(root, &Path::new(dir).join(soname)).ok()
+        };
+        if let Some(found) = candidate.and_then(|p| try_candidate(&p, program)) {
+            return Some(found);
+        }
+    }
+    default_dirs(program).iter().find_map(|dir| {
+        let path = resolve_in_root(root, &Path::new(dir).join(soname)).ok()?;
+        try_candidate(&path, program)
+    })
+}
+
+/// Resolve the loader (`PT_INTERP`) and every `DT_NEEDED` library of
+/// `program` below `sysroot`, and encode them as the payload archive:
+/// the program, then the loader, then the libraries in breadth-first
+/// order, named after the loader's file name and the sonames.
+///
+/// Only glibc's loader is accepted: the stub preloads the libraries by
+/// `/proc/self/fd` path, and musl's loader does not match them by soname.
+pub fn bundle_libs(
+    program: &[u8],
+    program_path: &Path,
+    name: &str,
+    sysroot: &Path,
+) -> io::Result<(Vec<u8>, Vec<String>)> {
+    validate_name(name).map_err(|_| input_err(format!("Invalid payload name '{}'", name)))?;
+    let elf = parse_elf(program)
+        .map_err(|e| input_err(format!("Payload is not a valid ELF image: {}", e)))?;
+    let Some(interp) = &elf.interp else {
+        return Err(input_err(
+            "Payload is statically linked; --bundle-libs is not needed".into(),
+        ));
+    };
+    let interp_name = Path::new(interp)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| validate_name(n).is_ok())
+        .ok_or_else(|| input_err(format!("Invalid ELF interpreter '{}'", interp)))?;
+    if interp_name.starts_with("ld-musl-") {
+        return Err(input_err(
+            "musl's loader cannot run preloaded libraries; link the payload statically".into(),
+        ));
+    }
+    let loader = resolve_in_root(sysroot, Path::new(interp))
+        .ok()
+        .and_then(|p| try_candidate(&p, &elf))
+        .ok_or_else(|| {
+            input_err(format!(
+                "ELF interpreter {} not found in sysroot {}",
+                interp,
+                sysroot.display()
+            ))
+        })?;
+    if !contains(&loader.data, b"--preload") {
+        return Err(input_err(format!(
+            "{} does not support --preload (glibc 2.30 or later is needed)",
+            interp
+        )));
+    }
+
+    let mut entries = vec![
+        Asset {
+            name: name.to_string(),
+            mode: 0o755,
+            data: program.to_vec(),
+        },
+        Asset {
+            name: interp_name.to_string(),
+            mode: 0o755,
+            data: loader.data,
+        },
+    ];
+    // The loader satisfies its own soname, e.g. libc's `ld-linux-*.so.2`.
+    let mut seen: HashSet<String> = HashSet::from([interp_name.to_string()]);
+    seen.extend(loader.elf.soname);
+    let origin = fs::canonicalize(program_path)
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let mut queue = VecDeque::from([(elf.clone(), origin, name.to_string())]);
+    let mut libs = Vec::new();
+    while let Some((object, origin, object_name)) = queue.pop_front() {
+        for soname in &object.needed {
+            if seen.contains(soname) {
+                continue;
+            }
+            if validate_name(soname).is_err() || soname.contains('/') {
+                return Err(input_err(format!(
+                    "{} needs '{}', which is not a plain soname",
+                    object_name, soname
+                )));
+            }
+            let found =
+                find_library(soname, &object.runpath, &origin, sysroot, &elf).ok_or_else(|| {
+                    input_err(format!(
+                        "Library {} needed by {} not found in sysroot {}",
+                        soname,
+                        object_name,
+                        sysroot.display()
+                    ))
+                })?;
+            // Preloaded libraries are matched to DT_NEEDED by DT_SONAME
+            // alone; without it the loader would search the host.
+            if found.elf.soname.as_deref() != Some(soname.as_str()) {
+                return Err(input_err(format!(
+                    "{} does not have DT_SONAME {}",
+                    found.path.display(),
+                    soname
+                )));
+            }
+            seen.insert(soname.clone());
+            let lib_origin = found
+                .path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            queue.push_back((found.elf, lib_origin, soname.clone()));
+            libs.push(soname.clone());
+            entries.push(Asset {
+                name: soname.clone(),
+                mode: 0o755,
+                data: found.data,
+            });
+        }
+    }
+    Ok((encode_archive(&entries), libs))
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+/// Loader arguments that run `program_fd` with `lib_fds` preloaded, in
+/// order, so the loader finds every `DT_NEEDED` library by soname among
+/// them instead of on disk.
+pub fn loader_args(
+    program_fd: i32,
+    lib_fds: &[i32],
+    argv0: Option<&str>,
+    args: Vec<String>,
+) -> Vec<String> {
+    let mut out = Vec::with_capacity(args.len() + 5);
+    if !lib_fds.is_empty() {
+        let list: Vec<String> = lib_fds
+            .iter()
+            .map(|fd| format!("/proc/self/fd/{}", fd))
+            .collect();
+        out.push("--preload".to_string());
+        out.push(list.join(":"));
+    }
+    if let Some(argv0) = argv0 {
+        out.push("--argv0".to_string());
+        out.push(argv0.to_string());
+    }
+    out.push(format!("/proc/self/fd/{}", program_fd));
+    out.extend(args);
+    out
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::prepare_launch;
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{contains, loader_args};
+    use crate::assets::parse_archive;
+    use crate::memfd::{create_sealed_memfd, into_inherited_fd};
+    use std::ffi::CString;
+    use std::io;
+
+    /// Publish the program and libraries of a bundle as inherited memfds.
+    /// Returns the loader image to execute and its arguments; `argv0` is
+    /// passed on if the loader supports `--argv0` (glibc 2.33 or later).
+    pub fn prepare_launch(
+        archive: &[u8],
+        argv0: &str,
+        args: Vec<String>,
+    ) -> io::Result<(Vec<u8>, Vec<String>)> {
+        let entries = parse_archive(archive)?;
+        let [program, loader, libs @ ..] = &entries[..] else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Bundle without program or loader",
+            ));
+        };
+        let mut fds = Vec::with_capacity(entries.len() - 1);
+        for entry in std::iter::once(program).chain(libs) {
+            let name: String = entry.name.chars().take(64).collect();
+            let name = CString::new(format!("xsfx:{}", name))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            fds.push(into_inherited_fd(
+                create_sealed_memfd(&name, entry.data, entry.mode)?.file,
+            )?);
+        }
+        let argv0 = contains(loader.data, b"--argv0").then_some(argv0);
+        Ok((
+            loader.data.to_vec(),
+            loader_args(fds[0], &fds[1..], argv0, args),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::parse_archive;
+    use crate::elf::tests::build_elf;
+
+    const DT_NEEDED: u64 = 1;
+    const DT_SONAME: u64 = 14;
+    const DT_RUNPATH: u64 = 29;
+
+    fn temp_root(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xsfx-dynlink-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn put(root: &Path, path: &str, data: &[u8]) {
+        let full = root.join(path.trim_start_matches('/'));
+        fs::create_dir_all(full.parent().unwrap()).unwrap();
+        fs::write(full, data).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_in_root_stays_inside() {
+        let root = temp_root("resolve");
+        put(&root, "/lib/x86_64-linux-gnu/libc.so.6", b"libc");
+        put(&root, "/etc/passwd", b"inside");
+        std::os::unix::fs::symlink("/lib/x86_64-linux-gnu", root.join("lib64")).unwrap();
+        std::os::unix::fs::symlink("../../../../etc/passwd", root.join("lib/escape")).unwrap();
+        let libc = resolve_in_root(&root, Path::new("/lib64/libc.so.6")).unwrap();
+        assert_eq!(libc, root.join("lib/x86_64-linux-gnu/libc.so.6"));
+        let escape = resolve_in_root(&root, Path::new("/lib/escape")).unwrap();
+        assert_eq!(escape, root.join("etc/passwd"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_libs_resolves_tree() {
+        let root = temp_root("bundle");
+        let mut loader = build_elf(None, &[(DT_SONAME, "ld-linux-x86-64.so.2")]);
+        loader.extend_from_slice(b"--preload\0--argv0\0");
+        put(&root, "/lib64/ld-linux-x86-64.so.2", &loader);
+        let libc = build_elf(
+            None,
+            &[
+                (DT_SONAME, "libc.so.6"),
+                (DT_NEEDED, "ld-linux-x86-64.so.2"),
+            ],
+        );
+        put(&root, "/lib/x86_64-linux-gnu/libc.so.6", &libc);
+        let libz = build_elf(None, &[(DT_SONAME, "libz.so.1"), (DT_NEEDED, "libc.so.6")]);
+        put(&root, "/opt/app/lib/libz.so.1", &libz);
+        let program = build_elf(
+            Some("/lib64/ld-linux-x86-64.so.2"),
+            &[
+                (DT_NEEDED, "libz.so.1"),
+                (DT_NEEDED, "libc.so.6"),
+                (DT_RUNPATH, "/opt/app/lib"),
+            ],
+        );
+        let (archive, libs) = bundle_libs(&program, Path::new("app"), "app", &root).unwrap();
+        assert_eq!(libs, ["libz.so.1", "libc.so.6"]);
+        let entries = parse_archive(&archive).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name).collect();
+        assert_eq!(
+            names,
+            ["app", "ld-linux-x86-64.so.2", "libz.so.1", "libc.so.6"]
+        );
+        assert_eq!(entries[3].data, libc);
+
+        let missing = build_elf(
+            Some("/lib64/ld-linux-x86-64.so.2"),
+            &[(DT_NEEDED, "libm.so.6")],
+        );
+        let err = bundle_libs(&missing, Path::new("app"), "app", &root).unwrap_err();
+        assert!(err.to_string().contains("libm.so.6"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_sec_bundle_libs_checks_soname() {
+        let root = temp_root("soname");
+        let mut loader = build_elf(None, &[(DT_SONAME, "ld-linux-x86-64.so.2")]);
+        loader.extend_from_slice(b"--preload\0");
+        put(&root, "/lib64/ld-linux-x86-64.so.2", &loader);
+        let program = build_elf(
+            Some("/lib64/ld-linux-x86-64.so.2"),
+            &[(DT_NEEDED, "libz.so.1")],
+        );
+        // No DT_SONAME, then one that names another library.
+        for dynamic in [vec![], vec![(DT_SONAME, "libz.so.2")]] {
+            put(&root, "/usr/lib/libz.so.1", &build_elf(None, &dynamic));
+            let err = bundle_libs(&program, Path::new("app"), "app", &root).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().contains("DT_SONAME libz.so.1"));
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_bundle_libs_rejects_static_and_musl() {
+        let root = temp_root("reject");
+        let err = bundle_libs(&build_elf(None, &[]), Path::new("a"), "a", &root).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let musl = build_elf(Some("/lib/ld-musl-x86_64.so.1"), &[]);
+        assert!(bundle_libs(&musl, Path::new("a"), "a", &root).is_err());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_loader_args() {
+        let args = loader_args(5, &[6, 7], Some("/opt/app"), vec!["-v".into()]);
+        assert_eq!(
+            args,
+            [
+                "--preload",
+                "/proc/self/fd/6:/proc/self/fd/7",
+                "--argv0",
+                "/opt/app",
+                "/proc/self/fd/5",
+                "-v"
+            ]
+        );
+        assert_eq!(loader_args(3, &[], None, vec![]), ["/proc/self/fd/3"]);
+    }
+}
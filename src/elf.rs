@@ -0,0 +1,620 @@
+use std::io;
+
+pub const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+const ELFDATA2LSB: u8 = 1;
+const ELFDATA2MSB: u8 = 2;
+const EV_CURRENT: u8 = 1;
+
+// e_type
+pub const ET_REL: u16 = 1;
+pub const ET_EXEC: u16 = 2;
+pub const ET_DYN: u16 = 3;
+pub const ET_CORE: u16 = 4;
+
+// e_machine
+pub const EM_386: u16 = 3;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+pub const EM_RISCV: u16 = 243;
+
+pub const PT_LOAD: u32 = 1;
+pub const PT_DYNAMIC: u32 = 2;
+pub const PT_INTERP: u32 = 3;
+pub const PT_NOTE: u32 = 4;
+
+const DT_NULL: u64 = 0;
+const DT_NEEDED: u64 = 1;
+const DT_STRTAB: u64 = 5;
+const DT_STRSZ: u64 = 10;
+const DT_SONAME: u64 = 14;
+const DT_RPATH: u64 = 15;
+const DT_RUNPATH: u64 = 29;
+const DT_FLAGS_1: u64 = 0x6FFF_FFFB;
+const DF_1_PIE: u64 = 0x0800_0000;
+
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Header fields, program headers and dynamic linking information of an
+/// ELF image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ElfInfo {
+    pub is_64: bool,
+    pub little_endian: bool,
+    /// `e_type`.
+    pub elf_type: u16,
+    /// `e_machine`.
+    pub machine: u16,
+    pub entry: u64,
+    pub program_headers: Vec<ProgramHeader>,
+    /// `PT_INTERP`: the dynamic loader, `None` for static images.
+    pub interp: Option<String>,
+    /// `DT_NEEDED` entries, in order.
+    pub needed: Vec<String>,
+    pub soname: Option<String>,
+    /// `DT_RUNPATH`, or `DT_RPATH` if there is no `DT_RUNPATH`.
+    pub runpath: Vec<String>,
+    /// `DF_1_PIE` is set: a position-independent executable rather than a
+    /// shared library.
+    pub pie: bool,
+    /// `NT_GNU_BUILD_ID` note from a `PT_NOTE` segment.
+    pub build_id: Option<Vec<u8>>,
+}
+
+impl ElfInfo {
+    /// Whether `other` could be loaded into the same process.
+    pub fn compatible(&self, other: &ElfInfo) -> bool {
+        (self.is_64, self.little_endian, self.machine)
+            == (other.is_64, other.little_endian, other.machine)
+    }
+
+    /// Whether the image can be started as a program: `ET_EXEC`, or
+    /// `ET_DYN` with an interpreter or the PIE flag. Shared libraries have
+    /// neither.
+    pub fn is_program(&self) -> bool {
+        match self.elf_type {
+            ET_EXEC => true,
+            ET_DYN => self.interp.is_some() || self.pie,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgramHeader {
+    /// `p_type`.
+    pub kind: u32,
+    pub flags: u32,
+    pub offset: u64,
+    pub vaddr: u64,
+    pub filesz: u64,
+    pub memsz: u64,
+    pub align: u64,
+}
+
+fn type_name(elf_type: u16) -> &'static str {
+    match elf_type {
+        ET_REL => "a relocatable object file",
+        ET_CORE => "a core dump",
+        ET_DYN => "a shared library",
+        _ => "not an executable",
+    }
+}
+
+/// Check an ELF payload against the Linux `target` it is packed for. The
+/// architecture is checked by `platform::check_target`. Returns warnings
+/// for images that may not run; an image that cannot run at all is an
+/// `InvalidInput` error.
+pub fn check_payload(info: &ElfInfo, target: &str, bundled_libs: bool) -> io::Result<Vec<String>> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    if !matches!(info.elf_type, ET_EXEC | ET_DYN) {
+        return Err(invalid(format!(
+            "ELF payload is {}, not an executable",
+            type_name(info.elf_type)
+        )));
+    }
+    let mut warnings = Vec::new();
+    if !info.is_program() {
+        warnings.push(
+            "ELF payload has no interpreter and no PIE flag; it looks like a shared library"
+                .to_string(),
+        );
+    }
+    if let (Some(interp), false) = (&info.interp, bundled_libs) {
+        if target.contains("musl") {
+            warnings.push(format!(
+                "ELF payload is dynamically linked against {}, but the target is musl; \
+                 it only runs where that loader and its libraries exist (link statically \
+                 or use --bundle-libs)",
+                interp
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+fn elf_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(&ELF_MAGIC)
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) is_64: bool,
+    pub(crate) le: bool,
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&self, offset: u64) -> io::Result<[u8; N]> {
+        let start = usize::try_from(offset).map_err(|_| elf_err("ELF offset overflow"))?;
+        self.data
+            .get(start..)
+            .and_then(|d| d.get(..N))
+            .and_then(|d| d.try_into().ok())
+            .ok_or_else(|| elf_err("ELF image truncated"))
+    }
+
+    pub(crate) fn u16(&self, offset: u64) -> io::Result<u16> {
+        let b = self.bytes(offset)?;
+        Ok(if self.le {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    }
+
+    pub(crate) fn u32(&self, offset: u64) -> io::Result<u32> {
+        let b = self.bytes(offset)?;
+        Ok(if self.le {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    pub(crate) fn u64(&self, offset: u64) -> io::Result<u64> {
+        let b = self.bytes(offset)?;
+        Ok(if self.le {
+            u64::from_le_bytes(b)
+        } else {
+            u64::from_be_bytes(b)
+        })
+    }
+
+    /// A word that is 8 bytes in ELF64 and 4 bytes in ELF32.
+    pub(crate) fn word(&self, offset: u64) -> io::Result<u64> {
+        if self.is_64 {
+            self.u64(offset)
+        } else {
+            self.u32(offset).map(u64::from)
+        }
+    }
+
+    /// NUL-terminated string at `offset`, not reaching past `end`.
+    pub(crate) fn cstr(&self, offset: u64, end: u64) -> io::Result<String> {
+        let (Ok(start), Ok(end)) = (usize::try_from(offset), usize::try_from(end)) else {
+            return Err(elf_err("ELF offset overflow"));
+        };
+        let bytes = self
+            .data
+            .get(start..end.min(self.data.len()))
+            .ok_or_else(|| elf_err("ELF string outside the image"))?;
+        let len = bytes
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| elf_err("Unterminated ELF string"))?;
+        String::from_utf8(bytes[..len].to_vec()).map_err(|_| elf_err("ELF string is not UTF-8"))
+    }
+}
+
+fn read_program_headers(r: &Reader) -> io::Result<Vec<ProgramHeader>> {
+    let (phoff, phentsize, phnum) = if r.is_64 {
+        (r.u64(32)?, r.u16(54)?, r.u16(56)?)
+    } else {
+        (u64::from(r.u32(28)?), r.u16(42)?, r.u16(44)?)
+    };
+    let min_entsize = if r.is_64 { 56 } else { 32 };
+    if phnum > 0 && phentsize < min_entsize {
+        return Err(elf_err("Invalid ELF program header size"));
+    }
+    let mut segments = Vec::with_capacity(phnum as usize);
+    for i in 0..u64::from(phnum) {
+        let at = phoff
+            .checked_add(i * u64::from(phentsize))
+            .ok_or_else(|| elf_err("ELF offset overflow"))?;
+        let segment = if r.is_64 {
+            ProgramHeader {
+                kind: r.u32(at)?,
+                flags: r.u32(at + 4)?,
+                offset: r.u64(at + 8)?,
+                vaddr: r.u64(at + 16)?,
+                filesz: r.u64(at + 32)?,
+                memsz: r.u64(at + 40)?,
+                align: r.u64(at + 48)?,
+            }
+        } else {
+            ProgramHeader {
+                kind: r.u32(at)?,
+                offset: u64::from(r.u32(at + 4)?),
+                vaddr: u64::from(r.u32(at + 8)?),
+                filesz: u64::from(r.u32(at + 16)?),
+                memsz: u64::from(r.u32(at + 20)?),
+                flags: r.u32(at + 24)?,
+                align: u64::from(r.u32(at + 28)?),
+            }
+        };
+        segments.push(segment);
+    }
+    Ok(segments)
+}
+
+/// `e_shoff`, `e_shentsize`, `e_shnum` and `e_shstrndx`.
+pub(crate) fn section_table(r: &Reader) -> io::Result<(u64, u16, u16, u16)> {
+    Ok(if r.is_64 {
+        (r.u64(40)?, r.u16(58)?, r.u16(60)?, r.u16(62)?)
+    } else {
+        (u64::from(r.u32(32)?), r.u16(46)?, r.u16(48)?, r.u16(50)?)
+    })
+}
+
+/// Offsets of `sh_offset`, `sh_size`, `sh_link`, `sh_info` and
+/// `sh_addralign` in a section header.
+pub(crate) fn section_fields(is_64: bool) -> (usize, usize, usize, usize, usize) {
+    if is_64 {
+        (24, 32, 40, 44, 48)
+    } else {
+        (16, 20, 24, 28, 32)
+    }
+}
+
+/// Write the low `len` bytes of `value` at `at` in the image's byte order.
+pub(crate) fn put(buf: &mut [u8], at: usize, value: u64, len: usize, le: bool) {
+    let bytes = if le {
+        value.to_le_bytes()[..len].to_vec()
+    } else {
+        value.to_be_bytes()[8 - len..].to_vec()
+    };
+    buf[at..at + len].copy_from_slice(&bytes);
+}
+
+/// Descriptor of the first GNU build-id note in the `PT_NOTE` segments.
+/// Malformed notes are skipped rather than failing the parse.
+fn read_build_id(r: &Reader, segments: &[ProgramHeader]) -> Option<Vec<u8>> {
+    for s in segments.iter().filter(|s| s.kind == PT_NOTE) {
+        let align = if s.align == 8 { 8 } else { 4 };
+        let end = s.offset.checked_add(s.filesz)?;
+        let mut at = s.offset;
+        while at.checked_add(12)? <= end {
+            let namesz = u64::from(r.u32(at).ok()?);
+            let descsz = u64::from(r.u32(at + 4).ok()?);
+            let kind = r.u32(at + 8).ok()?;
+            let name = at + 12;
+            let desc = name.checked_add(namesz.next_multiple_of(4))?;
+            if desc.checked_add(descsz)? > end {
+                break;
+            }
+            if kind == NT_GNU_BUILD_ID && r.cstr(name, name + namesz).ok()? == "GNU" {
+                let (start, len) = (usize::try_from(desc).ok()?, descsz as usize);
+                return r
+                    .data
+                    .get(start..start.checked_add(len)?)
+                    .map(<[u8]>::to_vec);
+            }
+            at = desc + descsz.next_multiple_of(align);
+        }
+    }
+    None
+}
+
+/// File offset of a virtual address, via the `PT_LOAD` segment holding it.
+fn vaddr_to_offset(segments: &[ProgramHeader], vaddr: u64) -> Option<u64> {
+    segments
+        .iter()
+        .filter(|s| s.kind == PT_LOAD)
+        .find(|s| vaddr >= s.vaddr && vaddr - s.vaddr < s.filesz)
+        .map(|s| s.offset + (vaddr - s.vaddr))
+}
+
+/// Parse the ELF header, the program headers, `PT_INTERP` and the
+/// dynamic section.
+pub fn parse_elf(data: &[u8]) -> io::Result<ElfInfo> {
+    if !is_elf(data) || data.len() < 52 {
+        return Err(elf_err("Not an ELF image"));
+    }
+    let is_64 = match data[4] {
+        ELFCLASS32 => false,
+        ELFCLASS64 => true,
+        _ => return Err(elf_err("Invalid ELF class")),
+    };
+    let le = match data[5] {
+        ELFDATA2LSB => true,
+        ELFDATA2MSB => false,
+        _ => return Err(elf_err("Invalid ELF byte order")),
+    };
+    if data[6] != EV_CURRENT {
+        return Err(elf_err("Unsupported ELF version"));
+    }
+    let r = Reader { data, is_64, le };
+    let elf_type = r.u16(16)?;
+    let machine = r.u16(18)?;
+    let entry = r.word(24)?;
+    let segments = read_program_headers(&r)?;
+
+    let mut interp = None;
+    if let Some(s) = segments.iter().find(|s| s.kind == PT_INTERP) {
+        interp = Some(r.cstr(s.offset, s.offset.saturating_add(s.filesz))?);
+    }
+
+    let mut info = ElfInfo {
+        is_64,
+        little_endian: le,
+        elf_type,
+        machine,
+        entry,
+        program_headers: segments,
+        interp,
+        needed: Vec::new(),
+        soname: None,
+        runpath: Vec::new(),
+        pie: false,
+        build_id: None,
+    };
+    info.build_id = read_build_id(&r, &info.program_headers);
+    let dynamic = info.program_headers.iter().find(|s| s.kind == PT_DYNAMIC);
+    let Some(dynamic) = dynamic.copied() else {
+        return Ok(info);
+    };
+    let entsize = if r.is_64 { 16 } else { 8 };
+    let mut entries = Vec::new();
+    let (mut strtab, mut strsz) = (None, 0);
+    for i in 0..dynamic.filesz / entsize {
+        let at = dynamic.offset.saturating_add(i * entsize);
+        let tag = r.word(at)?;
+        let value = r.word(at + entsize / 2)?;
+        match tag {
+            DT_NULL => break,
+            DT_STRTAB => strtab = Some(value),
+            DT_STRSZ => strsz = value,
+            DT_FLAGS_1 => info.pie = value & DF_1_PIE != 0,
+            DT_NEEDED | DT_SONAME | DT_RPATH | DT_RUNPATH => entries.push((tag, value)),
+            _ => {}
+        }
+    }
+    if entries.is_empty() {
+        return Ok(info);
+    }
+    let strtab = strtab
+        .and_then(|vaddr| vaddr_to_offset(&info.program_headers, vaddr))
+        .ok_or_else(|| elf_err("ELF dynamic string table not found"))?;
+    let strtab_end = strtab.saturating_add(strsz);
+    let mut rpath = Vec::new();
+    for (tag, value) in entries {
+        let s = r.cstr(strtab.saturating_add(value), strtab_end)?;
+        match tag {
+            DT_NEEDED => info.needed.push(s),
+            DT_SONAME => info.soname = Some(s),
+            DT_RPATH => rpath.extend(s.split(':').map(str::to_string)),
+            _ => info.runpath.extend(s.split(':').map(str::to_string)),
+        }
+    }
+    if info.runpath.is_empty() {
+        info.runpath = rpath;
+    }
+    info.runpath.retain(|p| !p.is_empty());
+    Ok(info)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A little-endian ELF64 x86_64 `ET_DYN` image with one `PT_LOAD`
+    /// covering the whole file, an optional `PT_INTERP` and a dynamic
+    /// section with string entries `dynamic` and numeric entries `values`.
+    pub(crate) fn build_elf_with(
+        interp: Option<&str>,
+        dynamic: &[(u64, &str)],
+        values: &[(u64, u64)],
+    ) -> Vec<u8> {
+        const PHDRS: u64 = 64;
+        let phnum = if interp.is_some() { 3 } else { 2 };
+        let mut strtab = vec![0u8];
+        let mut dyn_entries = Vec::new();
+        for (tag, s) in dynamic {
+            dyn_entries.push((*tag, strtab.len() as u64));
+            strtab.extend_from_slice(s.as_bytes());
+            strtab.push(0);
+        }
+        let interp_off = PHDRS + 56 * phnum;
+        let interp_bytes = interp.map(|s| format!("{}\0", s)).unwrap_or_default();
+        let strtab_off = interp_off + interp_bytes.len() as u64;
+        let dyn_off = strtab_off + strtab.len() as u64;
+        dyn_entries.extend_from_slice(values);
+        dyn_entries.push((DT_STRTAB, strtab_off));
+        dyn_entries.push((DT_STRSZ, strtab.len() as u64));
+        dyn_entries.push((DT_NULL, 0));
+        let dyn_len = 16 * dyn_entries.len() as u64;
+        let total = dyn_off + dyn_len;
+
+        let mut out = vec![0u8; 64];
+        out[..4].copy_from_slice(&ELF_MAGIC);
+        out[4] = ELFCLASS64;
+        out[5] = ELFDATA2LSB;
+        out[6] = 1;
+        out[16..18].copy_from_slice(&3u16.to_le_bytes()); // ET_DYN
+        out[18..20].copy_from_slice(&62u16.to_le_bytes()); // EM_X86_64
+        out[32..40].copy_from_slice(&PHDRS.to_le_bytes());
+        out[52..54].copy_from_slice(&64u16.to_le_bytes());
+        out[54..56].copy_from_slice(&56u16.to_le_bytes());
+        out[56..58].copy_from_slice(&(phnum as u16).to_le_bytes());
+        let mut phdr = |kind: u32, offset: u64, filesz: u64| {
+            let mut p = vec![0u8; 56];
+            p[..4].copy_from_slice(&kind.to_le_bytes());
+            p[8..16].copy_from_slice(&offset.to_le_bytes());
+            p[16..24].copy_from_slice(&offset.to_le_bytes());
+            p[32..40].copy_from_slice(&filesz.to_le_bytes());
+            p[40..48].copy_from_slice(&filesz.to_le_bytes());
+            out.extend_from_slice(&p);
+        };
+        phdr(PT_LOAD, 0, total);
+        phdr(PT_DYNAMIC, dyn_off, dyn_len);
+        if interp.is_some() {
+            phdr(PT_INTERP, interp_off, interp_bytes.len() as u64);
+        }
+        out.extend_from_slice(interp_bytes.as_bytes());
+        out.extend_from_slice(&strtab);
+        for (tag, value) in dyn_entries {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    pub(crate) fn build_elf(interp: Option<&str>, dynamic: &[(u64, &str)]) -> Vec<u8> {
+        build_elf_with(interp, dynamic, &[])
+    }
+
+    fn with_header(mut elf: Vec<u8>, elf_type: u16, machine: u16) -> Vec<u8> {
+        elf[16..18].copy_from_slice(&elf_type.to_le_bytes());
+        elf[18..20].copy_from_slice(&machine.to_le_bytes());
+        elf
+    }
+
+    const GLIBC: &str = "/lib64/ld-linux-x86-64.so.2";
+
+    #[test]
+    fn test_parse_header_fields() {
+        let info = parse_elf(&build_elf(Some(GLIBC), &[])).unwrap();
+        assert_eq!((info.elf_type, info.machine), (ET_DYN, EM_X86_64));
+        let kinds: Vec<u32> = info.program_headers.iter().map(|p| p.kind).collect();
+        assert_eq!(kinds, [PT_LOAD, PT_DYNAMIC, PT_INTERP]);
+        assert!(info.is_program() && !info.pie);
+        let static_pie = build_elf_with(None, &[], &[(DT_FLAGS_1, DF_1_PIE)]);
+        let info = parse_elf(&static_pie).unwrap();
+        assert!(info.pie && info.is_program());
+        assert!(!parse_elf(&build_elf(None, &[])).unwrap().is_program());
+    }
+
+    #[test]
+    fn test_parse_build_id() {
+        let mut elf = build_elf(None, &[]);
+        assert_eq!(parse_elf(&elf).unwrap().build_id, None);
+        let mut notes = Vec::new();
+        for (name, kind, desc) in [
+            (&b"GNU\0"[..], 1u32, &[0u8; 16][..]),
+            (b"GNU\0", 3, &[0xAB; 20]),
+        ] {
+            notes.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            notes.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+            notes.extend_from_slice(&kind.to_le_bytes());
+            notes.extend_from_slice(name);
+            notes.extend_from_slice(desc);
+        }
+        // Turn the PT_DYNAMIC header into a PT_NOTE for the appended notes.
+        let (phdr, offset) = (64 + 56, elf.len() as u64);
+        elf[phdr..phdr + 4].copy_from_slice(&PT_NOTE.to_le_bytes());
+        elf[phdr + 8..phdr + 16].copy_from_slice(&offset.to_le_bytes());
+        elf[phdr + 32..phdr + 40].copy_from_slice(&(notes.len() as u64).to_le_bytes());
+        elf[phdr + 48..phdr + 56].copy_from_slice(&4u64.to_le_bytes());
+        elf.extend_from_slice(&notes);
+        assert_eq!(parse_elf(&elf).unwrap().build_id, Some(vec![0xAB; 20]));
+
+        let len = elf.len();
+        elf.truncate(len - 4); // the descriptor no longer fits: skipped
+        assert_eq!(parse_elf(&elf).unwrap().build_id, None);
+    }
+
+    #[test]
+    fn test_check_payload() {
+        const MUSL: &str = "x86_64-unknown-linux-musl";
+        let static_pie = parse_elf(&build_elf_with(None, &[], &[(DT_FLAGS_1, DF_1_PIE)])).unwrap();
+        assert!(check_payload(&static_pie, MUSL, false).unwrap().is_empty());
+
+        let glibc = parse_elf(&build_elf(Some(GLIBC), &[(DT_NEEDED, "libc.so.6")])).unwrap();
+        assert!(check_payload(&glibc, "x86_64-unknown-linux-gnu", false)
+            .unwrap()
+            .is_empty());
+        let warnings = check_payload(&glibc, MUSL, false).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(GLIBC));
+        assert!(check_payload(&glibc, MUSL, true).unwrap().is_empty());
+
+        let library = parse_elf(&build_elf(None, &[(DT_SONAME, "libz.so.1")])).unwrap();
+        assert!(check_payload(&library, MUSL, false).unwrap()[0].contains("shared library"));
+    }
+
+    #[test]
+    fn test_check_payload_rejects() {
+        let arm = parse_elf(&with_header(build_elf(None, &[]), ET_EXEC, EM_AARCH64)).unwrap();
+        assert!(check_payload(&arm, "aarch64-unknown-linux-musl", false).is_ok());
+
+        let object = parse_elf(&with_header(build_elf(None, &[]), ET_REL, EM_X86_64)).unwrap();
+        let err = check_payload(&object, "x86_64-unknown-linux-musl", false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("relocatable"));
+        let core = parse_elf(&with_header(build_elf(None, &[]), ET_CORE, EM_X86_64)).unwrap();
+        assert!(check_payload(&core, "x86_64-unknown-linux-musl", false).is_err());
+    }
+
+    #[test]
+    fn test_parse_dynamic_executable() {
+        let elf = build_elf(
+            Some("/lib64/ld-linux-x86-64.so.2"),
+            &[
+                (DT_NEEDED, "libz.so.1"),
+                (DT_NEEDED, "libc.so.6"),
+                (DT_RPATH, "/old"),
+                (DT_RUNPATH, "$ORIGIN/../lib:/opt/lib"),
+            ],
+        );
+        let info = parse_elf(&elf).unwrap();
+        assert!(info.is_64 && info.little_endian);
+        assert_eq!(info.machine, 62);
+        assert_eq!(info.interp.as_deref(), Some("/lib64/ld-linux-x86-64.so.2"));
+        assert_eq!(info.needed, ["libz.so.1", "libc.so.6"]);
+        assert_eq!(info.runpath, ["$ORIGIN/../lib", "/opt/lib"]);
+        assert_eq!(info.soname, None);
+    }
+
+    #[test]
+    fn test_parse_shared_library() {
+        let elf = build_elf(None, &[(DT_SONAME, "libz.so.1"), (DT_RPATH, "/a:/b")]);
+        let info = parse_elf(&elf).unwrap();
+        assert_eq!(info.interp, None);
+        assert_eq!(info.soname.as_deref(), Some("libz.so.1"));
+        assert_eq!(info.runpath, ["/a", "/b"]);
+        assert!(info.compatible(&parse_elf(&build_elf(None, &[])).unwrap()));
+    }
+
+    #[test]
+    fn test_sec_parse_elf_truncated() {
+        let elf = build_elf(Some("/lib/ld.so"), &[(DT_NEEDED, "libc.so.6")]);
+        for len in [0, 10, 60, 100, elf.len() - 1] {
+            assert!(parse_elf(&elf[..len]).is_err(), "len {}", len);
+        }
+        assert!(parse_elf(b"MZ\x90\x00").is_err());
+    }
+
+    #[test]
+    fn test_sec_parse_elf_bad_ident() {
+        for (index, value) in [(4, 3), (5, 0), (6, 2)] {
+            let mut elf = build_elf(None, &[]);
+            elf[index] = value;
+            assert!(parse_elf(&elf).is_err(), "e_ident[{}] = {}", index, value);
+        }
+    }
+
+    #[test]
+    fn test_sec_parse_elf_unterminated_string() {
+        let mut elf = build_elf(Some("/lib/ld.so"), &[]);
+        let pos = elf.windows(2).position(|w| w == b"so").unwrap();
+        elf[pos + 2] = b'X'; // overwrite the interpreter's NUL
+        assert!(parse_elf(&elf).is_err());
+    }
+}
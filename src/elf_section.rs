@@ -0,0 +1,239 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::elf::{is_elf, parse_elf, put, section_fields, section_table, Reader};
+
+/// Section that holds the compressed payload, assets, metadata and
+/// trailer of an SFX written with `--elf-section`.
+pub const SECTION_NAME: &str = ".xsfx";
+
+const SHT_PROGBITS: u32 = 1;
+const SHN_LORESERVE: u16 = 0xFF00;
+
+/// Upper bound for the section header table and name table the stub
+/// reads; real stubs need a few KiB.
+const MAX_TABLE_LEN: u64 = 1024 * 1024;
+
+fn section_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Append a non-loaded `SHT_PROGBITS` section `name` holding `contents`
+/// to the ELF image `elf`. The section contents, an extended copy of the
+/// section name table and the section header table are written after the
+/// image; a section header table at the very end of `elf` is replaced,
+/// everything else keeps its offset.
+pub fn add_section(elf: &[u8], name: &str, contents: &[u8]) -> io::Result<Vec<u8>> {
+    let info = parse_elf(elf)?;
+    let (is_64, le) = (info.is_64, info.little_endian);
+    let r = Reader {
+        data: elf,
+        is_64,
+        le,
+    };
+    let (shoff, shentsize, shnum, shstrndx) = section_table(&r)?;
+    let entsize: u16 = if is_64 { 64 } else { 40 };
+    if shoff == 0 || shnum == 0 || shstrndx == 0 || shstrndx >= shnum || shentsize != entsize {
+        return Err(section_err(
+            "ELF image without a usable section header table",
+        ));
+    }
+    if shnum >= SHN_LORESERVE - 1 {
+        return Err(section_err("Too many ELF sections"));
+    }
+    let table_end = shoff
+        .checked_add(u64::from(shnum) * u64::from(entsize))
+        .filter(|&end| end <= elf.len() as u64)
+        .ok_or_else(|| section_err("ELF section header table outside the image"))?;
+    let mut table = elf[shoff as usize..table_end as usize].to_vec();
+    let (offset_at, size_at, _, _, align_at) = section_fields(is_64);
+    let names_header = usize::from(shstrndx) * usize::from(entsize);
+    let names_offset = r.word(shoff + (names_header + offset_at) as u64)?;
+    let names_size = r.word(shoff + (names_header + size_at) as u64)?;
+    let mut names = names_offset
+        .checked_add(names_size)
+        .and_then(|end| elf.get(names_offset as usize..usize::try_from(end).ok()?))
+        .ok_or_else(|| section_err("ELF section name table outside the image"))?
+        .to_vec();
+
+    let mut out = if table_end == elf.len() as u64 {
+        elf[..shoff as usize].to_vec()
+    } else {
+        elf.to_vec()
+    };
+    let word = if is_64 { 8 } else { 4 };
+    let mut header = vec![0u8; usize::from(entsize)];
+    put(&mut header, 0, names.len() as u64, 4, le);
+    put(&mut header, 4, u64::from(SHT_PROGBITS), 4, le);
+    put(&mut header, offset_at, out.len() as u64, word, le);
+    put(&mut header, size_at, contents.len() as u64, word, le);
+    put(&mut header, align_at, 1, word, le);
+    out.extend_from_slice(contents);
+    names.extend_from_slice(name.as_bytes());
+    names.push(0);
+    put(
+        &mut table,
+        names_header + offset_at,
+        out.len() as u64,
+        word,
+        le,
+    );
+    put(
+        &mut table,
+        names_header + size_at,
+        names.len() as u64,
+        word,
+        le,
+    );
+    out.extend_from_slice(&names);
+    out.resize(out.len().next_multiple_of(word), 0);
+
+    let new_shoff = out.len() as u64;
+    out.extend_from_slice(&table);
+    out.extend_from_slice(&header);
+    let (shoff_at, shnum_at) = if is_64 { (40, 60) } else { (32, 48) };
+    put(&mut out, shoff_at, new_shoff, word, le);
+    put(&mut out, shnum_at, u64::from(shnum + 1), 2, le);
+    Ok(out)
+}
+
+fn read_at<R: Read + Seek>(r: &mut R, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    if len > MAX_TABLE_LEN {
+        return Err(section_err("ELF table too large"));
+    }
+    r.seek(SeekFrom::Start(offset))?;
+    let mut buf = Vec::with_capacity(len as usize);
+    r.take(len).read_to_end(&mut buf)?;
+    if buf.len() as u64 != len {
+        return Err(section_err("ELF image truncated"));
+    }
+    Ok(buf)
+}
+
+/// File offset and size of the section `name` in the ELF image read by
+/// `r`. `None` for other files and for images without that section.
+pub fn find_section<R: Read + Seek>(r: &mut R, name: &str) -> io::Result<Option<(u64, u64)>> {
+    let mut ehdr = Vec::with_capacity(64);
+    r.seek(SeekFrom::Start(0))?;
+    r.take(64).read_to_end(&mut ehdr)?;
+    if !is_elf(&ehdr) || ehdr.len() < 52 || !matches!(ehdr[4], 1 | 2) {
+        return Ok(None);
+    }
+    let (is_64, le) = (ehdr[4] == 2, ehdr[5] == 1);
+    let (shoff, shentsize, shnum, shstrndx) = section_table(&Reader {
+        data: &ehdr,
+        is_64,
+        le,
+    })?;
+    if shoff == 0 || shnum == 0 || shstrndx >= shnum {
+        return Ok(None);
+    }
+    if shentsize < if is_64 { 64 } else { 40 } {
+        return Err(section_err("Invalid ELF section header table"));
+    }
+    let table = read_at(r, shoff, u64::from(shnum) * u64::from(shentsize))?;
+    let t = Reader {
+        data: &table,
+        is_64,
+        le,
+    };
+    let (offset_at, size_at, ..) = section_fields(is_64);
+    let field = |index: u16, at: usize| t.word(u64::from(index) * u64::from(shentsize) + at as u64);
+    let names = read_at(r, field(shstrndx, offset_at)?, field(shstrndx, size_at)?)?;
+    let n = Reader {
+        data: &names,
+        is_64,
+        le,
+    };
+    for index in 1..shnum {
+        let name_offset = u64::from(t.u32(u64::from(index) * u64::from(shentsize))?);
+        if n.cstr(name_offset, names.len() as u64).ok().as_deref() == Some(name) {
+            return Ok(Some((field(index, offset_at)?, field(index, size_at)?)));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::elf::tests::build_elf;
+    use std::io::Cursor;
+
+    /// `build_elf(None, &[])` with a null section, a `.shstrtab` and the
+    /// section header table at the end, like a linker writes it.
+    pub(crate) fn with_section_table(mut elf: Vec<u8>) -> Vec<u8> {
+        let names = b"\0.shstrtab\0";
+        let names_offset = elf.len() as u64;
+        elf.extend_from_slice(names);
+        elf.resize(elf.len().next_multiple_of(8), 0);
+        let shoff = elf.len() as u64;
+        elf.extend_from_slice(&[0u8; 64]);
+        let mut header = [0u8; 64];
+        header[..4].copy_from_slice(&1u32.to_le_bytes());
+        header[4..8].copy_from_slice(&3u32.to_le_bytes()); // SHT_STRTAB
+        header[24..32].copy_from_slice(&names_offset.to_le_bytes());
+        header[32..40].copy_from_slice(&(names.len() as u64).to_le_bytes());
+        elf.extend_from_slice(&header);
+        elf[40..48].copy_from_slice(&shoff.to_le_bytes());
+        elf[58..60].copy_from_slice(&64u16.to_le_bytes());
+        elf[60..62].copy_from_slice(&2u16.to_le_bytes());
+        elf[62..64].copy_from_slice(&1u16.to_le_bytes());
+        elf
+    }
+
+    #[test]
+    fn test_add_and_find_section() {
+        let stub = with_section_table(build_elf(None, &[]));
+        let image_len = build_elf(None, &[]).len();
+        let out = add_section(&stub, SECTION_NAME, b"payload bytes").unwrap();
+        assert_eq!(out[64..image_len], stub[64..image_len]);
+        let (offset, size) = find_section(&mut Cursor::new(&out), SECTION_NAME)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&out[offset as usize..][..size as usize], b"payload bytes");
+        let info = parse_elf(&out).unwrap();
+        assert_eq!(
+            info.program_headers,
+            parse_elf(&stub).unwrap().program_headers
+        );
+        assert!(find_section(&mut Cursor::new(&out), ".shstrtab")
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            find_section(&mut Cursor::new(&stub), SECTION_NAME).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_section_other_files() {
+        for data in [&b"#!/bin/sh\n"[..], b"", &build_elf(None, &[])] {
+            assert_eq!(
+                find_section(&mut Cursor::new(data), SECTION_NAME).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn test_sec_add_section_needs_section_table() {
+        assert!(add_section(&build_elf(None, &[]), SECTION_NAME, b"x").is_err());
+        assert!(add_section(b"MZ", SECTION_NAME, b"x").is_err());
+    }
+
+    #[test]
+    fn test_sec_find_section_truncated() {
+        let out = add_section(
+            &with_section_table(build_elf(None, &[])),
+            SECTION_NAME,
+            b"x",
+        )
+        .unwrap();
+        let truncated = &out[..out.len() - 10];
+        assert!(find_section(&mut Cursor::new(truncated), SECTION_NAME).is_err());
+        let mut huge = out.clone();
+        huge[60..62].copy_from_slice(&u16::MAX.to_le_bytes()); // e_shnum
+        huge[58..60].copy_from_slice(&u16::MAX.to_le_bytes()); // e_shentsize
+        assert!(find_section(&mut Cursor::new(&huge), SECTION_NAME).is_err());
+    }
+}
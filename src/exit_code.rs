@@ -0,0 +1,330 @@
+use std::io;
+
+use crate::diag::{Phase, StubError};
+
+/// First exit code reserved for stub failures.
+pub const STUB_EXIT_MIN: i32 = 240;
+/// Last exit code reserved for stub failures.
+pub const STUB_EXIT_MAX: i32 = 254;
+
+/// Exit codes used by the stub when it fails before the payload runs.
+///
+/// The 240–254 range sits above the shell's 126/127 and the 128+N signal
+/// codes, and outside the BSD `sysexits.h` range, so supervisors can tell a
+/// stub failure from a payload exit status. A payload that itself exits
+/// with a code in this range is indistinguishable; this is documented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StubExit {
+    /// Unclassified stub failure (e.g. cannot open own executable).
+    Internal = 240,
+    /// Trailer or metadata block missing, truncated or inconsistent.
+    CorruptTrailer = 241,
+    /// Compressed payload could not be decoded.
+    Decompress = 242,
+    /// Decompressed payload does not match its recorded digest.
+    Integrity = 243,
+    /// The platform cannot execute this payload (memfd/execveat or loader).
+    ExecUnsupported = 244,
+    /// Not enough memory to hold or map the payload.
+    OutOfMemory = 245,
+    /// Invalid stub-level arguments: a reserved `--xsfx-*` flag, installer
+    /// arguments or an unknown multi-call tool.
+    Usage = 246,
+    /// Encrypted payload: no key available, wrong key or modified SFX.
+    Decrypt = 247,
+    /// Current time is outside the recorded not-before / not-after window.
+    Validity = 248,
+    /// A required sandbox feature could not be applied (strict mode).
+    Sandbox = 249,
+    /// Supervisor mode: the pre-start hook failed.
+    Hook = 250,
+    /// Namespaces could not be created or set up (`--isolate`).
+    Isolate = 251,
+    /// Installer mode: the tree could not be extracted or the post-extract
+    /// command could not be started.
+    Install = 252,
+}
+
+const ALL: [StubExit; 13] = [
+    StubExit::Internal,
+    StubExit::CorruptTrailer,
+    StubExit::Decompress,
+    StubExit::Integrity,
+    StubExit::ExecUnsupported,
+    StubExit::OutOfMemory,
+    StubExit::Usage,
+    StubExit::Decrypt,
+    StubExit::Validity,
+    StubExit::Sandbox,
+    StubExit::Hook,
+    StubExit::Isolate,
+    StubExit::Install,
+];
+
+impl StubExit {
+    pub fn code(self) -> i32 {
+        self as i32
+    }
+
+    /// Decode a process exit status; `None` if it is not a stub code.
+    pub fn from_code(code: i32) -> Option<Self> {
+        ALL.iter().copied().find(|e| e.code() == code)
+    }
+
+    /// Whether the underlying message is meant for the end user and is
+    /// printed even without `XSFX_DEBUG`.
+    pub fn is_user_facing(self) -> bool {
+        matches!(
+            self,
+            StubExit::Usage
+                | StubExit::Validity
+                | StubExit::Sandbox
+                | StubExit::Hook
+                | StubExit::Isolate
+                | StubExit::Install
+        )
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            StubExit::Internal => "internal stub error",
+            StubExit::CorruptTrailer => "corrupt SFX trailer or metadata",
+            StubExit::Decompress => "payload decompression failed",
+            StubExit::Integrity => "payload integrity check failed",
+            StubExit::ExecUnsupported => "payload execution not supported",
+            StubExit::OutOfMemory => "out of memory",
+            StubExit::Usage => "invalid stub usage",
+            StubExit::Decrypt => "payload decryption failed",
+            StubExit::Validity => "outside validity window",
+            StubExit::Sandbox => "sandbox setup failed",
+            StubExit::Hook => "pre-start hook failed",
+            StubExit::Isolate => "namespace setup failed",
+            StubExit::Install => "installation failed",
+        }
+    }
+
+    /// Map a stub error to its exit class.
+    pub fn classify(err: &StubError) -> Self {
+        if is_out_of_memory(&err.source) {
+            return StubExit::OutOfMemory;
+        }
+        match err.phase {
+            Phase::Args => StubExit::Usage,
+            Phase::Open | Phase::Cache | Phase::Setup | Phase::Supervise | Phase::Extract => {
+                StubExit::Internal
+            }
+            Phase::Trailer => StubExit::CorruptTrailer,
+            Phase::Validity => StubExit::Validity,
+            Phase::Sandbox => StubExit::Sandbox,
+            Phase::Hook => StubExit::Hook,
+            Phase::Isolate => StubExit::Isolate,
+            Phase::Install => StubExit::Install,
+            Phase::Decrypt => StubExit::Decrypt,
+            Phase::Decompress => StubExit::Decompress,
+            Phase::Verify => StubExit::Integrity,
+            Phase::Memfd | Phase::Exec => {
+                if is_exec_unsupported(&err.source) {
+                    StubExit::ExecUnsupported
+                } else {
+                    StubExit::Internal
+                }
+            }
+        }
+    }
+}
+
+fn is_out_of_memory(err: &io::Error) -> bool {
+    if err.kind() == io::ErrorKind::OutOfMemory {
+        return true;
+    }
+    // Raw errno values only mean something on Unix; Windows error codes
+    // are a different numbering.
+    #[cfg(unix)]
+    if matches!(err.raw_os_error(), Some(libc::ENOMEM) | Some(libc::ENOSPC)) {
+        return true;
+    }
+    false
+}
+
+/// Whether the platform cannot run the payload at all. Permission errors
+/// (a `noexec` mount, a seccomp or LSM denial) are not: the system could
+/// run it but refused to, so they stay `Internal`.
+fn is_exec_unsupported(err: &io::Error) -> bool {
+    match err.kind() {
+        io::ErrorKind::Unsupported | io::ErrorKind::InvalidData => return true,
+        io::ErrorKind::PermissionDenied => return false,
+        _ => {}
+    }
+    #[cfg(unix)]
+    if matches!(
+        err.raw_os_error(),
+        Some(libc::ENOSYS) | Some(libc::EINVAL) | Some(libc::ENOEXEC)
+    ) {
+        return true;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err(phase: Phase, e: io::Error) -> StubError {
+        StubError::new(phase, e)
+    }
+
+    #[test]
+    fn test_codes_within_reserved_range() {
+        for e in ALL {
+            assert!((STUB_EXIT_MIN..=STUB_EXIT_MAX).contains(&e.code()));
+        }
+    }
+
+    #[test]
+    fn test_codes_unique_and_roundtrip() {
+        for e in ALL {
+            assert_eq!(StubExit::from_code(e.code()), Some(e));
+            assert!(!e.description().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_from_code_outside_range() {
+        assert_eq!(StubExit::from_code(0), None);
+        assert_eq!(StubExit::from_code(1), None);
+        assert_eq!(StubExit::from_code(STUB_EXIT_MAX), None);
+    }
+
+    #[test]
+    fn test_classify_by_phase() {
+        let other = || io::Error::other("x");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Open, other())),
+            StubExit::Internal
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Trailer, other())),
+            StubExit::CorruptTrailer
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Decompress, other())),
+            StubExit::Decompress
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Verify, other())),
+            StubExit::Integrity
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Exec, other())),
+            StubExit::Internal
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Args, other())),
+            StubExit::Usage
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Decrypt, other())),
+            StubExit::Decrypt
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Validity, other())),
+            StubExit::Validity
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Sandbox, other())),
+            StubExit::Sandbox
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Hook, other())),
+            StubExit::Hook
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Isolate, other())),
+            StubExit::Isolate
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Install, other())),
+            StubExit::Install
+        );
+        assert_eq!(
+            StubExit::classify(&err(Phase::Supervise, other())),
+            StubExit::Internal
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_out_of_memory() {
+        let kind = io::Error::new(io::ErrorKind::OutOfMemory, "oom");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Decompress, kind)),
+            StubExit::OutOfMemory
+        );
+        let errno = io::Error::from_raw_os_error(libc::ENOMEM);
+        assert_eq!(
+            StubExit::classify(&err(Phase::Exec, errno)),
+            StubExit::OutOfMemory
+        );
+        let nospc = io::Error::from_raw_os_error(libc::ENOSPC);
+        assert_eq!(
+            StubExit::classify(&err(Phase::Memfd, nospc)),
+            StubExit::OutOfMemory
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_exec_unsupported() {
+        for code in [libc::ENOSYS, libc::ENOEXEC, libc::EINVAL] {
+            let e = io::Error::from_raw_os_error(code);
+            assert_eq!(
+                StubExit::classify(&err(Phase::Exec, e)),
+                StubExit::ExecUnsupported
+            );
+        }
+        let loader = io::Error::new(io::ErrorKind::InvalidData, "Invalid Mach-O magic");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Exec, loader)),
+            StubExit::ExecUnsupported
+        );
+        let memfd = io::Error::from_raw_os_error(libc::ENOSYS);
+        assert_eq!(
+            StubExit::classify(&err(Phase::Memfd, memfd)),
+            StubExit::ExecUnsupported
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_classify_exec_permission_denied() {
+        // noexec mount, seccomp or LSM denial: not a missing platform feature.
+        for code in [libc::EACCES, libc::EPERM] {
+            let e = io::Error::from_raw_os_error(code);
+            assert_eq!(StubExit::classify(&err(Phase::Exec, e)), StubExit::Internal);
+        }
+    }
+
+    #[test]
+    fn test_classify_by_error_kind() {
+        let unsupported = io::Error::new(io::ErrorKind::Unsupported, "no memfd");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Memfd, unsupported)),
+            StubExit::ExecUnsupported
+        );
+        let denied = io::Error::new(io::ErrorKind::PermissionDenied, "denied");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Exec, denied)),
+            StubExit::Internal
+        );
+    }
+
+    #[test]
+    fn test_sec_uc006_trailer_oom_still_oom() {
+        // A huge bogus allocation in any phase is reported as out of memory.
+        let e = io::Error::new(io::ErrorKind::OutOfMemory, "oom");
+        assert_eq!(
+            StubExit::classify(&err(Phase::Trailer, e)),
+            StubExit::OutOfMemory
+        );
+    }
+}
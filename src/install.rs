@@ -0,0 +1,673 @@
+use std::collections::HashSet;
+#[cfg(unix)]
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::assets::validate_name;
+use crate::cursor::ByteReader;
+
+const FLAG_DEFAULT_DIR: u8 = 0x01;
+const FLAG_POST_EXTRACT: u8 = 0x02;
+
+const KIND_DIR: u8 = 0;
+const KIND_FILE: u8 = 1;
+const KIND_SYMLINK: u8 = 2;
+
+/// Bytes of a tree entry header without the name.
+const ENTRY_HEADER_SIZE: usize = 1 + 2 + 4 + 8;
+
+/// Absolute path of the extracted tree, passed to the post-extract command.
+pub const ENV_TARGET_DIR: &str = "XSFX_TARGET_DIR";
+
+fn install_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Installer mode: the payload is a directory tree that the stub extracts
+/// instead of executing.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InstallSpec {
+    /// Used when no `--target-dir` is given; a leading `~/` is the home
+    /// directory.
+    pub default_dir: Option<String>,
+    /// Shell command run in the extracted tree.
+    pub post_extract: Option<String>,
+}
+
+impl InstallSpec {
+    /// `[flags u8]` followed by `[len u16][text]` for each field that is set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.default_dir.is_some() {
+            flags |= FLAG_DEFAULT_DIR;
+        }
+        if self.post_extract.is_some() {
+            flags |= FLAG_POST_EXTRACT;
+        }
+        let mut out = vec![flags];
+        for s in [&self.default_dir, &self.post_extract]
+            .into_iter()
+            .flatten()
+        {
+            out.extend_from_slice(&(s.len() as u16).to_le_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut r = ByteReader::new(data, "installer data");
+        let flags = r.u8()?;
+        if flags & !(FLAG_DEFAULT_DIR | FLAG_POST_EXTRACT) != 0 {
+            return Err(install_err("Unknown installer flags"));
+        }
+        let mut spec = Self::default();
+        if flags & FLAG_DEFAULT_DIR != 0 {
+            spec.default_dir = Some(read_text(&mut r)?);
+        }
+        if flags & FLAG_POST_EXTRACT != 0 {
+            spec.post_extract = Some(read_text(&mut r)?);
+        }
+        if !r.is_empty() {
+            return Err(install_err("Trailing bytes in installer record"));
+        }
+        Ok(spec)
+    }
+
+    /// Directory to extract to: `--target-dir` if given, else the baked
+    /// default.
+    pub fn target_dir(&self, arg: Option<PathBuf>) -> io::Result<PathBuf> {
+        if let Some(dir) = arg {
+            return Ok(dir);
+        }
+        let Some(dir) = &self.default_dir else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No target directory: pass --target-dir <dir>",
+            ));
+        };
+        match dir.strip_prefix("~/") {
+            Some(rest) => {
+                let home = std::env::var_os("HOME")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?;
+                Ok(Path::new(&home).join(rest))
+            }
+            None => Ok(PathBuf::from(dir)),
+        }
+    }
+}
+
+/// `[len u16][utf8]`, non-empty and without NUL.
+fn read_text(r: &mut ByteReader<'_>) -> io::Result<String> {
+    let s = r.str16()?;
+    if s.is_empty() || s.contains('\0') {
+        return Err(install_err("Invalid installer text"));
+    }
+    Ok(s.to_string())
+}
+
+/// Parse the installer's own arguments: only `--target-dir <dir>` (or
+/// `--target-dir=<dir>`) is accepted.
+pub fn parse_install_args(args: &[String]) -> Result<Option<PathBuf>, String> {
+    let mut target = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let value = match arg.strip_prefix("--target-dir") {
+            Some("") => iter.next().cloned(),
+            Some(v) if v.starts_with('=') => Some(v[1..].to_string()),
+            _ => return Err(format!("Unknown installer argument '{}'", arg)),
+        };
+        match value {
+            Some(v) if !v.is_empty() && target.is_none() => target = Some(PathBuf::from(v)),
+            _ => return Err("Usage: --target-dir <dir>".to_string()),
+        }
+    }
+    Ok(target)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    Dir,
+    File,
+    Symlink,
+}
+
+/// One entry of a packed tree. `data` is the file content or the symlink
+/// target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    /// Permission bits (`0o777` at most); unused for symlinks.
+    pub mode: u32,
+    pub data: Vec<u8>,
+}
+
+/// A tree entry inside a decoded archive.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TreeEntryRef<'a> {
+    pub name: &'a str,
+    pub kind: EntryKind,
+    pub mode: u32,
+    pub data: &'a [u8],
+}
+
+/// Whether a symlink at `name` pointing to `target` resolves outside the
+/// tree: absolute, too many `..`, or through another symlink of the tree
+/// (one of `links`), whose own target would decide where `..` leads.
+pub fn symlink_escapes(name: &str, target: &Path, links: &HashSet<&str>) -> bool {
+    let mut path: Vec<String> = name.split('/').map(str::to_string).collect();
+    path.pop();
+    for c in target.components() {
+        if !path.is_empty() && links.contains(path.join("/").as_str()) {
+            return true;
+        }
+        match c {
+            Component::Normal(part) => path.push(part.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if path.pop().is_none() {
+                    return true;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => return true,
+        }
+    }
+    false
+}
+
+/// Walk `root` without following symlinks. Entries come in name order,
+/// each directory before its contents; `root` itself is not included.
+/// Symlinks that point outside the tree are rejected.
+#[cfg(unix)]
+pub fn collect_tree(root: &Path) -> io::Result<Vec<TreeEntry>> {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    let mut out = Vec::new();
+    walk(root, None, &mut out)?;
+    let links: HashSet<&str> = out
+        .iter()
+        .filter(|e| e.kind == EntryKind::Symlink)
+        .map(|e| e.name.as_str())
+        .collect();
+    for e in out.iter().filter(|e| e.kind == EntryKind::Symlink) {
+        if symlink_escapes(&e.name, Path::new(OsStr::from_bytes(&e.data)), &links) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Symlink points outside the tree: {}", e.name),
+            ));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(not(unix))]
+pub fn collect_tree(_root: &Path) -> io::Result<Vec<TreeEntry>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Packing a directory tree requires a Unix host",
+    ))
+}
+
+#[cfg(unix)]
+fn walk(dir: &Path, prefix: Option<&str>, out: &mut Vec<TreeEntry>) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let file_name = entry.file_name();
+        let file_name = file_name
+            .to_str()
+            .ok_or_else(|| install_err("File name is not valid UTF-8"))?;
+        let name = match prefix {
+            Some(p) => format!("{}/{}", p, file_name),
+            None => file_name.to_string(),
+        };
+        validate_name(&name)?;
+        let meta = fs::symlink_metadata(entry.path())?;
+        let mode = meta.permissions().mode() & 0o777;
+        if meta.file_type().is_symlink() {
+            let target = fs::read_link(entry.path())?;
+            out.push(TreeEntry {
+                name,
+                kind: EntryKind::Symlink,
+                mode: 0,
+                data: target.as_os_str().as_bytes().to_vec(),
+            });
+        } else if meta.is_dir() {
+            out.push(TreeEntry {
+                name: name.clone(),
+                kind: EntryKind::Dir,
+                mode,
+                data: Vec::new(),
+            });
+            walk(&entry.path(), Some(&name), out)?;
+        } else if meta.is_file() {
+            out.push(TreeEntry {
+                data: fs::read(entry.path())?,
+                name,
+                kind: EntryKind::File,
+                mode,
+            });
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unsupported file type: {}", name),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Encode as `[count u32]`, one `[kind u8][name_len u16][name][mode u32]
+/// [len u64]` header per entry, then all file contents and symlink targets.
+pub fn encode_tree(entries: &[TreeEntry]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+    for e in entries {
+        out.push(match e.kind {
+            EntryKind::Dir => KIND_DIR,
+            EntryKind::File => KIND_FILE,
+            EntryKind::Symlink => KIND_SYMLINK,
+        });
+        out.extend_from_slice(&(e.name.len() as u16).to_le_bytes());
+        out.extend_from_slice(e.name.as_bytes());
+        out.extend_from_slice(&e.mode.to_le_bytes());
+        out.extend_from_slice(&(e.data.len() as u64).to_le_bytes());
+    }
+    for e in entries {
+        out.extend_from_slice(&e.data);
+    }
+    out
+}
+
+/// Decode and validate a tree archive. Every entry's parent must be an
+/// earlier directory entry, so nothing is ever written through a symlink,
+/// and no symlink may point outside the tree, directly or through another
+/// symlink.
+pub fn parse_tree(data: &[u8]) -> io::Result<Vec<TreeEntryRef<'_>>> {
+    let mut r = ByteReader::new(data, "installer data");
+    let count = r.u32()? as usize;
+    if count > r.remaining() / ENTRY_HEADER_SIZE {
+        return Err(install_err("Truncated installer data"));
+    }
+    let mut headers = Vec::with_capacity(count);
+    let mut dirs = HashSet::new();
+    let mut names = HashSet::new();
+    for _ in 0..count {
+        let kind = match r.u8()? {
+            KIND_DIR => EntryKind::Dir,
+            KIND_FILE => EntryKind::File,
+            KIND_SYMLINK => EntryKind::Symlink,
+            _ => return Err(install_err("Unknown tree entry kind")),
+        };
+        let name = r.str16()?;
+        validate_name(name)?;
+        if let Some((parent, _)) = name.rsplit_once('/') {
+            if !dirs.contains(parent) {
+                return Err(install_err("Tree entry outside a packed directory"));
+            }
+        }
+        if !names.insert(name) {
+            return Err(install_err("Duplicate tree entry"));
+        }
+        if kind == EntryKind::Dir {
+            dirs.insert(name);
+        }
+        let mode = r.u32()?;
+        if mode & !0o777 != 0 {
+            return Err(install_err("Invalid tree entry mode"));
+        }
+        let len = usize::try_from(r.u64()?).map_err(|_| install_err("Truncated installer data"))?;
+        if kind == EntryKind::Dir && len != 0 {
+            return Err(install_err("Directory entry with contents"));
+        }
+        headers.push((name, kind, mode, len));
+    }
+    let links: HashSet<&str> = headers
+        .iter()
+        .filter(|(_, kind, ..)| *kind == EntryKind::Symlink)
+        .map(|(name, ..)| *name)
+        .collect();
+    let mut entries = Vec::with_capacity(count);
+    for (name, kind, mode, len) in headers {
+        let data = r.take(len)?;
+        if kind == EntryKind::Symlink {
+            let target = std::str::from_utf8(data)
+                .map_err(|_| install_err("Symlink target is not valid UTF-8"))?;
+            if target.is_empty()
+                || target.contains('\0')
+                || symlink_escapes(name, Path::new(target), &links)
+            {
+                return Err(install_err("Symlink points outside the tree"));
+            }
+        }
+        entries.push(TreeEntryRef {
+            name,
+            kind,
+            mode,
+            data,
+        });
+    }
+    if !r.is_empty() {
+        return Err(install_err("Trailing data in installer archive"));
+    }
+    Ok(entries)
+}
+
+/// Extract a tree archive to `target` atomically: everything is written
+/// to a fresh staging directory next to it, which is then renamed into
+/// place. `target` must not exist or be an empty directory. Returns the
+/// number of entries.
+#[cfg(unix)]
+pub fn install_tree(archive: &[u8], target: &Path) -> io::Result<usize> {
+    use std::os::unix::fs::DirBuilderExt;
+    let entries = parse_tree(archive)?;
+    let target_name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid target directory"))?;
+    if fs::symlink_metadata(target).is_ok()
+        && fs::read_dir(target).map_or(true, |mut d| d.next().is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "Target exists and is not an empty directory: {}",
+                target.display()
+            ),
+        ));
+    }
+    let parent = match target.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p,
+        _ => Path::new("."),
+    };
+    fs::create_dir_all(parent)?;
+    let staging = parent.join(format!(
+        ".{}.xsfx-{}",
+        target_name.to_string_lossy(),
+        std::process::id()
+    ));
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let result = extract_into(&entries, &staging).and_then(|()| {
+        set_mode(&staging, 0o755)?;
+        if target.is_dir() {
+            fs::remove_dir(target)?;
+        }
+        fs::rename(&staging, target)
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    Ok(entries.len())
+}
+
+#[cfg(not(unix))]
+pub fn install_tree(_archive: &[u8], _target: &Path) -> io::Result<usize> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Installer mode is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn extract_into(entries: &[TreeEntryRef<'_>], root: &Path) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+    for e in entries {
+        let path = root.join(e.name);
+        match e.kind {
+            EntryKind::Dir => fs::DirBuilder::new().mode(0o700).create(&path)?,
+            EntryKind::File => {
+                let mut f = fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&path)?;
+                f.write_all(e.data)?;
+                f.sync_all()?;
+                set_mode(&path, e.mode)?;
+            }
+            EntryKind::Symlink => {
+                let target = std::str::from_utf8(e.data).map_err(|_| install_err("Bad symlink"))?;
+                std::os::unix::fs::symlink(target, &path)?;
+            }
+        }
+    }
+    // Directories last, so read-only ones do not block their contents.
+    for e in entries.iter().rev().filter(|e| e.kind == EntryKind::Dir) {
+        set_mode(&root.join(e.name), e.mode)?;
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, kind: EntryKind, data: &[u8]) -> TreeEntry {
+        TreeEntry {
+            name: name.into(),
+            kind,
+            mode: if kind == EntryKind::Dir { 0o755 } else { 0o644 },
+            data: data.to_vec(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("xsfx-install-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_install_spec_roundtrip() {
+        let spec = InstallSpec {
+            default_dir: Some("~/apps/tool".into()),
+            post_extract: Some("./setup.sh".into()),
+        };
+        assert_eq!(InstallSpec::from_bytes(&spec.to_bytes()).unwrap(), spec);
+        let empty = InstallSpec::default();
+        assert_eq!(InstallSpec::from_bytes(&empty.to_bytes()).unwrap(), empty);
+        assert!(InstallSpec::from_bytes(&[0x04]).is_err());
+        assert!(InstallSpec::from_bytes(&[0x01, 5, 0, b'a']).is_err());
+    }
+
+    #[test]
+    fn test_target_dir() {
+        let spec = InstallSpec::default();
+        assert!(spec.target_dir(None).is_err());
+        let arg = Some(PathBuf::from("/opt/x"));
+        assert_eq!(spec.target_dir(arg).unwrap(), PathBuf::from("/opt/x"));
+        let spec = InstallSpec {
+            default_dir: Some("/opt/tool".into()),
+            ..InstallSpec::default()
+        };
+        assert_eq!(spec.target_dir(None).unwrap(), PathBuf::from("/opt/tool"));
+    }
+
+    #[test]
+    fn test_parse_install_args() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(parse_install_args(&[]), Ok(None));
+        assert_eq!(
+            parse_install_args(&args(&["--target-dir", "/opt/x"])),
+            Ok(Some(PathBuf::from("/opt/x")))
+        );
+        assert_eq!(
+            parse_install_args(&args(&["--target-dir=rel"])),
+            Ok(Some(PathBuf::from("rel")))
+        );
+        assert!(parse_install_args(&args(&["--target-dir"])).is_err());
+        assert!(parse_install_args(&args(&["--target-dir="])).is_err());
+        assert!(parse_install_args(&args(&["--target-directory=x"])).is_err());
+        assert!(parse_install_args(&args(&["--target-dir=a", "--target-dir=b"])).is_err());
+        assert!(parse_install_args(&args(&["run"])).is_err());
+    }
+
+    #[test]
+    fn test_symlink_escapes() {
+        let none = HashSet::new();
+        assert!(!symlink_escapes("a/link", Path::new("../b"), &none));
+        assert!(!symlink_escapes("link", Path::new("a/b/../c"), &none));
+        assert!(symlink_escapes("link", Path::new("../x"), &none));
+        assert!(symlink_escapes("a/link", Path::new("../../x"), &none));
+        assert!(symlink_escapes("a/link", Path::new("/etc/passwd"), &none));
+        let links = HashSet::from(["t", "a/u"]);
+        assert!(!symlink_escapes("s", Path::new("t"), &links));
+        assert!(!symlink_escapes("a/s", Path::new("u"), &links));
+        assert!(symlink_escapes("s", Path::new("t/.."), &links));
+        assert!(symlink_escapes("s", Path::new("a/u/x"), &links));
+    }
+
+    #[test]
+    fn test_sec_uc017_chained_symlink_escape() {
+        // "s" -> "t/.." looks like the tree root, but "t" -> "." makes it
+        // the parent of the install target; the order of entries must not
+        // matter.
+        let chained = [
+            entry("s", EntryKind::Symlink, b"t/.."),
+            entry("t", EntryKind::Symlink, b"."),
+        ];
+        assert!(parse_tree(&encode_tree(&chained)).is_err());
+        let reversed = [
+            entry("t", EntryKind::Symlink, b"."),
+            entry("s", EntryKind::Symlink, b"t/.."),
+        ];
+        assert!(parse_tree(&encode_tree(&reversed)).is_err());
+        let link_to_link = [
+            entry("t", EntryKind::Symlink, b"."),
+            entry("s", EntryKind::Symlink, b"t"),
+        ];
+        assert!(parse_tree(&encode_tree(&link_to_link)).is_ok());
+    }
+
+    #[test]
+    fn test_tree_roundtrip() {
+        let tree = [
+            entry("bin", EntryKind::Dir, b""),
+            entry("bin/tool", EntryKind::File, b"#!/bin/sh\n"),
+            entry("current", EntryKind::Symlink, b"bin/tool"),
+        ];
+        let archive = encode_tree(&tree);
+        let parsed = parse_tree(&archive).unwrap();
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[1].name, "bin/tool");
+        assert_eq!(parsed[1].data, b"#!/bin/sh\n");
+        assert_eq!(parsed[2].kind, EntryKind::Symlink);
+    }
+
+    #[test]
+    fn test_sec_tree_rejects_traversal() {
+        // Writing through a symlink: "link" -> "." then "link/x" is not a
+        // packed directory.
+        let through_link = [
+            entry("link", EntryKind::Symlink, b"."),
+            entry("link/x", EntryKind::File, b"x"),
+        ];
+        assert!(parse_tree(&encode_tree(&through_link)).is_err());
+        let escape = [entry("l", EntryKind::Symlink, b"../../etc")];
+        assert!(parse_tree(&encode_tree(&escape)).is_err());
+        let absolute = [entry("l", EntryKind::Symlink, b"/etc/passwd")];
+        assert!(parse_tree(&encode_tree(&absolute)).is_err());
+        let dotdot = [entry("..", EntryKind::File, b"x")];
+        assert!(parse_tree(&encode_tree(&dotdot)).is_err());
+        let dup = [
+            entry("a", EntryKind::File, b""),
+            entry("a", EntryKind::File, b""),
+        ];
+        assert!(parse_tree(&encode_tree(&dup)).is_err());
+        let orphan = [entry("missing/a", EntryKind::File, b"")];
+        assert!(parse_tree(&encode_tree(&orphan)).is_err());
+    }
+
+    #[test]
+    fn test_sec_tree_malformed() {
+        let archive = encode_tree(&[entry("a", EntryKind::File, b"abc")]);
+        assert!(parse_tree(&archive[..archive.len() - 1]).is_err());
+        let mut long = archive.clone();
+        long.push(0);
+        assert!(parse_tree(&long).is_err());
+        let mut kind = archive.clone();
+        kind[4] = 9;
+        assert!(parse_tree(&kind).is_err());
+        let mut mode = archive;
+        mode[8..12].copy_from_slice(&0o4755u32.to_le_bytes());
+        assert!(parse_tree(&mode).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_and_install_tree() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir("roundtrip");
+        let src = dir.join("src");
+        fs::create_dir_all(src.join("bin")).unwrap();
+        fs::write(src.join("bin/tool"), b"tool").unwrap();
+        fs::set_permissions(src.join("bin/tool"), fs::Permissions::from_mode(0o750)).unwrap();
+        fs::write(src.join("README"), b"readme").unwrap();
+        std::os::unix::fs::symlink("bin/tool", src.join("tool")).unwrap();
+        let tree = collect_tree(&src).unwrap();
+        let names: Vec<_> = tree.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["README", "bin", "bin/tool", "tool"]);
+
+        let target = dir.join("out/app");
+        assert_eq!(install_tree(&encode_tree(&tree), &target).unwrap(), 4);
+        assert_eq!(fs::read(target.join("tool")).unwrap(), b"tool");
+        let mode = fs::metadata(target.join("bin/tool"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o750);
+        assert_eq!(
+            fs::read_link(target.join("tool")).unwrap(),
+            Path::new("bin/tool")
+        );
+        // A second install into the now non-empty target fails cleanly.
+        let err = install_tree(&encode_tree(&tree), &target).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        let leftovers = fs::read_dir(dir.join("out")).unwrap().count();
+        assert_eq!(leftovers, 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sec_collect_tree_rejects_escaping_symlink() {
+        let dir = temp_dir("escape");
+        std::os::unix::fs::symlink("/etc/passwd", dir.join("pw")).unwrap();
+        assert!(collect_tree(&dir).is_err());
+        fs::remove_file(dir.join("pw")).unwrap();
+        std::os::unix::fs::symlink("t/..", dir.join("s")).unwrap();
+        std::os::unix::fs::symlink(".", dir.join("t")).unwrap();
+        assert!(collect_tree(&dir).is_err());
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_install_into_empty_dir_and_failure_cleanup() {
+        let dir = temp_dir("empty");
+        let target = dir.join("app");
+        fs::create_dir(&target).unwrap();
+        let tree = [entry("f", EntryKind::File, b"data")];
+        install_tree(&encode_tree(&tree), &target).unwrap();
+        assert_eq!(fs::read(target.join("f")).unwrap(), b"data");
+        // Corrupt archive: nothing is left behind.
+        let other = dir.join("other");
+        assert!(install_tree(b"\x01\x00\x00\x00", &other).is_err());
+        assert!(!other.exists());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
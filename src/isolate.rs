@@ -0,0 +1,341 @@
+use std::io;
+
+const NS_USER: u8 = 0x01;
+const NS_MOUNT: u8 = 0x02;
+const NS_PID: u8 = 0x04;
+const NS_NET: u8 = 0x08;
+const NS_IPC: u8 = 0x10;
+
+/// Namespace names accepted by `--isolate`, in the order they are listed.
+pub const NAMESPACE_NAMES: [&str; 5] = ["user", "mount", "pid", "net", "ipc"];
+
+/// Linux namespaces the payload is started in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Namespaces {
+    pub user: bool,
+    pub mount: bool,
+    pub pid: bool,
+    pub net: bool,
+    pub ipc: bool,
+}
+
+/// Parse a comma-separated list such as `user,pid,net`, or `all`.
+pub fn parse_namespaces(s: &str) -> Result<Namespaces, String> {
+    let mut ns = Namespaces::default();
+    for name in s.split(',').map(str::trim) {
+        match name {
+            "all" => ns = Namespaces::from_byte(0x1F).unwrap(),
+            "user" => ns.user = true,
+            "mount" | "mnt" => ns.mount = true,
+            "pid" => ns.pid = true,
+            "net" => ns.net = true,
+            "ipc" => ns.ipc = true,
+            _ => {
+                return Err(format!(
+                    "Unknown namespace '{}' (expected {} or all)",
+                    name,
+                    NAMESPACE_NAMES.join(", ")
+                ))
+            }
+        }
+    }
+    Ok(ns)
+}
+
+impl Namespaces {
+    pub fn is_empty(&self) -> bool {
+        self.to_byte() == 0
+    }
+
+    pub fn to_byte(self) -> u8 {
+        let mut b = 0;
+        for (on, bit) in [
+            (self.user, NS_USER),
+            (self.mount, NS_MOUNT),
+            (self.pid, NS_PID),
+            (self.net, NS_NET),
+            (self.ipc, NS_IPC),
+        ] {
+            if on {
+                b |= bit;
+            }
+        }
+        b
+    }
+
+    pub fn from_byte(b: u8) -> io::Result<Self> {
+        if b & !(NS_USER | NS_MOUNT | NS_PID | NS_NET | NS_IPC) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unknown namespace flags",
+            ));
+        }
+        Ok(Self {
+            user: b & NS_USER != 0,
+            mount: b & NS_MOUNT != 0,
+            pid: b & NS_PID != 0,
+            net: b & NS_NET != 0,
+            ipc: b & NS_IPC != 0,
+        })
+    }
+
+    /// Selected namespace names, e.g. `user,pid`.
+    pub fn names(&self) -> String {
+        let on = [self.user, self.mount, self.pid, self.net, self.ipc];
+        NAMESPACE_NAMES
+            .iter()
+            .zip(on)
+            .filter(|(_, on)| *on)
+            .map(|(n, _)| *n)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{clone_process, enter_in_process, setup_inside, OuterIds};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::Namespaces;
+    use std::io;
+
+    /// Credentials captured before entering a user namespace (inside it,
+    /// `getuid` returns the overflow id until the maps are written).
+    #[derive(Debug, Clone, Copy)]
+    pub struct OuterIds {
+        pub uid: libc::uid_t,
+        pub gid: libc::gid_t,
+    }
+
+    impl OuterIds {
+        pub fn current() -> Self {
+            unsafe {
+                Self {
+                    uid: libc::geteuid(),
+                    gid: libc::getegid(),
+                }
+            }
+        }
+    }
+
+    impl Namespaces {
+        pub fn clone_flags(&self) -> libc::c_int {
+            let mut flags = 0;
+            for (on, flag) in [
+                (self.user, libc::CLONE_NEWUSER),
+                (self.mount, libc::CLONE_NEWNS),
+                (self.pid, libc::CLONE_NEWPID),
+                (self.net, libc::CLONE_NEWNET),
+                (self.ipc, libc::CLONE_NEWIPC),
+            ] {
+                if on {
+                    flags |= flag;
+                }
+            }
+            flags
+        }
+    }
+
+    /// Move the calling process into new namespaces. A new PID namespace
+    /// only applies to children, so callers use `clone_process` for it.
+    pub fn enter_in_process(ns: &Namespaces) -> io::Result<()> {
+        if unsafe { libc::unshare(ns.clone_flags()) } != 0 {
+            return Err(explain(ns, io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Fork into new namespaces: returns the child's pid in the parent and
+    /// 0 in the child, which is PID 1 of a new PID namespace if requested.
+    pub fn clone_process(ns: &Namespaces) -> io::Result<libc::pid_t> {
+        // With a null stack, clone behaves like fork on the child's side.
+        let flags = (ns.clone_flags() | libc::SIGCHLD) as libc::c_ulong;
+        let r = unsafe { libc::syscall(libc::SYS_clone, flags, 0usize, 0usize, 0usize, 0usize) };
+        if r < 0 {
+            return Err(explain(ns, io::Error::last_os_error()));
+        }
+        Ok(r as libc::pid_t)
+    }
+
+    /// Finish setting up the namespaces from inside them: uid/gid maps
+    /// (fatal on failure), then private mount propagation, a fresh `/proc`
+    /// and a loopback interface. Returns the best-effort steps that failed.
+    pub fn setup_inside(
+        ns: &Namespaces,
+        ids: OuterIds,
+    ) -> io::Result<Vec<(&'static str, io::Error)>> {
+        let mut skipped = Vec::new();
+        if ns.user {
+            write_id_maps(ids).map_err(|e| explain(ns, e))?;
+        }
+        if ns.mount {
+            mount(None, c"/", None, libc::MS_REC | libc::MS_PRIVATE)?;
+            if ns.pid {
+                let flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC;
+                if let Err(e) = mount(Some(c"proc"), c"/proc", Some(c"proc"), flags) {
+                    skipped.push(("/proc remount", e));
+                }
+            }
+        }
+        if ns.net {
+            if let Err(e) = loopback_up() {
+                skipped.push(("loopback", e));
+            }
+        }
+        Ok(skipped)
+    }
+
+    /// Map the outer uid/gid to themselves, so files keep their owners.
+    fn write_id_maps(ids: OuterIds) -> io::Result<()> {
+        // Unprivileged gid maps require setgroups to be disabled first.
+        match std::fs::write("/proc/self/setgroups", "deny") {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+        std::fs::write("/proc/self/uid_map", format!("{0} {0} 1", ids.uid))?;
+        std::fs::write("/proc/self/gid_map", format!("{0} {0} 1", ids.gid))
+    }
+
+    fn mount(
+        source: Option<&std::ffi::CStr>,
+        target: &std::ffi::CStr,
+        fstype: Option<&std::ffi::CStr>,
+        flags: libc::c_ulong,
+    ) -> io::Result<()> {
+        let ptr = |s: Option<&std::ffi::CStr>| s.map_or(std::ptr::null(), |s| s.as_ptr());
+        let r = unsafe {
+            libc::mount(
+                ptr(source),
+                target.as_ptr(),
+                ptr(fstype),
+                flags,
+                std::ptr::null(),
+            )
+        };
+        if r != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn loopback_up() -> io::Result<()> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+        for (dst, src) in req.ifr_name.iter_mut().zip(b"lo\0") {
+            *dst = *src as libc::c_char;
+        }
+        let result = unsafe {
+            if libc::ioctl(fd, libc::SIOCGIFFLAGS as _, &mut req) != 0 {
+                Err(io::Error::last_os_error())
+            } else {
+                req.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+                if libc::ioctl(fd, libc::SIOCSIFFLAGS as _, &req) != 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }
+        };
+        unsafe { libc::close(fd) };
+        result
+    }
+
+    /// Turn a permission error into a message that names the likely cause.
+    fn explain(ns: &Namespaces, err: io::Error) -> io::Error {
+        let denied = matches!(
+            err.raw_os_error(),
+            Some(libc::EPERM) | Some(libc::EACCES) | Some(libc::ENOSPC) | Some(libc::EUSERS)
+        );
+        if !denied {
+            return err;
+        }
+        let msg = if !ns.user {
+            format!(
+                "Creating {} namespaces requires root or the user namespace ({})",
+                ns.names(),
+                err
+            )
+        } else {
+            format!(
+                "Unprivileged user namespaces are disabled on this system{} ({})",
+                userns_hint(),
+                err
+            )
+        };
+        io::Error::new(io::ErrorKind::PermissionDenied, msg)
+    }
+
+    /// The sysctl that most likely disables user namespaces, if any.
+    fn userns_hint() -> String {
+        let read = |p: &str| std::fs::read_to_string(p).map(|v| v.trim().to_string());
+        for (path, name, off) in [
+            (
+                "/proc/sys/kernel/unprivileged_userns_clone",
+                "kernel.unprivileged_userns_clone",
+                "0",
+            ),
+            (
+                "/proc/sys/user/max_user_namespaces",
+                "user.max_user_namespaces",
+                "0",
+            ),
+            (
+                "/proc/sys/kernel/apparmor_restrict_unprivileged_userns",
+                "kernel.apparmor_restrict_unprivileged_userns",
+                "1",
+            ),
+        ] {
+            if read(path).is_ok_and(|v| v == off) {
+                return format!(": {} = {}", name, off);
+            }
+        }
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_namespaces() {
+        let ns = parse_namespaces("user, pid,net").unwrap();
+        assert!(ns.user && ns.pid && ns.net && !ns.mount && !ns.ipc);
+        assert_eq!(ns.names(), "user,pid,net");
+        assert_eq!(parse_namespaces("mnt").unwrap().names(), "mount");
+        let all = parse_namespaces("all").unwrap();
+        assert_eq!(all.names(), NAMESPACE_NAMES.join(","));
+        assert!(parse_namespaces("uts").is_err());
+        assert!(parse_namespaces("").is_err());
+    }
+
+    #[test]
+    fn test_namespaces_byte_roundtrip() {
+        for b in 0..=0x1F {
+            assert_eq!(Namespaces::from_byte(b).unwrap().to_byte(), b);
+        }
+        assert!(Namespaces::from_byte(0x20).is_err());
+        assert!(Namespaces::default().is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_clone_flags() {
+        let ns = parse_namespaces("user,mount,pid,net,ipc").unwrap();
+        let flags = ns.clone_flags();
+        for f in [
+            libc::CLONE_NEWUSER,
+            libc::CLONE_NEWNS,
+            libc::CLONE_NEWPID,
+            libc::CLONE_NEWNET,
+            libc::CLONE_NEWIPC,
+        ] {
+            assert_ne!(flags & f, 0);
+        }
+        assert_eq!(Namespaces::default().clone_flags(), 0);
+    }
+}
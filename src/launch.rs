@@ -0,0 +1,428 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::cache::parse_size;
+use crate::cursor::ByteReader;
+
+/// Value meaning "no limit" for a resource limit.
+pub const RLIM_UNLIMITED: u64 = u64::MAX;
+
+const FLAG_UMASK: u8 = 0x01;
+const FLAG_NICE: u8 = 0x02;
+const FLAG_WORKDIR: u8 = 0x04;
+
+const WORKDIR_SFX_DIR: u8 = 0;
+const WORKDIR_PATH: u8 = 1;
+
+/// Resource limits that can be baked into an SFX.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resource {
+    /// Address space (`RLIMIT_AS`), in bytes.
+    AddressSpace = 0,
+    /// Open file descriptors (`RLIMIT_NOFILE`).
+    NoFile = 1,
+    /// Core dump size (`RLIMIT_CORE`), in bytes.
+    Core = 2,
+    /// CPU time (`RLIMIT_CPU`), in seconds.
+    Cpu = 3,
+}
+
+impl Resource {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "as" => Some(Resource::AddressSpace),
+            "nofile" => Some(Resource::NoFile),
+            "core" => Some(Resource::Core),
+            "cpu" => Some(Resource::Cpu),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Resource::AddressSpace => "as",
+            Resource::NoFile => "nofile",
+            Resource::Core => "core",
+            Resource::Cpu => "cpu",
+        }
+    }
+
+    fn from_u8(v: u8) -> Option<Self> {
+        [
+            Resource::AddressSpace,
+            Resource::NoFile,
+            Resource::Core,
+            Resource::Cpu,
+        ]
+        .into_iter()
+        .find(|r| *r as u8 == v)
+    }
+}
+
+/// Working directory for the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorkDir {
+    /// The directory containing the SFX.
+    SfxDir,
+    /// A fixed path.
+    Path(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rlimit {
+    pub resource: Resource,
+    pub soft: u64,
+    pub hard: u64,
+}
+
+/// Process settings applied by the stub before the payload starts,
+/// replacing the usual `ulimit`/`umask`/`cd`/`nice` shell wrapper.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LaunchSettings {
+    pub rlimits: Vec<Rlimit>,
+    pub umask: Option<u32>,
+    pub nice: Option<i32>,
+    /// `None` leaves the working directory unchanged.
+    pub workdir: Option<WorkDir>,
+}
+
+fn launch_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_limit_value(s: &str) -> Option<u64> {
+    match s {
+        "unlimited" | "infinity" => Some(RLIM_UNLIMITED),
+        _ => parse_size(s),
+    }
+}
+
+impl Rlimit {
+    /// The limit in `--rlimit` syntax, e.g. `cpu=60:unlimited`.
+    pub fn spec(&self) -> String {
+        let value = |v: u64| match v {
+            RLIM_UNLIMITED => "unlimited".to_string(),
+            v => v.to_string(),
+        };
+        if self.soft == self.hard {
+            format!("{}={}", self.resource.name(), value(self.soft))
+        } else {
+            format!(
+                "{}={}:{}",
+                self.resource.name(),
+                value(self.soft),
+                value(self.hard)
+            )
+        }
+    }
+}
+
+/// Parse `<name>=<soft>[:<hard>]`, e.g. `nofile=4096`, `as=2G:4G`,
+/// `core=0`, `cpu=60:unlimited`. A missing hard limit equals the soft one.
+pub fn parse_rlimit(spec: &str) -> Option<Rlimit> {
+    let (name, values) = spec.split_once('=')?;
+    let resource = Resource::from_name(name.trim())?;
+    let (soft, hard) = match values.split_once(':') {
+        Some((s, h)) => (parse_limit_value(s.trim())?, parse_limit_value(h.trim())?),
+        None => {
+            let v = parse_limit_value(values.trim())?;
+            (v, v)
+        }
+    };
+    if soft > hard {
+        return None;
+    }
+    Some(Rlimit {
+        resource,
+        soft,
+        hard,
+    })
+}
+
+/// Parse an octal umask such as `022` or `0077`.
+pub fn parse_umask(s: &str) -> Option<u32> {
+    u32::from_str_radix(s.trim(), 8)
+        .ok()
+        .filter(|m| *m <= 0o777)
+}
+
+/// Parse a nice level in `-20..=19`.
+pub fn parse_nice(s: &str) -> Option<i32> {
+    s.trim()
+        .parse::<i32>()
+        .ok()
+        .filter(|n| (-20..=19).contains(n))
+}
+
+/// `sfx-dir` or an absolute path.
+pub fn parse_workdir(s: &str) -> Option<WorkDir> {
+    if s == "sfx-dir" {
+        return Some(WorkDir::SfxDir);
+    }
+    // Accept both Unix and Windows absolute forms regardless of the host.
+    let absolute = s.starts_with('/') || s.starts_with('\\') || s.get(1..3) == Some(":\\");
+    absolute.then(|| WorkDir::Path(s.to_string()))
+}
+
+impl LaunchSettings {
+    pub fn is_empty(&self) -> bool {
+        self.rlimits.is_empty()
+            && self.umask.is_none()
+            && self.nice.is_none()
+            && self.workdir.is_none()
+    }
+
+    /// `[count u8]{[resource u8][soft u64][hard u64]}[flags u8]` followed
+    /// by `umask u16`, `nice i8` and `[kind u8][len u16][path]` when set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![self.rlimits.len() as u8];
+        for l in &self.rlimits {
+            out.push(l.resource as u8);
+            out.extend_from_slice(&l.soft.to_le_bytes());
+            out.extend_from_slice(&l.hard.to_le_bytes());
+        }
+        let mut flags = 0;
+        if self.umask.is_some() {
+            flags |= FLAG_UMASK;
+        }
+        if self.nice.is_some() {
+            flags |= FLAG_NICE;
+        }
+        if self.workdir.is_some() {
+            flags |= FLAG_WORKDIR;
+        }
+        out.push(flags);
+        if let Some(m) = self.umask {
+            out.extend_from_slice(&(m as u16).to_le_bytes());
+        }
+        if let Some(n) = self.nice {
+            out.push(n as i8 as u8);
+        }
+        match &self.workdir {
+            Some(WorkDir::SfxDir) => out.push(WORKDIR_SFX_DIR),
+            Some(WorkDir::Path(p)) => {
+                out.push(WORKDIR_PATH);
+                out.extend_from_slice(&(p.len() as u16).to_le_bytes());
+                out.extend_from_slice(p.as_bytes());
+            }
+            None => {}
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut r = ByteReader::new(data, "launch settings");
+        let mut settings = Self::default();
+        for _ in 0..r.u8()? {
+            let resource =
+                Resource::from_u8(r.u8()?).ok_or_else(|| launch_err("Unknown rlimit resource"))?;
+            let soft = r.u64()?;
+            let hard = r.u64()?;
+            if soft > hard {
+                return Err(launch_err("Soft rlimit above hard rlimit"));
+            }
+            settings.rlimits.push(Rlimit {
+                resource,
+                soft,
+                hard,
+            });
+        }
+        let flags = r.u8()?;
+        if flags & !(FLAG_UMASK | FLAG_NICE | FLAG_WORKDIR) != 0 {
+            return Err(launch_err("Unknown launch settings flags"));
+        }
+        if flags & FLAG_UMASK != 0 {
+            let m = r.u16()? as u32;
+            if m > 0o777 {
+                return Err(launch_err("Invalid umask"));
+            }
+            settings.umask = Some(m);
+        }
+        if flags & FLAG_NICE != 0 {
+            let n = r.u8()? as i8 as i32;
+            if !(-20..=19).contains(&n) {
+                return Err(launch_err("Invalid nice level"));
+            }
+            settings.nice = Some(n);
+        }
+        if flags & FLAG_WORKDIR != 0 {
+            settings.workdir = Some(match r.u8()? {
+                WORKDIR_SFX_DIR => WorkDir::SfxDir,
+                WORKDIR_PATH => WorkDir::Path(r.str16()?.to_string()),
+                _ => return Err(launch_err("Unknown working directory kind")),
+            });
+        }
+        if !r.is_empty() {
+            return Err(launch_err("Trailing bytes in launch settings"));
+        }
+        Ok(settings)
+    }
+
+    /// Directory to change into, resolved against the SFX location.
+    pub fn resolve_workdir(&self, sfx_path: &Path) -> Option<PathBuf> {
+        match self.workdir.as_ref()? {
+            WorkDir::SfxDir => sfx_path.parent().map(Path::to_path_buf),
+            WorkDir::Path(p) => Some(PathBuf::from(p)),
+        }
+    }
+
+    /// Apply to the current process (inherited across exec). Any failure
+    /// is returned: a limit that was asked for is never silently dropped.
+    #[cfg(unix)]
+    pub fn apply(&self, sfx_path: &Path) -> io::Result<()> {
+        for l in &self.rlimits {
+            set_rlimit(l)?;
+        }
+        if let Some(m) = self.umask {
+            unsafe { libc::umask(m as libc::mode_t) };
+        }
+        if let Some(n) = self.nice {
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, n) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        if let Some(dir) = self.resolve_workdir(sfx_path) {
+            std::env::set_current_dir(&dir)?;
+        }
+        Ok(())
+    }
+
+    /// Only the working directory has a portable equivalent here; limits,
+    /// umask and nice level fail with `Unsupported` (the packer rejects
+    /// them for such targets).
+    #[cfg(not(unix))]
+    pub fn apply(&self, sfx_path: &Path) -> io::Result<()> {
+        if !self.rlimits.is_empty() || self.umask.is_some() || self.nice.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Resource limits, umask and nice level require a Unix target",
+            ));
+        }
+        if let Some(dir) = self.resolve_workdir(sfx_path) {
+            std::env::set_current_dir(&dir)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn set_rlimit(l: &Rlimit) -> io::Result<()> {
+    let resource = match l.resource {
+        Resource::AddressSpace => libc::RLIMIT_AS,
+        Resource::NoFile => libc::RLIMIT_NOFILE,
+        Resource::Core => libc::RLIMIT_CORE,
+        Resource::Cpu => libc::RLIMIT_CPU,
+    };
+    let to_rlim = |v: u64| {
+        if v == RLIM_UNLIMITED {
+            libc::RLIM_INFINITY
+        } else {
+            v as libc::rlim_t
+        }
+    };
+    let lim = libc::rlimit {
+        rlim_cur: to_rlim(l.soft),
+        rlim_max: to_rlim(l.hard),
+    };
+    if unsafe { libc::setrlimit(resource, &lim) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> LaunchSettings {
+        LaunchSettings {
+            rlimits: vec![
+                parse_rlimit("nofile=1024:4096").unwrap(),
+                parse_rlimit("core=0").unwrap(),
+            ],
+            umask: Some(0o077),
+            nice: Some(-5),
+            workdir: Some(WorkDir::Path("/srv/app".into())),
+        }
+    }
+
+    #[test]
+    fn test_launch_settings_roundtrip() {
+        let s = sample();
+        assert_eq!(LaunchSettings::from_bytes(&s.to_bytes()).unwrap(), s);
+        let sfx_dir = LaunchSettings {
+            workdir: Some(WorkDir::SfxDir),
+            ..LaunchSettings::default()
+        };
+        assert_eq!(
+            LaunchSettings::from_bytes(&sfx_dir.to_bytes()).unwrap(),
+            sfx_dir
+        );
+        assert!(LaunchSettings::default().is_empty());
+    }
+
+    #[test]
+    fn test_parse_rlimit() {
+        let l = parse_rlimit("as=2G:unlimited").unwrap();
+        assert_eq!(l.resource, Resource::AddressSpace);
+        assert_eq!(l.soft, 2 << 30);
+        assert_eq!(l.hard, RLIM_UNLIMITED);
+        assert_eq!(l.spec(), format!("as={}:unlimited", 2u64 << 30));
+        assert_eq!(parse_rlimit("cpu=60").unwrap().hard, 60);
+        assert_eq!(parse_rlimit("cpu=60").unwrap().spec(), "cpu=60");
+        assert!(parse_rlimit("stack=1M").is_none());
+        assert!(parse_rlimit("nofile=10:5").is_none());
+        assert!(parse_rlimit("nofile").is_none());
+    }
+
+    #[test]
+    fn test_parse_umask_nice_workdir() {
+        assert_eq!(parse_umask("022"), Some(0o022));
+        assert_eq!(parse_umask("0777"), Some(0o777));
+        assert_eq!(parse_umask("1000"), None);
+        assert_eq!(parse_umask("9"), None);
+        assert_eq!(parse_nice("10"), Some(10));
+        assert_eq!(parse_nice("-21"), None);
+        assert_eq!(parse_workdir("sfx-dir"), Some(WorkDir::SfxDir));
+        assert_eq!(
+            parse_workdir("/var/lib/x"),
+            Some(WorkDir::Path("/var/lib/x".into()))
+        );
+        assert_eq!(
+            parse_workdir("C:\\app"),
+            Some(WorkDir::Path("C:\\app".into()))
+        );
+        assert_eq!(parse_workdir("relative/dir"), None);
+    }
+
+    #[test]
+    fn test_resolve_workdir() {
+        let sfx = Path::new("/opt/tools/app");
+        let s = LaunchSettings {
+            workdir: Some(WorkDir::SfxDir),
+            ..LaunchSettings::default()
+        };
+        assert_eq!(s.resolve_workdir(sfx), Some(PathBuf::from("/opt/tools")));
+        assert_eq!(LaunchSettings::default().resolve_workdir(sfx), None);
+    }
+
+    #[test]
+    fn test_sec_uc012_malformed_settings_rejected() {
+        assert!(LaunchSettings::from_bytes(&[]).is_err());
+        assert!(LaunchSettings::from_bytes(&[0, 0x80]).is_err());
+        assert!(LaunchSettings::from_bytes(&[1, 9]).is_err());
+        assert!(LaunchSettings::from_bytes(&[0, FLAG_UMASK, 0xFF, 0xFF]).is_err());
+        assert!(LaunchSettings::from_bytes(&[0, FLAG_NICE, 100]).is_err());
+        assert!(LaunchSettings::from_bytes(&[0, FLAG_WORKDIR, 7]).is_err());
+        assert!(LaunchSettings::from_bytes(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc012_soft_above_hard_rejected() {
+        let mut bytes = vec![1, Resource::Cpu as u8];
+        bytes.extend_from_slice(&10u64.to_le_bytes());
+        bytes.extend_from_slice(&5u64.to_le_bytes());
+        bytes.push(0);
+        assert!(LaunchSettings::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,360 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::common::{Trailer, MAGIC, MAGIC_META, TRAILER_SIZE};
+use crate::elf_section::{find_section, SECTION_NAME};
+use crate::metadata::{Codec, Metadata};
+
+/// How far before the end of the file a displaced trailer is searched for
+/// when data (a signature, installer overlay, ...) was appended to the SFX.
+pub const OVERLAY_SEARCH_MAX: u64 = 1024 * 1024;
+
+/// Located payload region plus the metadata recorded at pack time.
+#[derive(Debug)]
+pub struct SfxLayout {
+    pub payload_offset: u64,
+    pub payload_len: u64,
+    pub metadata: Metadata,
+    /// Offset of the compressed asset archive, if any.
+    pub assets_offset: Option<u64>,
+    /// Bytes after the trailer that are not part of the SFX.
+    pub overlay_len: u64,
+}
+
+fn layout_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Find and validate the trailer. It is expected in the last 16 bytes; if
+/// it is not there, it ends the `.xsfx` section of an ELF SFX, or the last
+/// `OVERLAY_SEARCH_MAX` bytes are searched backwards for a trailer whose
+/// metadata records the payload offset.
+pub fn read_layout<R: Read + Seek>(r: &mut R) -> io::Result<SfxLayout> {
+    let total_len = r.seek(SeekFrom::End(0))?;
+    if total_len < TRAILER_SIZE {
+        return Err(layout_err("File too small to contain trailer"));
+    }
+    let first_err = match layout_at(r, total_len) {
+        Ok(layout) => return Ok(layout),
+        Err(e) => e,
+    };
+    if let Ok(Some((offset, len))) = find_section(r, SECTION_NAME) {
+        return section_layout(r, total_len, offset, len);
+    }
+    let start = total_len.saturating_sub(OVERLAY_SEARCH_MAX + TRAILER_SIZE);
+    let mut window = Vec::new();
+    r.seek(SeekFrom::Start(start))?;
+    r.take(total_len - start).read_to_end(&mut window)?;
+    for end in trailer_candidates(&window) {
+        let trailer_end = start + end as u64;
+        if trailer_end == total_len {
+            continue;
+        }
+        let Ok(mut layout) = layout_at(r, trailer_end) else {
+            continue;
+        };
+        // Without a recorded payload offset any magic-looking bytes could
+        // match, so only self-describing SFX files may carry an overlay.
+        if layout.metadata.payload_offset.is_some() {
+            layout.overlay_len = total_len - trailer_end;
+            return Ok(layout);
+        }
+    }
+    Err(first_err)
+}
+
+/// Parse an SFX stored in an ELF section (`--elf-section`). The section
+/// holds what otherwise follows the stub, with the recorded payload offset
+/// relative to the section, so the section may move when the file is
+/// rewritten by binary tools.
+fn section_layout<R: Read + Seek>(
+    r: &mut R,
+    total_len: u64,
+    offset: u64,
+    len: u64,
+) -> io::Result<SfxLayout> {
+    if len < TRAILER_SIZE || offset.checked_add(len).is_none_or(|end| end > total_len) {
+        return Err(layout_err("Invalid .xsfx section"));
+    }
+    let mut layout = layout_at(&mut Region::new(&mut *r, offset, len), len)?;
+    layout.payload_offset += offset;
+    layout.assets_offset = layout.assets_offset.map(|a| a + offset);
+    Ok(layout)
+}
+
+/// End offsets (within `window`) of possible trailers, last first.
+fn trailer_candidates(window: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    let magics = [MAGIC.to_le_bytes(), MAGIC_META.to_le_bytes()];
+    (TRAILER_SIZE as usize..=window.len())
+        .rev()
+        .filter(move |&end| magics.iter().any(|m| window[end - 8..end] == m[..]))
+}
+
+/// Parse the SFX as if it ended at `end`.
+fn layout_at<R: Read + Seek>(r: &mut R, end: u64) -> io::Result<SfxLayout> {
+    r.seek(SeekFrom::Start(end - TRAILER_SIZE))?;
+    let trailer = Trailer::from_reader(&mut *r)?;
+    if !trailer.is_valid_magic() {
+        return Err(layout_err("Invalid SFX magic marker"));
+    }
+    if trailer.payload_len == 0 || trailer.payload_len > end {
+        return Err(layout_err("Invalid payload length in trailer"));
+    }
+    let mut payload_end = end - TRAILER_SIZE;
+    let mut metadata = Metadata::default();
+    if trailer.has_metadata() {
+        let (meta, meta_len) = Metadata::read_before(r, payload_end)?;
+        metadata = meta;
+        payload_end -= meta_len;
+    }
+    let mut assets_offset = None;
+    if let Some(assets) = &metadata.assets {
+        if assets.compressed_len > payload_end {
+            return Err(layout_err("Invalid asset archive length"));
+        }
+        payload_end -= assets.compressed_len;
+        assets_offset = Some(payload_end);
+    }
+    if trailer.payload_len > payload_end {
+        return Err(layout_err("Invalid payload length in trailer"));
+    }
+    let derived = payload_end - trailer.payload_len;
+    let payload_offset = match metadata.payload_offset {
+        None => derived,
+        // In a ZIP container the central directory sits between the
+        // payload and the metadata; otherwise they are adjacent.
+        Some(off) if metadata.codec == Codec::Deflate => {
+            if off
+                .checked_add(trailer.payload_len)
+                .is_none_or(|e| e > payload_end)
+            {
+                return Err(layout_err("Payload offset does not match the trailer"));
+            }
+            off
+        }
+        Some(off) if off != derived => {
+            return Err(layout_err("Payload offset does not match the trailer"));
+        }
+        Some(off) => off,
+    };
+    Ok(SfxLayout {
+        payload_offset,
+        payload_len: trailer.payload_len,
+        metadata,
+        assets_offset,
+        overlay_len: 0,
+    })
+}
+
+/// A `Read + Seek` window onto part of another reader.
+pub struct Region<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    pos: u64,
+}
+
+impl<R> Region<R> {
+    /// The `len` bytes of `inner` starting at `start`.
+    pub fn new(inner: R, start: u64, len: u64) -> Self {
+        Self {
+            inner,
+            start,
+            len,
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for Region<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.len.saturating_sub(self.pos);
+        let n = (buf.len() as u64).min(left) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.inner.seek(SeekFrom::Start(self.start + self.pos))?;
+        let read = self.inner.read(&mut buf[..n])?;
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Read + Seek> Seek for Region<R> {
+    fn seek(&mut self, to: SeekFrom) -> io::Result<u64> {
+        let pos = match to {
+            SeekFrom::Start(n) => Some(n),
+            SeekFrom::End(d) => self.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Seek before start of region")
+        })?;
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::AssetsInfo;
+    use crate::elf::tests::build_elf;
+    use crate::elf_section::add_section;
+    use crate::elf_section::tests::with_section_table;
+    use std::io::Cursor;
+
+    fn sfx(stub: &[u8], payload: &[u8], payload_offset: Option<u64>) -> Vec<u8> {
+        let meta = Metadata {
+            payload_offset,
+            payload_version: Some("1".into()),
+            ..Metadata::default()
+        };
+        let mut out = stub.to_vec();
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&meta.to_bytes().unwrap());
+        out.extend_from_slice(&Trailer::with_metadata(payload.len() as u64).to_bytes());
+        out
+    }
+
+    fn legacy(stub: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut out = stub.to_vec();
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&Trailer::new(payload.len() as u64).to_bytes());
+        out
+    }
+
+    #[test]
+    fn test_layout_at_end() {
+        let data = sfx(b"STUB", b"payload", Some(4));
+        let layout = read_layout(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(layout.payload_offset, 4);
+        assert_eq!(layout.payload_len, 7);
+        assert_eq!(layout.overlay_len, 0);
+        assert_eq!(layout.metadata.payload_offset, Some(4));
+        let layout = read_layout(&mut Cursor::new(legacy(b"STUB", b"xz"))).unwrap();
+        assert_eq!((layout.payload_offset, layout.payload_len), (4, 2));
+    }
+
+    #[test]
+    fn test_layout_elf_section() {
+        let stub = with_section_table(build_elf(None, &[]));
+        let image = add_section(&stub, SECTION_NAME, &sfx(b"", b"payload", Some(0))).unwrap();
+        let (offset, _) = find_section(&mut Cursor::new(&image), SECTION_NAME)
+            .unwrap()
+            .unwrap();
+        let layout = read_layout(&mut Cursor::new(&image)).unwrap();
+        assert_eq!(layout.payload_offset, offset);
+        assert_eq!(layout.payload_len, 7);
+        assert_eq!(&image[offset as usize..][..7], b"payload");
+        assert_eq!(layout.overlay_len, 0);
+    }
+
+    #[test]
+    fn test_sec_layout_elf_section_bounds() {
+        let stub = with_section_table(build_elf(None, &[]));
+        let mut image = add_section(&stub, SECTION_NAME, &sfx(b"", b"payload", Some(0))).unwrap();
+        // sh_size of the .xsfx header, the last one in the table.
+        let size_at = image.len() - 64 + 32;
+        image[size_at..size_at + 8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(read_layout(&mut Cursor::new(&image)).is_err());
+        // An absolute payload offset does not match inside the section.
+        let image = add_section(&stub, SECTION_NAME, &sfx(b"", b"payload", Some(4))).unwrap();
+        assert!(read_layout(&mut Cursor::new(&image)).is_err());
+    }
+
+    #[test]
+    fn test_layout_with_overlay() {
+        let mut data = sfx(b"STUB", b"payload", Some(4));
+        data.extend_from_slice(&[0xEE; 5000]);
+        let layout = read_layout(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(layout.payload_offset, 4);
+        assert_eq!(layout.payload_len, 7);
+        assert_eq!(layout.overlay_len, 5000);
+    }
+
+    #[test]
+    fn test_layout_overlay_containing_magic() {
+        let mut data = sfx(b"STUB", b"payload", Some(4));
+        // A bogus trailer-shaped blob inside the overlay is skipped.
+        data.extend_from_slice(&Trailer::with_metadata(3).to_bytes());
+        data.extend_from_slice(b"tail");
+        let layout = read_layout(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(layout.overlay_len, 20);
+    }
+
+    #[test]
+    fn test_sec_layout_overlay_requires_payload_offset() {
+        let mut data = sfx(b"STUB", b"payload", None);
+        data.extend_from_slice(b"overlay");
+        assert!(read_layout(&mut Cursor::new(&data)).is_err());
+        let mut data = legacy(b"STUB", b"payload");
+        data.extend_from_slice(b"overlay");
+        assert!(read_layout(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn test_sec_layout_payload_offset_mismatch() {
+        let data = sfx(b"STUB", b"payload", Some(3));
+        let err = read_layout(&mut Cursor::new(&data)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_sec_layout_overlay_beyond_window() {
+        let mut data = sfx(b"STUB", b"payload", Some(4));
+        data.resize(data.len() + OVERLAY_SEARCH_MAX as usize + 1, 0);
+        assert!(read_layout(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn test_layout_deflate_gap_allowed() {
+        let meta = Metadata {
+            payload_offset: Some(4),
+            codec: Codec::Deflate,
+            ..Metadata::default()
+        };
+        let mut data = b"STUBpayload<central directory>".to_vec();
+        data.extend_from_slice(&meta.to_bytes().unwrap());
+        data.extend_from_slice(&Trailer::with_metadata(7).to_bytes());
+        let layout = read_layout(&mut Cursor::new(&data)).unwrap();
+        assert_eq!((layout.payload_offset, layout.payload_len), (4, 7));
+        let bad = Metadata {
+            payload_offset: Some(30),
+            ..meta
+        };
+        let mut data = b"STUBpayload<central directory>".to_vec();
+        data.extend_from_slice(&bad.to_bytes().unwrap());
+        data.extend_from_slice(&Trailer::with_metadata(7).to_bytes());
+        assert!(read_layout(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn test_layout_with_assets() {
+        let meta = Metadata {
+            payload_offset: Some(4),
+            assets: Some(AssetsInfo {
+                count: 1,
+                compressed_len: 6,
+                archive_len: 10,
+                sha256: [0; 32],
+            }),
+            ..Metadata::default()
+        };
+        let mut data = b"STUBpayloadASSETS".to_vec();
+        data.extend_from_slice(&meta.to_bytes().unwrap());
+        data.extend_from_slice(&Trailer::with_metadata(7).to_bytes());
+        let layout = read_layout(&mut Cursor::new(&data)).unwrap();
+        assert_eq!((layout.payload_offset, layout.payload_len), (4, 7));
+        assert_eq!(layout.assets_offset, Some(11));
+        let mut bad = meta.clone();
+        bad.assets.as_mut().unwrap().compressed_len = 1 << 40;
+        let mut data = b"STUBpayloadASSETS".to_vec();
+        data.extend_from_slice(&bad.to_bytes().unwrap());
+        data.extend_from_slice(&Trailer::with_metadata(7).to_bytes());
+        assert!(read_layout(&mut Cursor::new(&data)).is_err());
+    }
+
+    #[test]
+    fn test_layout_too_small() {
+        assert!(read_layout(&mut Cursor::new(&[0u8; 15])).is_err());
+    }
+}
@@ -1,5 +1,33 @@
+pub mod assets;
+pub mod cache;
 pub mod common;
 pub mod compress;
+pub mod crypto;
+pub mod cursor;
 pub mod decompress;
+pub mod diag;
+pub mod digest;
+pub mod dynlink;
+pub mod elf;
+pub mod elf_section;
+pub mod exit_code;
+pub mod install;
+pub mod isolate;
+pub mod launch;
+pub mod layout;
 pub mod macho_loader;
+#[cfg(target_os = "linux")]
+pub mod memfd;
+pub mod metadata;
+pub mod multiarch;
+pub mod multicall;
 pub mod pe_loader;
+pub mod platform;
+pub mod runtime_flags;
+pub mod sandbox;
+pub mod self_exe;
+pub mod strip;
+pub mod supervise;
+pub mod validity;
+pub mod variant;
+pub mod zip;
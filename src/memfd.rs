@@ -0,0 +1,122 @@
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd, RawFd};
+
+/// Seals applied to the payload image: no writes, no resizing, and no
+/// further changes to the seal set itself.
+pub const PAYLOAD_SEALS: libc::c_int =
+    libc::F_SEAL_WRITE | libc::F_SEAL_GROW | libc::F_SEAL_SHRINK | libc::F_SEAL_SEAL;
+
+/// An executable in-memory copy of the payload.
+pub struct PayloadMemfd {
+    pub file: File,
+    /// `None` if sealed; otherwise why sealing was not possible.
+    pub unsealed: Option<io::Error>,
+}
+
+/// Copy `data` into a close-on-exec memfd, mark it executable and seal it
+/// against modification. Kernels (or sandboxes such as gVisor) that reject
+/// `MFD_ALLOW_SEALING` or `F_ADD_SEALS` still get a working, unsealed memfd.
+pub fn create_payload_memfd(name: &CStr, data: &[u8]) -> io::Result<PayloadMemfd> {
+    create_sealed_memfd(name, data, 0o700)
+}
+
+/// Like `create_payload_memfd`, with the given permission bits.
+pub fn create_sealed_memfd(name: &CStr, data: &[u8], mode: u32) -> io::Result<PayloadMemfd> {
+    let (mut file, sealable) = match memfd_create(name, libc::MFD_CLOEXEC | libc::MFD_ALLOW_SEALING)
+    {
+        Ok(f) => (f, None),
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+            (memfd_create(name, libc::MFD_CLOEXEC)?, Some(e))
+        }
+        Err(e) => return Err(e),
+    };
+    file.write_all(data)?;
+    if unsafe { libc::fchmod(file.as_raw_fd(), mode as libc::mode_t) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let unsealed = match sealable {
+        Some(e) => Some(e),
+        None => add_seals(&file, PAYLOAD_SEALS).err(),
+    };
+    Ok(PayloadMemfd { file, unsealed })
+}
+
+/// Give up ownership of `file` and let its fd survive `exec`.
+pub fn into_inherited_fd(file: File) -> io::Result<RawFd> {
+    let fd = file.into_raw_fd();
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+fn memfd_create(name: &CStr, flags: libc::c_uint) -> io::Result<File> {
+    let r = unsafe { libc::syscall(libc::SYS_memfd_create, name.as_ptr(), flags) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { File::from_raw_fd(r as i32) })
+}
+
+fn add_seals(file: &File, seals: libc::c_int) -> io::Result<()> {
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_ADD_SEALS, seals) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Current seal set of a memfd.
+pub fn seals(file: &File) -> io::Result<libc::c_int> {
+    let r = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GET_SEALS) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_create_payload_memfd_contents_and_mode() {
+        let mut m = create_payload_memfd(c"test", b"payload bytes").unwrap();
+        let mut buf = Vec::new();
+        m.file.seek(SeekFrom::Start(0)).unwrap();
+        m.file.read_to_end(&mut buf).unwrap();
+        assert_eq!(buf, b"payload bytes");
+        let mode = m.file.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[test]
+    fn test_sec_payload_memfd_sealed() {
+        let mut m = create_payload_memfd(c"test", b"payload").unwrap();
+        if m.unsealed.is_some() {
+            return; // kernel without sealing support
+        }
+        assert_eq!(seals(&m.file).unwrap() & PAYLOAD_SEALS, PAYLOAD_SEALS);
+        m.file.seek(SeekFrom::Start(0)).unwrap();
+        assert!(m.file.write_all(b"X").is_err());
+        assert!(m.file.set_len(0).is_err());
+        assert!(m.file.set_len(4096).is_err());
+        assert!(add_seals(&m.file, 0).is_err());
+    }
+
+    #[test]
+    fn test_sec_payload_memfd_reopen_not_writable() {
+        let m = create_payload_memfd(c"test", b"payload").unwrap();
+        if m.unsealed.is_some() {
+            return;
+        }
+        let path = format!("/proc/self/fd/{}", m.file.as_raw_fd());
+        // Opening may succeed; the seal applies to the inode, not the fd.
+        if let Ok(mut f) = std::fs::OpenOptions::new().write(true).open(path) {
+            assert!(f.write_all(b"X").is_err());
+        }
+    }
+}
@@ -0,0 +1,617 @@
+use std::ffi::OsString;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::assets::AssetsInfo;
+use crate::crypto::Encryption;
+use crate::digest::{to_hex, SHA256_LEN};
+use crate::install::InstallSpec;
+use crate::isolate::Namespaces;
+use crate::launch::LaunchSettings;
+use crate::sandbox::SandboxPolicy;
+use crate::supervise::SupervisorPolicy;
+
+/// Upper bound for the metadata block; anything larger is treated as corrupt.
+pub const METADATA_MAX_LEN: u64 = 64 * 1024;
+/// Size of the `records_len` footer that closes the metadata block.
+pub const METADATA_FOOTER_SIZE: u64 = 4;
+
+// Record tags. Unknown tags are rejected so that an older stub never
+// silently ignores a setting it does not understand.
+const TAG_EXPOSE_ENV: u8 = 0x01;
+const TAG_PAYLOAD_VERSION: u8 = 0x02;
+const TAG_PAYLOAD_SHA256: u8 = 0x03;
+const TAG_DEBUG: u8 = 0x04;
+const TAG_PAYLOAD_LEN: u8 = 0x05;
+const TAG_CACHE: u8 = 0x06;
+const TAG_RUNTIME_FLAGS: u8 = 0x07;
+const TAG_ENCRYPTION: u8 = 0x08;
+const TAG_NOT_BEFORE: u8 = 0x09;
+const TAG_NOT_AFTER: u8 = 0x0A;
+const TAG_SANDBOX: u8 = 0x0B;
+const TAG_LAUNCH: u8 = 0x0C;
+const TAG_SUPERVISE: u8 = 0x0D;
+const TAG_ISOLATE: u8 = 0x0E;
+const TAG_PAYLOAD_OFFSET: u8 = 0x0F;
+const TAG_CODEC: u8 = 0x10;
+const TAG_ASSETS: u8 = 0x11;
+const TAG_INSTALL: u8 = 0x12;
+const TAG_MULTI_CALL: u8 = 0x13;
+const TAG_VARIANTS: u8 = 0x14;
+const TAG_BUNDLED_LIBS: u8 = 0x15;
+const TAG_BUILD_IDS: u8 = 0x16;
+
+pub const ENV_SFX_PATH: &str = "XSFX_SFX_PATH";
+pub const ENV_SFX_DIR: &str = "XSFX_SFX_DIR";
+pub const ENV_PAYLOAD_SHA256: &str = "XSFX_PAYLOAD_SHA256";
+pub const ENV_PAYLOAD_VERSION: &str = "XSFX_PAYLOAD_VERSION";
+
+/// Pack-time settings recorded in the SFX and read back by the stub.
+///
+/// Encoded as a sequence of `[tag u8][len u16 LE][value]` records followed
+/// by a `records_len` (u32 LE) footer, placed between the compressed
+/// payload and the trailer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub expose_env: bool,
+    pub payload_version: Option<String>,
+    pub payload_sha256: Option<[u8; SHA256_LEN]>,
+    /// Always print stub diagnostics, as if `XSFX_DEBUG=1` were set.
+    pub debug: bool,
+    /// Uncompressed payload size, used to pre-allocate and bound decompression.
+    pub payload_len: Option<u64>,
+    /// Cache the decompressed payload per user, capped at this many bytes.
+    pub cache_max_size: Option<u64>,
+    /// Honour leading `--xsfx-*` arguments (info, verify, extract).
+    pub runtime_flags: bool,
+    /// The compressed payload is sealed with these parameters.
+    pub encryption: Option<Encryption>,
+    /// Refuse to run before this Unix time.
+    pub not_before: Option<u64>,
+    /// Refuse to run at or after this Unix time.
+    pub not_after: Option<u64>,
+    /// Containment applied right before the payload is executed.
+    pub sandbox: Option<SandboxPolicy>,
+    /// Resource limits, umask, nice level and working directory.
+    pub launch: Option<LaunchSettings>,
+    /// Keep the stub as parent of the payload (Linux).
+    pub supervise: Option<SupervisorPolicy>,
+    /// Linux namespaces the payload is started in.
+    pub isolate: Option<Namespaces>,
+    /// Absolute offset of the compressed payload (relative to the `.xsfx`
+    /// section with `--elf-section`); lets the stub find its trailer when
+    /// data was appended after it.
+    pub payload_offset: Option<u64>,
+    /// Compression of the payload.
+    pub codec: Codec,
+    /// Bundled asset files (Linux), stored after the payload.
+    pub assets: Option<AssetsInfo>,
+    /// Installer mode: the payload is a directory tree to extract.
+    pub install: Option<InstallSpec>,
+    /// The payload is an archive of several tools, chosen at run time by
+    /// `argv[0]` or the first argument.
+    pub multi_call: bool,
+    /// The payload is an archive of CPU-level variants of one program; the
+    /// stub runs the best one for the host.
+    pub variants: bool,
+    /// The payload is an archive of a dynamically linked ELF program, its
+    /// loader and its shared libraries.
+    pub bundled_libs: bool,
+    /// GNU build-ids of ELF payloads stripped at pack time, by archive
+    /// entry name (empty for a single payload).
+    pub build_ids: Vec<(String, Vec<u8>)>,
+}
+
+/// Payload compression format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// XZ / LZMA2, the native format.
+    #[default]
+    Xz = 0,
+    /// Raw Deflate, stored as a ZIP entry (`--zip`).
+    Deflate = 1,
+}
+
+fn meta_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn push_record(out: &mut Vec<u8>, tag: u8, value: &[u8]) {
+    out.push(tag);
+    out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+/// Flag records carry no value; their presence means "on".
+fn parse_flag(value: &[u8]) -> io::Result<bool> {
+    if !value.is_empty() {
+        return Err(meta_err("Invalid flag record"));
+    }
+    Ok(true)
+}
+
+/// `[name_len u8][name][id_len u8][id]` per entry.
+fn encode_build_ids(ids: &[(String, Vec<u8>)]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for (name, id) in ids {
+        if name.len() > u8::MAX as usize || id.is_empty() || id.len() > u8::MAX as usize {
+            return Err(meta_err("Invalid build-id entry"));
+        }
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        out.push(id.len() as u8);
+        out.extend_from_slice(id);
+    }
+    Ok(out)
+}
+
+fn parse_build_ids(mut value: &[u8]) -> io::Result<Vec<(String, Vec<u8>)>> {
+    let mut ids = Vec::new();
+    let take = |value: &mut &[u8]| -> io::Result<Vec<u8>> {
+        let (&len, rest) = value
+            .split_first()
+            .ok_or_else(|| meta_err("Truncated build-id record"))?;
+        let field = rest
+            .get(..len as usize)
+            .ok_or_else(|| meta_err("Truncated build-id record"))?;
+        *value = &rest[len as usize..];
+        Ok(field.to_vec())
+    };
+    while !value.is_empty() {
+        let name = String::from_utf8(take(&mut value)?)
+            .map_err(|_| meta_err("Build-id entry name is not valid UTF-8"))?;
+        let id = take(&mut value)?;
+        if id.is_empty() {
+            return Err(meta_err("Empty build-id"));
+        }
+        ids.push((name, id));
+    }
+    Ok(ids)
+}
+
+fn parse_u64(value: &[u8]) -> io::Result<u64> {
+    let bytes: [u8; 8] = value
+        .try_into()
+        .map_err(|_| meta_err("Invalid integer record"))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+impl Metadata {
+    /// Serialize to records plus footer, ready to be written before the trailer.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        if self.expose_env {
+            push_record(&mut out, TAG_EXPOSE_ENV, &[]);
+        }
+        if let Some(v) = &self.payload_version {
+            if v.len() > u16::MAX as usize {
+                return Err(meta_err("Payload version string too long"));
+            }
+            push_record(&mut out, TAG_PAYLOAD_VERSION, v.as_bytes());
+        }
+        if let Some(d) = &self.payload_sha256 {
+            push_record(&mut out, TAG_PAYLOAD_SHA256, d);
+        }
+        if self.debug {
+            push_record(&mut out, TAG_DEBUG, &[]);
+        }
+        if let Some(len) = self.payload_len {
+            push_record(&mut out, TAG_PAYLOAD_LEN, &len.to_le_bytes());
+        }
+        if let Some(max) = self.cache_max_size {
+            push_record(&mut out, TAG_CACHE, &max.to_le_bytes());
+        }
+        if self.runtime_flags {
+            push_record(&mut out, TAG_RUNTIME_FLAGS, &[]);
+        }
+        if let Some(enc) = &self.encryption {
+            push_record(&mut out, TAG_ENCRYPTION, &enc.to_bytes());
+        }
+        if let Some(t) = self.not_before {
+            push_record(&mut out, TAG_NOT_BEFORE, &t.to_le_bytes());
+        }
+        if let Some(t) = self.not_after {
+            push_record(&mut out, TAG_NOT_AFTER, &t.to_le_bytes());
+        }
+        if let Some(policy) = &self.sandbox {
+            let value = policy.to_bytes();
+            if value.len() > u16::MAX as usize {
+                return Err(meta_err("Sandbox policy too long"));
+            }
+            push_record(&mut out, TAG_SANDBOX, &value);
+        }
+        if let Some(launch) = &self.launch {
+            let value = launch.to_bytes();
+            if value.len() > u16::MAX as usize {
+                return Err(meta_err("Launch settings too long"));
+            }
+            push_record(&mut out, TAG_LAUNCH, &value);
+        }
+        if let Some(policy) = &self.supervise {
+            let value = policy.to_bytes();
+            if value.len() > u16::MAX as usize {
+                return Err(meta_err("Supervisor policy too long"));
+            }
+            push_record(&mut out, TAG_SUPERVISE, &value);
+        }
+        if let Some(ns) = self.isolate {
+            push_record(&mut out, TAG_ISOLATE, &[ns.to_byte()]);
+        }
+        if let Some(offset) = self.payload_offset {
+            push_record(&mut out, TAG_PAYLOAD_OFFSET, &offset.to_le_bytes());
+        }
+        if self.codec != Codec::Xz {
+            push_record(&mut out, TAG_CODEC, &[self.codec as u8]);
+        }
+        if let Some(assets) = &self.assets {
+            push_record(&mut out, TAG_ASSETS, &assets.to_bytes());
+        }
+        if let Some(install) = &self.install {
+            push_record(&mut out, TAG_INSTALL, &install.to_bytes());
+        }
+        if self.multi_call {
+            push_record(&mut out, TAG_MULTI_CALL, &[]);
+        }
+        if self.variants {
+            push_record(&mut out, TAG_VARIANTS, &[]);
+        }
+        if self.bundled_libs {
+            push_record(&mut out, TAG_BUNDLED_LIBS, &[]);
+        }
+        if !self.build_ids.is_empty() {
+            let value = encode_build_ids(&self.build_ids)?;
+            if value.len() > u16::MAX as usize {
+                return Err(meta_err("Too many build-ids"));
+            }
+            push_record(&mut out, TAG_BUILD_IDS, &value);
+        }
+        if out.len() as u64 + METADATA_FOOTER_SIZE > METADATA_MAX_LEN {
+            return Err(meta_err("Metadata block too large"));
+        }
+        let records_len = out.len() as u32;
+        out.extend_from_slice(&records_len.to_le_bytes());
+        Ok(out)
+    }
+
+    /// Associated data for payload encryption: the canonical record
+    /// encoding, so every recorded setting is authenticated.
+    pub fn aad(&self) -> io::Result<Vec<u8>> {
+        let mut bytes = self.to_bytes()?;
+        bytes.truncate(bytes.len() - METADATA_FOOTER_SIZE as usize);
+        Ok(bytes)
+    }
+
+    /// Parse the record area of a metadata block (without the footer).
+    pub fn from_records(mut data: &[u8]) -> io::Result<Self> {
+        let mut meta = Metadata::default();
+        while !data.is_empty() {
+            if data.len() < 3 {
+                return Err(meta_err("Truncated metadata record header"));
+            }
+            let tag = data[0];
+            let len = u16::from_le_bytes([data[1], data[2]]) as usize;
+            if data.len() - 3 < len {
+                return Err(meta_err("Truncated metadata record value"));
+            }
+            let value = &data[3..3 + len];
+            data = &data[3 + len..];
+            meta.apply_record(tag, value)?;
+        }
+        Ok(meta)
+    }
+
+    fn apply_record(&mut self, tag: u8, value: &[u8]) -> io::Result<()> {
+        match tag {
+            TAG_EXPOSE_ENV => self.expose_env = parse_flag(value)?,
+            TAG_DEBUG => self.debug = parse_flag(value)?,
+            TAG_RUNTIME_FLAGS => self.runtime_flags = parse_flag(value)?,
+            TAG_PAYLOAD_VERSION => {
+                let v = std::str::from_utf8(value)
+                    .map_err(|_| meta_err("Payload version is not valid UTF-8"))?;
+                self.payload_version = Some(v.to_string());
+            }
+            TAG_PAYLOAD_SHA256 => {
+                let digest: [u8; SHA256_LEN] = value
+                    .try_into()
+                    .map_err(|_| meta_err("Invalid payload digest length"))?;
+                self.payload_sha256 = Some(digest);
+            }
+            TAG_PAYLOAD_LEN => self.payload_len = Some(parse_u64(value)?),
+            TAG_CACHE => self.cache_max_size = Some(parse_u64(value)?),
+            TAG_ENCRYPTION => self.encryption = Some(Encryption::from_bytes(value)?),
+            TAG_NOT_BEFORE => self.not_before = Some(parse_u64(value)?),
+            TAG_NOT_AFTER => self.not_after = Some(parse_u64(value)?),
+            TAG_PAYLOAD_OFFSET => self.payload_offset = Some(parse_u64(value)?),
+            TAG_CODEC => {
+                self.codec = match value {
+                    [1] => Codec::Deflate,
+                    _ => return Err(meta_err("Invalid codec record")),
+                }
+            }
+            TAG_ASSETS => self.assets = Some(AssetsInfo::from_bytes(value)?),
+            TAG_MULTI_CALL => self.multi_call = parse_flag(value)?,
+            TAG_VARIANTS => self.variants = parse_flag(value)?,
+            TAG_BUNDLED_LIBS => self.bundled_libs = parse_flag(value)?,
+            TAG_BUILD_IDS => self.build_ids = parse_build_ids(value)?,
+            TAG_INSTALL => self.install = Some(InstallSpec::from_bytes(value)?),
+            TAG_SANDBOX => self.sandbox = Some(SandboxPolicy::from_bytes(value)?),
+            TAG_LAUNCH => self.launch = Some(LaunchSettings::from_bytes(value)?),
+            TAG_SUPERVISE => self.supervise = Some(SupervisorPolicy::from_bytes(value)?),
+            TAG_ISOLATE => match value {
+                [b] => self.isolate = Some(Namespaces::from_byte(*b)?),
+                _ => return Err(meta_err("Invalid namespace record")),
+            },
+            _ => return Err(meta_err("Unknown metadata record")),
+        }
+        Ok(())
+    }
+
+    /// Read the metadata block that ends at byte offset `end` of `r`.
+    /// Returns the parsed metadata and the total block length (records + footer).
+    pub fn read_before<R: Read + Seek>(r: &mut R, end: u64) -> io::Result<(Self, u64)> {
+        if end < METADATA_FOOTER_SIZE {
+            return Err(meta_err("File too small to contain metadata"));
+        }
+        r.seek(SeekFrom::Start(end - METADATA_FOOTER_SIZE))?;
+        let mut len_buf = [0u8; 4];
+        r.read_exact(&mut len_buf)?;
+        let records_len = u32::from_le_bytes(len_buf) as u64;
+        let block_len = records_len + METADATA_FOOTER_SIZE;
+        if block_len > METADATA_MAX_LEN || block_len > end {
+            return Err(meta_err("Invalid metadata length"));
+        }
+        r.seek(SeekFrom::Start(end - block_len))?;
+        let mut records = vec![0u8; records_len as usize];
+        r.read_exact(&mut records)?;
+        Ok((Self::from_records(&records)?, block_len))
+    }
+
+    /// Environment variables to export to the payload, or none when
+    /// `expose_env` was not requested at pack time.
+    pub fn runtime_env(&self, sfx_path: &Path) -> Vec<(&'static str, OsString)> {
+        let mut vars = Vec::new();
+        if !self.expose_env {
+            return vars;
+        }
+        vars.push((ENV_SFX_PATH, sfx_path.as_os_str().to_os_string()));
+        if let Some(dir) = sfx_path.parent() {
+            vars.push((ENV_SFX_DIR, dir.as_os_str().to_os_string()));
+        }
+        if let Some(d) = &self.payload_sha256 {
+            vars.push((ENV_PAYLOAD_SHA256, to_hex(d).into()));
+        }
+        if let Some(v) = &self.payload_version {
+            vars.push((ENV_PAYLOAD_VERSION, v.into()));
+        }
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{Kdf, NONCE_LEN};
+    use crate::launch::WorkDir;
+    use std::io::Cursor;
+
+    fn sample() -> Metadata {
+        Metadata {
+            expose_env: true,
+            payload_version: Some("1.2.3".into()),
+            payload_sha256: Some([0xAB; SHA256_LEN]),
+            debug: true,
+            payload_len: Some(4096),
+            cache_max_size: Some(1 << 20),
+            runtime_flags: true,
+            encryption: Some(Encryption {
+                kdf: Kdf::Raw,
+                nonce: [0x11; NONCE_LEN],
+            }),
+            not_before: Some(1_700_000_000),
+            not_after: Some(1_800_000_000),
+            sandbox: Some(SandboxPolicy {
+                no_new_privs: true,
+                seccomp_deny: vec!["ptrace".into()],
+                fs_read: vec!["/usr".into()],
+                ..SandboxPolicy::default()
+            }),
+            launch: Some(LaunchSettings {
+                umask: Some(0o027),
+                workdir: Some(WorkDir::SfxDir),
+                ..LaunchSettings::default()
+            }),
+            supervise: Some(SupervisorPolicy {
+                post_exit: Some("true".into()),
+                max_restarts: 2,
+                ..SupervisorPolicy::default()
+            }),
+            isolate: Some(Namespaces {
+                user: true,
+                pid: true,
+                ..Namespaces::default()
+            }),
+            payload_offset: Some(550_000),
+            codec: Codec::Deflate,
+            assets: Some(AssetsInfo {
+                count: 2,
+                compressed_len: 300,
+                archive_len: 900,
+                sha256: [0xCD; SHA256_LEN],
+            }),
+            install: Some(InstallSpec {
+                default_dir: Some("~/apps/tool".into()),
+                post_extract: Some("./setup.sh".into()),
+            }),
+            multi_call: true,
+            variants: true,
+            bundled_libs: true,
+            build_ids: vec![(String::new(), vec![0x5A; 20]), ("tool".into(), vec![0x01])],
+        }
+    }
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        let meta = sample();
+        let bytes = meta.to_bytes().unwrap();
+        let records = &bytes[..bytes.len() - METADATA_FOOTER_SIZE as usize];
+        assert_eq!(Metadata::from_records(records).unwrap(), meta);
+    }
+
+    #[test]
+    fn test_metadata_aad_is_records() {
+        let meta = sample();
+        let bytes = meta.to_bytes().unwrap();
+        let aad = meta.aad().unwrap();
+        assert_eq!(aad, bytes[..bytes.len() - METADATA_FOOTER_SIZE as usize]);
+    }
+
+    #[test]
+    fn test_metadata_empty_is_footer_only() {
+        let bytes = Metadata::default().to_bytes().unwrap();
+        assert_eq!(bytes, [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_metadata_read_before() {
+        let meta = sample();
+        let block = meta.to_bytes().unwrap();
+        let mut file = b"PAYLOAD".to_vec();
+        file.extend_from_slice(&block);
+        let end = file.len() as u64;
+        file.extend_from_slice(b"TRAILER");
+        let (parsed, len) = Metadata::read_before(&mut Cursor::new(&file), end).unwrap();
+        assert_eq!(parsed, meta);
+        assert_eq!(len, block.len() as u64);
+    }
+
+    #[test]
+    fn test_runtime_env_disabled_by_default() {
+        let meta = Metadata {
+            expose_env: false,
+            ..sample()
+        };
+        assert!(meta.runtime_env(Path::new("/opt/app/tool")).is_empty());
+    }
+
+    #[test]
+    fn test_runtime_env_values() {
+        let vars = sample().runtime_env(Path::new("/opt/app/tool"));
+        let get = |k: &str| vars.iter().find(|(n, _)| *n == k).map(|(_, v)| v.clone());
+        assert_eq!(get(ENV_SFX_PATH).unwrap(), "/opt/app/tool");
+        assert_eq!(get(ENV_SFX_DIR).unwrap(), "/opt/app");
+        assert_eq!(get(ENV_PAYLOAD_SHA256).unwrap(), "ab".repeat(32).as_str());
+        assert_eq!(get(ENV_PAYLOAD_VERSION).unwrap(), "1.2.3");
+    }
+
+    #[test]
+    fn test_runtime_env_without_optional_fields() {
+        let meta = Metadata {
+            expose_env: true,
+            ..Metadata::default()
+        };
+        let vars = meta.runtime_env(Path::new("/opt/app/tool"));
+        assert_eq!(vars.len(), 2);
+    }
+
+    #[test]
+    fn test_sec_uc004_unknown_tag_rejected() {
+        let result = Metadata::from_records(&[0x7F, 0, 0]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_truncated_record_header() {
+        assert!(Metadata::from_records(&[TAG_EXPOSE_ENV, 0]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_truncated_record_value() {
+        assert!(Metadata::from_records(&[TAG_PAYLOAD_VERSION, 10, 0, b'x']).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_bad_digest_length() {
+        assert!(Metadata::from_records(&[TAG_PAYLOAD_SHA256, 2, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_expose_env_with_value() {
+        assert!(Metadata::from_records(&[TAG_EXPOSE_ENV, 1, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc005_debug_with_value() {
+        assert!(Metadata::from_records(&[TAG_DEBUG, 1, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc008_runtime_flags_with_value() {
+        assert!(Metadata::from_records(&[TAG_RUNTIME_FLAGS, 1, 0, 1]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc010_timestamp_wrong_size() {
+        assert!(Metadata::from_records(&[TAG_NOT_AFTER, 2, 0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc006_payload_len_wrong_size() {
+        assert!(Metadata::from_records(&[TAG_PAYLOAD_LEN, 4, 0, 1, 2, 3, 4]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_version_not_utf8() {
+        assert!(Metadata::from_records(&[TAG_PAYLOAD_VERSION, 2, 0, 0xFF, 0xFE]).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_version_too_long() {
+        let meta = Metadata {
+            payload_version: Some("x".repeat(70_000)),
+            ..Metadata::default()
+        };
+        assert!(meta.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_block_too_large() {
+        let meta = Metadata {
+            payload_version: Some("x".repeat(65_530)),
+            ..Metadata::default()
+        };
+        assert!(meta.to_bytes().is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_read_before_length_exceeds_file() {
+        let file = 1000u32.to_le_bytes();
+        assert!(Metadata::read_before(&mut Cursor::new(&file), 4).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_read_before_too_small() {
+        assert!(Metadata::read_before(&mut Cursor::new(&[0u8; 2]), 2).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc004_read_before_length_over_max() {
+        let mut file = vec![0u8; 100];
+        file.extend_from_slice(&(METADATA_MAX_LEN as u32).to_le_bytes());
+        let end = file.len() as u64;
+        assert!(Metadata::read_before(&mut Cursor::new(&file), end).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc024_bad_build_ids() {
+        for value in [&[1u8][..], &[0, 0], &[1, b'a', 3, 1], &[1, 0xFF, 1, 7]] {
+            let mut records = vec![TAG_BUILD_IDS, value.len() as u8, 0];
+            records.extend_from_slice(value);
+            assert!(Metadata::from_records(&records).is_err(), "{:?}", value);
+        }
+        let long_name = Metadata {
+            build_ids: vec![("x".repeat(256), vec![1])],
+            ..Metadata::default()
+        };
+        assert!(long_name.to_bytes().is_err());
+    }
+}
@@ -0,0 +1,319 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::layout::Region;
+
+/// Images start on this boundary, so the launcher can copy them with a
+/// plain block-sized `dd`. The launcher script fills the first block.
+pub const IMAGE_ALIGN: u64 = 4096;
+
+/// Set by the launcher to the descriptor of the already unlinked image
+/// copy it started through `/proc/self/fd`; the stub closes it.
+pub const ENV_LAUNCHER_FD: &str = "XSFX_LAUNCHER_FD";
+/// Fallback without `/proc`: set by the launcher to the temporary copy of
+/// the image it started; the stub deletes it once it has opened itself.
+pub const ENV_UNLINK_SELF: &str = "XSFX_UNLINK_SELF";
+/// Set by the launcher to its own path (`$0`).
+pub const ENV_LAUNCHER: &str = "XSFX_LAUNCHER";
+
+const HEADER: &str = "#!/bin/sh\n# xsfx multi-arch launcher\n";
+const IMAGE_LINE: &str = "# xsfx-image ";
+
+/// A CPU architecture a launcher can dispatch to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LauncherArch {
+    /// Name used in the launcher and in `--arch-payload`.
+    pub name: &'static str,
+    /// `case` pattern matching `uname -m`.
+    pub uname: &'static str,
+    /// Stub catalog entry.
+    pub target: &'static str,
+}
+
+/// Architectures of a `--multiarch` SFX, in file order.
+pub const LAUNCHER_ARCHES: [LauncherArch; 2] = [
+    LauncherArch {
+        name: "x86_64",
+        uname: "x86_64|amd64",
+        target: "x86_64-unknown-linux-musl",
+    },
+    LauncherArch {
+        name: "aarch64",
+        uname: "aarch64|arm64|armv8*",
+        target: "aarch64-unknown-linux-musl",
+    },
+];
+
+/// One complete SFX embedded in a launcher.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchImage {
+    pub arch: String,
+    pub offset: u64,
+    pub len: u64,
+}
+
+impl ArchImage {
+    /// A reader over just this image, for `layout::read_layout` and the
+    /// rest of the SFX parsing code.
+    pub fn reader<R: Read + Seek>(&self, inner: R) -> Region<R> {
+        Region::new(inner, self.offset, self.len)
+    }
+}
+
+fn launcher_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn blocks(len: u64) -> u64 {
+    len.div_ceil(IMAGE_ALIGN)
+}
+
+/// Build a launcher: a POSIX `sh` script in the first block that picks an
+/// image by `uname -m` and runs a copy of it, followed by the images, each
+/// starting on an `IMAGE_ALIGN` boundary.
+///
+/// The copy goes to the first directory that allows executing files, out
+/// of `$XDG_RUNTIME_DIR`, `/dev/shm` and `$TMPDIR` (default `/tmp`). It is
+/// opened as fd 9, unlinked and run as `/proc/self/fd/9`, so no file is
+/// left behind. Only without `/proc` is the named copy run and left to
+/// the stub to delete.
+pub fn build_launcher(images: &[(LauncherArch, &[u8])]) -> io::Result<Vec<u8>> {
+    let mut placed = Vec::with_capacity(images.len());
+    let mut offset = IMAGE_ALIGN;
+    for (arch, data) in images {
+        placed.push((arch, offset, data.len() as u64));
+        offset += blocks(data.len() as u64) * IMAGE_ALIGN;
+    }
+    let mut script = String::from(HEADER);
+    for (arch, offset, len) in &placed {
+        script.push_str(&format!("{}{} {} {}\n", IMAGE_LINE, arch.name, offset, len));
+    }
+    script.push_str("case \"$(uname -m)\" in\n");
+    for (arch, offset, len) in &placed {
+        script.push_str(&format!(
+            "{}) skip={} count={} ;;\n",
+            arch.uname,
+            offset / IMAGE_ALIGN,
+            blocks(*len)
+        ));
+    }
+    script.push_str(concat!(
+        "*) echo \"$0: unsupported architecture $(uname -m)\" >&2; exit 244 ;;\n",
+        "esac\n",
+        "t=\n",
+        "for d in \"$XDG_RUNTIME_DIR\" /dev/shm \"${TMPDIR:-/tmp}\"; do\n",
+        "  [ -n \"$d\" ] && [ -d \"$d\" ] && [ -w \"$d\" ] || continue\n",
+        "  t=$(mktemp \"$d/.xsfx.XXXXXX\" 2>/dev/null) || continue\n",
+        "  if dd if=\"$0\" of=\"$t\" bs=4096 skip=$skip count=$count 2>/dev/null",
+        " && chmod 700 \"$t\" && [ -x \"$t\" ]; then\n",
+        "    break\n",
+        "  fi\n",
+        "  rm -f \"$t\"\n",
+        "  t=\n",
+        "done\n",
+        "[ -n \"$t\" ] || exit 240\n",
+        "export XSFX_LAUNCHER=\"$0\"\n",
+        "if [ -d /proc/self/fd ]; then\n",
+        "  exec 9<\"$t\"\n",
+        "  rm -f \"$t\"\n",
+        "  export XSFX_LAUNCHER_FD=9\n",
+        "  exec /proc/self/fd/9 \"$@\"\n",
+        "fi\n",
+        "export XSFX_UNLINK_SELF=\"$t\"\n",
+        "exec \"$t\" \"$@\"\n",
+        "exit 244\n",
+    ));
+    if script.len() as u64 > IMAGE_ALIGN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Launcher script too large",
+        ));
+    }
+    let mut out = script.into_bytes();
+    for ((_, data), (_, offset, _)) in images.iter().zip(&placed) {
+        out.resize(*offset as usize, b'\n');
+        out.extend_from_slice(data);
+    }
+    Ok(out)
+}
+
+/// List the images of a launcher, or `None` if `r` is not one.
+pub fn read_launcher<R: Read + Seek>(r: &mut R) -> io::Result<Option<Vec<ArchImage>>> {
+    let total_len = r.seek(SeekFrom::End(0))?;
+    r.seek(SeekFrom::Start(0))?;
+    let mut head = Vec::new();
+    r.take(IMAGE_ALIGN).read_to_end(&mut head)?;
+    if !head.starts_with(HEADER.as_bytes()) {
+        return Ok(None);
+    }
+    let mut images: Vec<ArchImage> = Vec::new();
+    let mut next_free = IMAGE_ALIGN;
+    for line in head[HEADER.len()..].split(|&b| b == b'\n') {
+        let Some(rest) = line.strip_prefix(IMAGE_LINE.as_bytes()) else {
+            break;
+        };
+        let rest = std::str::from_utf8(rest).map_err(|_| launcher_err("Invalid image line"))?;
+        let fields: Vec<&str> = rest.split(' ').collect();
+        let [arch, offset, len] = fields[..] else {
+            return Err(launcher_err("Invalid image line"));
+        };
+        let (Ok(offset), Ok(len)) = (offset.parse::<u64>(), len.parse::<u64>()) else {
+            return Err(launcher_err("Invalid image line"));
+        };
+        if offset < next_free
+            || offset % IMAGE_ALIGN != 0
+            || len == 0
+            || offset.checked_add(len).is_none_or(|end| end > total_len)
+        {
+            return Err(launcher_err("Image outside the launcher"));
+        }
+        next_free = offset + len;
+        images.push(ArchImage {
+            arch: arch.to_string(),
+            offset,
+            len,
+        });
+    }
+    if images.is_empty() {
+        return Err(launcher_err("Launcher without images"));
+    }
+    Ok(Some(images))
+}
+
+/// Called by the stub right after opening itself. If a launcher started
+/// this process, close the descriptor the image copy was run through, or
+/// without `/proc` delete the copy, and return the launcher's absolute
+/// path. Neither is touched unless it is the file `me` was opened from.
+/// All launcher variables are removed so the payload does not see them.
+#[cfg(unix)]
+pub fn adopt_launcher_copy(me: &std::fs::File) -> io::Result<Option<std::path::PathBuf>> {
+    use std::env;
+    use std::os::unix::fs::MetadataExt;
+    let fd = env::var(ENV_LAUNCHER_FD).ok();
+    let copy = env::var_os(ENV_UNLINK_SELF);
+    let launcher = env::var_os(ENV_LAUNCHER);
+    env::remove_var(ENV_LAUNCHER_FD);
+    env::remove_var(ENV_UNLINK_SELF);
+    env::remove_var(ENV_LAUNCHER);
+    let mine = me.metadata()?;
+    let is_me = |dev: u64, ino: u64| (dev, ino) == (mine.dev(), mine.ino());
+    if let Some(fd) = fd {
+        let Some(fd) = fd.parse::<i32>().ok().filter(|fd| *fd > 2) else {
+            return Ok(None);
+        };
+        let mut st: libc::stat = unsafe { std::mem::zeroed() };
+        if unsafe { libc::fstat(fd, &mut st) } != 0 || !is_me(st.st_dev as u64, st.st_ino as u64) {
+            return Ok(None);
+        }
+        unsafe { libc::close(fd) };
+    } else {
+        let Some(copy) = copy else {
+            return Ok(None);
+        };
+        let Ok(theirs) = std::fs::metadata(&copy) else {
+            return Ok(None);
+        };
+        if !is_me(theirs.dev(), theirs.ino()) {
+            return Ok(None);
+        }
+        std::fs::remove_file(&copy)?;
+    }
+    let cwd = env::current_dir()?;
+    Ok(launcher.map(|path| cwd.join(path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn sample() -> Vec<u8> {
+        let a = vec![0xAA; 5000];
+        let b = vec![0xBB; 100];
+        build_launcher(&[(LAUNCHER_ARCHES[0], &a), (LAUNCHER_ARCHES[1], &b)]).unwrap()
+    }
+
+    #[test]
+    fn test_launcher_roundtrip() {
+        let file = sample();
+        assert!(file.starts_with(b"#!/bin/sh\n"));
+        let images = read_launcher(&mut Cursor::new(&file)).unwrap().unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].arch, "x86_64");
+        assert_eq!((images[0].offset, images[0].len), (4096, 5000));
+        assert_eq!((images[1].offset, images[1].len), (3 * 4096, 100));
+        let mut data = Vec::new();
+        images[1]
+            .reader(Cursor::new(&file))
+            .read_to_end(&mut data)
+            .unwrap();
+        assert_eq!(data, [0xBB; 100]);
+        let text = String::from_utf8_lossy(&file[..4096]);
+        assert!(text.contains("x86_64|amd64) skip=1 count=2 ;;"));
+        assert!(text.contains("aarch64|arm64|armv8*) skip=3 count=1 ;;"));
+        // The unlinked copy run through its descriptor comes first; the
+        // named copy is only the fallback without /proc.
+        let by_fd = text.find("exec /proc/self/fd/9").unwrap();
+        assert!(by_fd < text.find("exec \"$t\"").unwrap());
+        assert!(text.find("rm -f \"$t\"\n  export").unwrap() < by_fd);
+    }
+
+    #[test]
+    fn test_read_launcher_not_a_launcher() {
+        let plain = vec![0x7F, b'E', b'L', b'F', 0, 0];
+        assert_eq!(read_launcher(&mut Cursor::new(&plain)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_sec_launcher_images_bounded() {
+        let mut file = sample();
+        file.truncate(3 * 4096 + 50);
+        assert!(read_launcher(&mut Cursor::new(&file)).is_err());
+        let forged = format!("{}{}x86_64 0 10\n", HEADER, IMAGE_LINE);
+        let mut file = forged.into_bytes();
+        file.resize(8192, 0);
+        assert!(read_launcher(&mut Cursor::new(&file)).is_err());
+    }
+
+    #[test]
+    fn test_region_seek() {
+        let data: Vec<u8> = (0..100).collect();
+        let image = ArchImage {
+            arch: "x".into(),
+            offset: 10,
+            len: 20,
+        };
+        let mut r = image.reader(Cursor::new(&data));
+        assert_eq!(r.seek(SeekFrom::End(-4)).unwrap(), 16);
+        let mut tail = Vec::new();
+        r.read_to_end(&mut tail).unwrap();
+        assert_eq!(tail, [26, 27, 28, 29]);
+        assert!(r.seek(SeekFrom::Current(-100)).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_adopt_launcher_copy_names_tool() {
+        use crate::multicall::select_tool;
+        use std::ffi::OsStr;
+        use std::os::unix::io::IntoRawFd;
+
+        let path = std::env::temp_dir().join(format!("xsfx-adopt-{}", std::process::id()));
+        std::fs::write(&path, b"image").unwrap();
+        let me = std::fs::File::open(&path).unwrap();
+        let fd = std::fs::File::open(&path).unwrap().into_raw_fd();
+        std::env::set_var(ENV_LAUNCHER_FD, fd.to_string());
+        std::env::set_var(ENV_LAUNCHER, "bin/fmt");
+        let launcher = adopt_launcher_copy(&me).unwrap().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(launcher.is_absolute() && launcher.ends_with("bin/fmt"));
+        assert!(std::env::var_os(ENV_LAUNCHER).is_none());
+        // argv[0] is the image copy; only the launcher path names the tool.
+        let names = ["lint", "fmt"];
+        let copy = OsStr::new("/proc/self/fd/9");
+        assert_eq!(select_tool(&names, Some(copy), &[]), None);
+        assert_eq!(
+            select_tool(&names, Some(launcher.as_os_str()), &[]),
+            Some((1, false))
+        );
+    }
+}
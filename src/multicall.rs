@@ -0,0 +1,125 @@
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+
+use crate::assets::validate_name;
+
+/// Tool names are plain file names that cannot be mistaken for options.
+pub fn validate_tool_name(name: &str) -> io::Result<()> {
+    if name.contains('/') || name.starts_with('-') || validate_name(name).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid tool name '{}'", name),
+        ));
+    }
+    Ok(())
+}
+
+/// Parse `--tool <path>[=<name>]`. Without `=name` the tool is named after
+/// its file.
+pub fn parse_tool(s: &str) -> Option<(String, String)> {
+    let (path, name) = match s.split_once('=') {
+        Some((path, name)) => (path, name.to_string()),
+        None => (s, Path::new(s).file_name()?.to_str()?.to_string()),
+    };
+    if path.is_empty() || validate_tool_name(&name).is_err() {
+        return None;
+    }
+    Some((path.to_string(), name))
+}
+
+/// Pick a tool by the basename of `argv0` (without a `.exe` suffix), or
+/// else by the first argument. Returns the tool index and whether the
+/// first argument named it (and must be dropped).
+pub fn select_tool(
+    names: &[&str],
+    argv0: Option<&OsStr>,
+    args: &[String],
+) -> Option<(usize, bool)> {
+    let position = |name: &str| names.iter().position(|n| *n == name);
+    let invoked = argv0
+        .and_then(|a| Path::new(a).file_name())
+        .and_then(|n| n.to_str())
+        .map(strip_exe);
+    if let Some(i) = invoked.and_then(position) {
+        return Some((i, false));
+    }
+    let i = position(args.first()?)?;
+    Some((i, true))
+}
+
+fn strip_exe(name: &str) -> &str {
+    let cut = name.len().saturating_sub(4);
+    match name.get(cut..) {
+        Some(ext) if ext.eq_ignore_ascii_case(".exe") => &name[..cut],
+        _ => name,
+    }
+}
+
+/// Message for an SFX started under no known tool name.
+pub fn unknown_tool_message(names: &[&str], args: &[String]) -> String {
+    let list = names.join(", ");
+    match args.first() {
+        Some(arg) => format!("Unknown tool '{}'. Available tools: {}", arg, list),
+        None => format!("Usage: <tool> [args...]. Available tools: {}", list),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [&str; 3] = ["fmt", "lint", "srv"];
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select_by_argv0() {
+        let argv0 = Some(OsStr::new("/usr/local/bin/lint"));
+        assert_eq!(
+            select_tool(&NAMES, argv0, &args(&["fmt"])),
+            Some((1, false))
+        );
+        let exe = Some(OsStr::new("srv.EXE"));
+        assert_eq!(select_tool(&NAMES, exe, &[]), Some((2, false)));
+        // Names are case-sensitive.
+        assert_eq!(select_tool(&NAMES, Some(OsStr::new("SRV")), &[]), None);
+    }
+
+    #[test]
+    fn test_select_by_first_argument() {
+        let argv0 = Some(OsStr::new("./mytools"));
+        assert_eq!(
+            select_tool(&NAMES, argv0, &args(&["fmt", "-w"])),
+            Some((0, true))
+        );
+        assert_eq!(select_tool(&NAMES, argv0, &args(&["nope"])), None);
+        assert_eq!(select_tool(&NAMES, argv0, &[]), None);
+        assert_eq!(select_tool(&NAMES, None, &args(&["srv"])), Some((2, true)));
+    }
+
+    #[test]
+    fn test_parse_tool() {
+        assert_eq!(
+            parse_tool("target/release/fmt"),
+            Some(("target/release/fmt".into(), "fmt".into()))
+        );
+        assert_eq!(
+            parse_tool("build/a.out=lint"),
+            Some(("build/a.out".into(), "lint".into()))
+        );
+        assert_eq!(parse_tool("x=sub/dir"), None);
+        assert_eq!(parse_tool("x=--help"), None);
+        assert_eq!(parse_tool("=fmt"), None);
+        assert_eq!(parse_tool("x="), None);
+    }
+
+    #[test]
+    fn test_unknown_tool_message() {
+        let msg = unknown_tool_message(&NAMES, &args(&["x"]));
+        assert_eq!(msg, "Unknown tool 'x'. Available tools: fmt, lint, srv");
+        assert!(unknown_tool_message(&NAMES, &[]).starts_with("Usage:"));
+    }
+}
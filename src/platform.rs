@@ -0,0 +1,364 @@
+use std::fmt;
+use std::io;
+
+use crate::elf::{is_elf, EM_386, EM_AARCH64, EM_ARM, EM_RISCV, EM_X86_64};
+
+const ELFOSABI_SYSV: u8 = 0;
+const ELFOSABI_GNU: u8 = 3;
+
+const PE_MACHINE_I386: u16 = 0x014C;
+const PE_MACHINE_ARMNT: u16 = 0x01C4;
+const PE_MACHINE_AMD64: u16 = 0x8664;
+const PE_MACHINE_ARM64: u16 = 0xAA64;
+const PE_MACHINE_RISCV64: u16 = 0x5064;
+
+const MH_MAGIC: u32 = 0xFEED_FACE;
+const MH_MAGIC_64: u32 = 0xFEED_FACF;
+const FAT_MAGIC: u32 = 0xCAFE_BABE;
+const FAT_MAGIC_64: u32 = 0xCAFE_BABF;
+/// More architectures than this means a Java class file, which shares
+/// `FAT_MAGIC`.
+const FAT_MAX_ARCHS: u32 = 20;
+const CPU_ARCH_ABI64: u32 = 0x0100_0000;
+const CPU_TYPE_X86: u32 = 7;
+const CPU_TYPE_ARM: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Linux,
+    MacOs,
+    Windows,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86,
+    X86_64,
+    Arm,
+    Aarch64,
+    Riscv64,
+}
+
+/// Operating system and architecture an executable is built for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub os: Os,
+    pub arch: Arch,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let os = match self.os {
+            Os::Linux => "Linux",
+            Os::MacOs => "macOS",
+            Os::Windows => "Windows",
+        };
+        let arch = match self.arch {
+            Arch::X86 => "x86",
+            Arch::X86_64 => "x86_64",
+            Arch::Arm => "ARM",
+            Arch::Aarch64 => "aarch64",
+            Arch::Riscv64 => "riscv64",
+        };
+        write!(f, "{} {}", os, arch)
+    }
+}
+
+/// Platform of a target triple, if it is one xsfx knows.
+pub fn target_platform(target: &str) -> Option<Platform> {
+    let arch = match target.split('-').next()? {
+        "x86_64" => Arch::X86_64,
+        "aarch64" => Arch::Aarch64,
+        "i386" | "i586" | "i686" => Arch::X86,
+        a if a.starts_with("arm") || a.starts_with("thumb") => Arch::Arm,
+        a if a.starts_with("riscv64") => Arch::Riscv64,
+        _ => return None,
+    };
+    let os = if target.contains("-linux") {
+        Os::Linux
+    } else if target.contains("-apple-darwin") {
+        Os::MacOs
+    } else if target.contains("-windows") {
+        Os::Windows
+    } else {
+        return None;
+    };
+    Some(Platform { os, arch })
+}
+
+fn u16_le(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u32_be(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn elf_platform(data: &[u8]) -> Option<Platform> {
+    if !matches!(*data.get(7)?, ELFOSABI_SYSV | ELFOSABI_GNU) {
+        return None;
+    }
+    let machine = match data.get(5)? {
+        1 => u16_le(data, 18)?,
+        _ => u16::from_be_bytes(data.get(18..20)?.try_into().ok()?),
+    };
+    let arch = match machine {
+        EM_X86_64 => Arch::X86_64,
+        EM_AARCH64 => Arch::Aarch64,
+        EM_386 => Arch::X86,
+        EM_ARM => Arch::Arm,
+        EM_RISCV if data.get(4) == Some(&2) => Arch::Riscv64,
+        _ => return None,
+    };
+    Some(Platform {
+        os: Os::Linux,
+        arch,
+    })
+}
+
+fn pe_platform(data: &[u8]) -> Option<Platform> {
+    let pe = u32_le(data, 0x3C)? as usize;
+    if data.get(pe..pe.checked_add(4)?)? != b"PE\0\0" {
+        return None;
+    }
+    let arch = match u16_le(data, pe + 4)? {
+        PE_MACHINE_AMD64 => Arch::X86_64,
+        PE_MACHINE_ARM64 => Arch::Aarch64,
+        PE_MACHINE_I386 => Arch::X86,
+        PE_MACHINE_ARMNT => Arch::Arm,
+        PE_MACHINE_RISCV64 => Arch::Riscv64,
+        _ => return None,
+    };
+    Some(Platform {
+        os: Os::Windows,
+        arch,
+    })
+}
+
+fn macho_arch(cputype: u32) -> Option<Arch> {
+    match cputype {
+        CPU_TYPE_X86 => Some(Arch::X86),
+        t if t == CPU_TYPE_X86 | CPU_ARCH_ABI64 => Some(Arch::X86_64),
+        CPU_TYPE_ARM => Some(Arch::Arm),
+        t if t == CPU_TYPE_ARM | CPU_ARCH_ABI64 => Some(Arch::Aarch64),
+        _ => None,
+    }
+}
+
+fn macho_platforms(data: &[u8]) -> Vec<Platform> {
+    let mac = |arch| Platform {
+        os: Os::MacOs,
+        arch,
+    };
+    match (u32_le(data, 0), u32_be(data, 0)) {
+        (Some(MH_MAGIC | MH_MAGIC_64), _) => u32_le(data, 4)
+            .and_then(macho_arch)
+            .map(mac)
+            .into_iter()
+            .collect(),
+        (_, Some(magic @ (FAT_MAGIC | FAT_MAGIC_64))) => {
+            let count = u32_be(data, 4).unwrap_or(0);
+            if count > FAT_MAX_ARCHS {
+                return Vec::new();
+            }
+            let entry_len = if magic == FAT_MAGIC { 20 } else { 32 };
+            (0..count as usize)
+                .filter_map(|i| u32_be(data, 8 + i * entry_len))
+                .filter_map(macho_arch)
+                .map(mac)
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Platforms an executable runs on, from its ELF, PE or Mach-O headers.
+/// Universal Mach-O binaries list every architecture they contain. Empty
+/// for anything else, such as scripts.
+pub fn detect_platforms(data: &[u8]) -> Vec<Platform> {
+    if is_elf(data) {
+        elf_platform(data).into_iter().collect()
+    } else if data.starts_with(b"MZ") {
+        pe_platform(data).into_iter().collect()
+    } else {
+        macho_platforms(data)
+    }
+}
+
+fn list(platforms: &[Platform]) -> String {
+    let names: Vec<String> = platforms.iter().map(|p| p.to_string()).collect();
+    names.join(" / ")
+}
+
+/// Refuse a payload detected as built for other platforms than `target`.
+/// Undetected payloads and unknown targets pass.
+pub fn check_target(payload: &[Platform], target: &str) -> io::Result<()> {
+    let Some(wanted) = target_platform(target) else {
+        return Ok(());
+    };
+    if payload.is_empty() || payload.contains(&wanted) {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "Payload is a {} executable, but target {} is {}",
+            list(payload),
+            target,
+            wanted
+        ),
+    ))
+}
+
+/// Pick the target from `available` that runs `payload`: `default` if it
+/// does, else a static musl stub for Linux, else the first match.
+pub fn pick_target<'a>(
+    payload: &[Platform],
+    available: &[&'a str],
+    default: &str,
+) -> io::Result<&'a str> {
+    let runs = |t: &&str| target_platform(t).is_some_and(|p| payload.contains(&p));
+    let matching: Vec<&'a str> = available.iter().copied().filter(runs).collect();
+    matching
+        .iter()
+        .find(|t| **t == default)
+        .or_else(|| matching.iter().find(|t| t.ends_with("-linux-musl")))
+        .or_else(|| matching.first())
+        .copied()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "No stub for a {} payload in this build (available: {})",
+                    list(payload),
+                    available.join(", ")
+                ),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINUX_X64: Platform = Platform {
+        os: Os::Linux,
+        arch: Arch::X86_64,
+    };
+    const MAC_ARM: Platform = Platform {
+        os: Os::MacOs,
+        arch: Arch::Aarch64,
+    };
+    const WIN_X64: Platform = Platform {
+        os: Os::Windows,
+        arch: Arch::X86_64,
+    };
+
+    fn elf(machine: u16) -> Vec<u8> {
+        let mut d = vec![0u8; 64];
+        d[..4].copy_from_slice(b"\x7FELF");
+        d[4] = 2;
+        d[5] = 1;
+        d[18..20].copy_from_slice(&machine.to_le_bytes());
+        d
+    }
+
+    fn pe(machine: u16) -> Vec<u8> {
+        let mut d = vec![0u8; 0x90];
+        d[..2].copy_from_slice(b"MZ");
+        d[0x3C..0x40].copy_from_slice(&0x80u32.to_le_bytes());
+        d[0x80..0x84].copy_from_slice(b"PE\0\0");
+        d[0x84..0x86].copy_from_slice(&machine.to_le_bytes());
+        d
+    }
+
+    #[test]
+    fn test_detect_elf_and_pe() {
+        assert_eq!(detect_platforms(&elf(EM_X86_64)), [LINUX_X64]);
+        let mut freebsd = elf(EM_X86_64);
+        freebsd[7] = 9;
+        assert!(detect_platforms(&freebsd).is_empty());
+        assert_eq!(detect_platforms(&pe(PE_MACHINE_AMD64)), [WIN_X64]);
+        assert_eq!(
+            detect_platforms(&pe(PE_MACHINE_ARM64))[0].arch,
+            Arch::Aarch64
+        );
+        assert!(detect_platforms(b"#!/bin/sh\necho hi\n").is_empty());
+    }
+
+    #[test]
+    fn test_detect_macho() {
+        let mut thin = MH_MAGIC_64.to_le_bytes().to_vec();
+        thin.extend_from_slice(&(CPU_TYPE_ARM | CPU_ARCH_ABI64).to_le_bytes());
+        assert_eq!(detect_platforms(&thin), [MAC_ARM]);
+
+        let mut fat = FAT_MAGIC.to_be_bytes().to_vec();
+        fat.extend_from_slice(&2u32.to_be_bytes());
+        for cpu in [CPU_TYPE_X86 | CPU_ARCH_ABI64, CPU_TYPE_ARM | CPU_ARCH_ABI64] {
+            fat.extend_from_slice(&cpu.to_be_bytes());
+            fat.extend_from_slice(&[0; 16]);
+        }
+        let platforms = detect_platforms(&fat);
+        assert_eq!(platforms.len(), 2);
+        assert!(platforms.contains(&MAC_ARM));
+
+        // A Java class file: CAFEBABE, then minor 0 / major 65.
+        let class = [0xCA, 0xFE, 0xBA, 0xBE, 0, 0, 0, 65];
+        assert!(detect_platforms(&class).is_empty());
+    }
+
+    #[test]
+    fn test_check_target() {
+        assert!(check_target(&[LINUX_X64], "x86_64-unknown-linux-musl").is_ok());
+        let err = check_target(&[WIN_X64], "x86_64-unknown-linux-musl").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(err.to_string().contains("Windows x86_64"));
+        assert!(check_target(&[LINUX_X64], "aarch64-unknown-linux-musl").is_err());
+        assert!(check_target(&[], "aarch64-unknown-linux-musl").is_ok());
+        assert!(check_target(&[LINUX_X64], "mips-unknown-linux-gnu").is_ok());
+    }
+
+    #[test]
+    fn test_pick_target() {
+        let available = [
+            "x86_64-unknown-linux-gnu",
+            "x86_64-unknown-linux-musl",
+            "aarch64-apple-darwin",
+            "x86_64-pc-windows-msvc",
+        ];
+        let default = "x86_64-unknown-linux-gnu";
+        assert_eq!(
+            pick_target(&[LINUX_X64], &available, default).unwrap(),
+            default
+        );
+        assert_eq!(
+            pick_target(&[LINUX_X64], &available, "aarch64-apple-darwin").unwrap(),
+            "x86_64-unknown-linux-musl"
+        );
+        assert_eq!(
+            pick_target(&[MAC_ARM, WIN_X64], &available, default).unwrap(),
+            "aarch64-apple-darwin"
+        );
+        let err = pick_target(&[LINUX_X64], &available[2..], default).unwrap_err();
+        assert!(err.to_string().contains("Linux x86_64"));
+    }
+
+    #[test]
+    fn test_target_platform() {
+        assert_eq!(
+            target_platform("x86_64-unknown-linux-musl"),
+            Some(LINUX_X64)
+        );
+        assert_eq!(target_platform("aarch64-apple-darwin"), Some(MAC_ARM));
+        assert_eq!(
+            target_platform("armv7-unknown-linux-gnueabihf").map(|p| p.arch),
+            Some(Arch::Arm)
+        );
+        assert_eq!(target_platform("x86_64-unknown-freebsd"), None);
+        assert_eq!(target_platform("powerpc64le-unknown-linux-gnu"), None);
+    }
+}
@@ -0,0 +1,325 @@
+use std::fmt::Write as _;
+use std::io;
+use std::path::PathBuf;
+
+use crate::digest::to_hex;
+use crate::launch::{LaunchSettings, WorkDir};
+use crate::metadata::{Codec, Metadata};
+use crate::sandbox::SandboxPolicy;
+use crate::supervise::SupervisorPolicy;
+use crate::validity::format_utc;
+
+/// Prefix reserved for stub-level flags. Only leading arguments are
+/// inspected, so a payload that takes `--xsfx-*` options of its own keeps
+/// receiving them after the first non-reserved argument.
+pub const FLAG_PREFIX: &str = "--xsfx-";
+
+/// What the stub should do instead of (or before) running the payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuntimeAction {
+    /// Print the embedded metadata and exit.
+    Info,
+    /// Decompress and check the payload against its recorded digest, then exit.
+    Verify,
+    /// Write the verified payload to a file (`-` for stdout) and exit.
+    Extract(PathBuf),
+}
+
+fn usage_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Split leading `--xsfx-*` flags from the payload arguments.
+///
+/// Returns the requested action, if any, and the remaining arguments.
+/// An unknown reserved flag, a missing value or more than one action is
+/// an error rather than being passed through to the payload.
+pub fn split_runtime_flags(args: &[String]) -> io::Result<(Option<RuntimeAction>, Vec<String>)> {
+    let mut action = None;
+    let mut idx = 0;
+    while let Some(arg) = args.get(idx) {
+        if !arg.starts_with(FLAG_PREFIX) {
+            break;
+        }
+        let next = match arg.as_str() {
+            "--xsfx-info" => RuntimeAction::Info,
+            "--xsfx-verify" => RuntimeAction::Verify,
+            "--xsfx-extract" => {
+                idx += 1;
+                match args.get(idx) {
+                    Some(path) if !path.is_empty() => RuntimeAction::Extract(PathBuf::from(path)),
+                    _ => return Err(usage_err("--xsfx-extract requires a path".into())),
+                }
+            }
+            other => return Err(usage_err(format!("Unknown stub flag {}", other))),
+        };
+        if action.replace(next).is_some() {
+            return Err(usage_err("Only one --xsfx-* action may be given".into()));
+        }
+        idx += 1;
+    }
+    Ok((action, args[idx..].to_vec()))
+}
+
+/// Human-readable description of an SFX for `--xsfx-info`.
+pub fn format_info(payload_offset: u64, compressed_len: u64, meta: &Metadata) -> String {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let size = meta
+        .payload_len
+        .map_or("unknown".to_string(), |len| format!("{} bytes", len));
+    let digest = meta
+        .payload_sha256
+        .map_or("not recorded".to_string(), |d| to_hex(&d));
+    let cache = meta
+        .cache_max_size
+        .map_or("no".to_string(), |max| format!("yes (max {} bytes)", max));
+    let encryption = match &meta.encryption {
+        None => "no",
+        Some(enc) if enc.is_passphrase() => "passphrase (Argon2id, ChaCha20-Poly1305)",
+        Some(_) => "key (ChaCha20-Poly1305)",
+    };
+    let codec = match meta.codec {
+        Codec::Xz => "xz",
+        Codec::Deflate => "deflate (ZIP entry)",
+    };
+    let mut out = String::new();
+    let _ = writeln!(out, "payload offset:     {}", payload_offset);
+    let _ = writeln!(out, "compressed payload: {} bytes", compressed_len);
+    let _ = writeln!(out, "compression:        {}", codec);
+    let _ = writeln!(out, "payload size:       {}", size);
+    let _ = writeln!(out, "payload sha256:     {}", digest);
+    if let Some(v) = &meta.payload_version {
+        let _ = writeln!(out, "payload version:    {}", v);
+    }
+    for (name, id) in &meta.build_ids {
+        let _ = match name.as_str() {
+            "" => writeln!(out, "build-id:           {}", to_hex(id)),
+            name => writeln!(out, "build-id:           {} ({})", to_hex(id), name),
+        };
+    }
+    let _ = writeln!(out, "expose env:         {}", yes_no(meta.expose_env));
+    let _ = writeln!(out, "debug:              {}", yes_no(meta.debug));
+    let _ = writeln!(out, "cache:              {}", cache);
+    let _ = writeln!(out, "runtime flags:      {}", yes_no(meta.runtime_flags));
+    let _ = writeln!(out, "encryption:         {}", encryption);
+    if meta.multi_call {
+        let _ = writeln!(out, "multi-call:         yes");
+    }
+    if meta.variants {
+        let _ = writeln!(out, "cpu variants:       yes");
+    }
+    if meta.bundled_libs {
+        let _ = writeln!(out, "bundled libraries:  yes");
+    }
+    if let Some(assets) = &meta.assets {
+        let _ = writeln!(
+            out,
+            "assets:             {} files ({} bytes)",
+            assets.count, assets.archive_len
+        );
+    }
+    if let Some(spec) = &meta.install {
+        let dir = spec
+            .default_dir
+            .as_deref()
+            .unwrap_or("none (--target-dir required)");
+        let _ = writeln!(out, "installer:          default dir {}", dir);
+        if let Some(cmd) = &spec.post_extract {
+            let _ = writeln!(out, "post-extract:       {}", cmd);
+        }
+    }
+    if let Some(t) = meta.not_before {
+        let _ = writeln!(out, "not before:         {}", format_utc(t));
+    }
+    if let Some(t) = meta.not_after {
+        let _ = writeln!(out, "not after:          {}", format_utc(t));
+    }
+    if let Some(launch) = &meta.launch {
+        write_launch(&mut out, launch);
+    }
+    if let Some(ns) = &meta.isolate {
+        let _ = writeln!(out, "namespaces:         {}", ns.names());
+    }
+    if let Some(sandbox) = &meta.sandbox {
+        write_sandbox(&mut out, sandbox);
+    }
+    if let Some(policy) = &meta.supervise {
+        write_supervise(&mut out, policy);
+    }
+    out
+}
+
+fn write_launch(out: &mut String, launch: &LaunchSettings) {
+    for limit in &launch.rlimits {
+        let _ = writeln!(out, "rlimit:             {}", limit.spec());
+    }
+    if let Some(mask) = launch.umask {
+        let _ = writeln!(out, "umask:              {:04o}", mask);
+    }
+    if let Some(nice) = launch.nice {
+        let _ = writeln!(out, "nice:               {}", nice);
+    }
+    match &launch.workdir {
+        Some(WorkDir::SfxDir) => {
+            let _ = writeln!(out, "workdir:            sfx-dir");
+        }
+        Some(WorkDir::Path(path)) => {
+            let _ = writeln!(out, "workdir:            {}", path);
+        }
+        None => {}
+    }
+}
+
+fn write_sandbox(out: &mut String, sandbox: &SandboxPolicy) {
+    let yes_no = |b: bool| if b { "yes" } else { "no" };
+    let _ = writeln!(out, "no new privs:       {}", yes_no(sandbox.no_new_privs));
+    let _ = writeln!(out, "sandbox strict:     {}", yes_no(sandbox.strict));
+    if !sandbox.seccomp_deny.is_empty() {
+        let _ = writeln!(
+            out,
+            "seccomp deny:       {}",
+            sandbox.seccomp_deny.join(",")
+        );
+    }
+    for path in &sandbox.fs_read {
+        let _ = writeln!(out, "landlock ro:        {}", path);
+    }
+    for path in &sandbox.fs_write {
+        let _ = writeln!(out, "landlock rw:        {}", path);
+    }
+}
+
+fn write_supervise(out: &mut String, policy: &SupervisorPolicy) {
+    let _ = writeln!(
+        out,
+        "supervisor:         yes (restarts {}, backoff {}-{} ms)",
+        policy.max_restarts, policy.backoff_initial_ms, policy.backoff_max_ms
+    );
+    if let Some(cmd) = &policy.pre_start {
+        let _ = writeln!(out, "pre-start:          {}", cmd);
+    }
+    if let Some(cmd) = &policy.post_exit {
+        let _ = writeln!(out, "post-exit:          {}", cmd);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::isolate::parse_namespaces;
+    use crate::launch::parse_rlimit;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_no_flags_passes_everything_through() {
+        let (action, rest) = split_runtime_flags(&args(&["a", "--xsfx-info"])).unwrap();
+        assert_eq!(action, None);
+        assert_eq!(rest, args(&["a", "--xsfx-info"]));
+    }
+
+    #[test]
+    fn test_info_and_verify() {
+        let (action, rest) = split_runtime_flags(&args(&["--xsfx-info"])).unwrap();
+        assert_eq!(action, Some(RuntimeAction::Info));
+        assert!(rest.is_empty());
+        let (action, rest) = split_runtime_flags(&args(&["--xsfx-verify", "x"])).unwrap();
+        assert_eq!(action, Some(RuntimeAction::Verify));
+        assert_eq!(rest, args(&["x"]));
+    }
+
+    #[test]
+    fn test_extract_takes_path() {
+        let (action, _) = split_runtime_flags(&args(&["--xsfx-extract", "/tmp/out"])).unwrap();
+        assert_eq!(action, Some(RuntimeAction::Extract("/tmp/out".into())));
+    }
+
+    #[test]
+    fn test_extract_missing_path() {
+        let err = split_runtime_flags(&args(&["--xsfx-extract"])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(split_runtime_flags(&args(&["--xsfx-extract", ""])).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc008_unknown_reserved_flag_rejected() {
+        let err = split_runtime_flags(&args(&["--xsfx-run-as-root"])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_sec_uc008_multiple_actions_rejected() {
+        assert!(split_runtime_flags(&args(&["--xsfx-info", "--xsfx-verify"])).is_err());
+    }
+
+    #[test]
+    fn test_format_info() {
+        let meta = Metadata {
+            payload_version: Some("2.0".into()),
+            payload_sha256: Some([0x01; 32]),
+            payload_len: Some(10),
+            cache_max_size: Some(64),
+            not_after: Some(0),
+            build_ids: vec![("ls".into(), vec![0xAB, 0xCD])],
+            ..Metadata::default()
+        };
+        let text = format_info(100, 5, &meta);
+        assert!(text.contains("payload offset:     100"));
+        assert!(text.contains(&"01".repeat(32)));
+        assert!(text.contains("payload version:    2.0"));
+        assert!(text.contains("build-id:           abcd (ls)"));
+        assert!(text.contains("cache:              yes (max 64 bytes)"));
+        assert!(text.contains("not after:          1970-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_format_info_process_settings() {
+        let meta = Metadata {
+            launch: Some(LaunchSettings {
+                rlimits: vec![parse_rlimit("nofile=64").unwrap()],
+                umask: Some(0o077),
+                nice: Some(5),
+                workdir: Some(WorkDir::SfxDir),
+            }),
+            isolate: Some(parse_namespaces("user,pid").unwrap()),
+            sandbox: Some(SandboxPolicy {
+                no_new_privs: true,
+                seccomp_deny: vec!["ptrace".into()],
+                fs_read: vec!["/usr".into()],
+                ..SandboxPolicy::default()
+            }),
+            supervise: Some(SupervisorPolicy {
+                pre_start: Some("true".into()),
+                max_restarts: 3,
+                ..SupervisorPolicy::default()
+            }),
+            ..Metadata::default()
+        };
+        let text = format_info(100, 5, &meta);
+        for line in [
+            "rlimit:             nofile=64",
+            "umask:              0077",
+            "nice:               5",
+            "workdir:            sfx-dir",
+            "namespaces:         user,pid",
+            "no new privs:       yes",
+            "sandbox strict:     no",
+            "seccomp deny:       ptrace",
+            "landlock ro:        /usr",
+            "pre-start:          true",
+        ] {
+            assert!(text.contains(line), "missing {:?} in\n{}", line, text);
+        }
+        assert!(text.contains("supervisor:         yes (restarts 3,"));
+    }
+
+    #[test]
+    fn test_format_info_legacy() {
+        let text = format_info(100, 5, &Metadata::default());
+        assert!(text.contains("payload sha256:     not recorded"));
+        assert!(text.contains("payload size:       unknown"));
+        assert!(text.contains("encryption:         no"));
+    }
+}
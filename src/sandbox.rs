@@ -0,0 +1,646 @@
+use std::io;
+
+use crate::cursor::ByteReader;
+
+/// Syscalls that may be named in a seccomp deny-list. Kept to calls that
+/// exist on every supported Linux architecture; the exec family is never
+/// deniable since the stub needs it to start the payload.
+pub const DENYABLE_SYSCALLS: &[&str] = &[
+    "accept",
+    "accept4",
+    "acct",
+    "add_key",
+    "bind",
+    "bpf",
+    "chroot",
+    "clock_settime",
+    "connect",
+    "delete_module",
+    "fanotify_init",
+    "finit_module",
+    "init_module",
+    "io_uring_setup",
+    "kcmp",
+    "kexec_load",
+    "keyctl",
+    "listen",
+    "mount",
+    "name_to_handle_at",
+    "open_by_handle_at",
+    "perf_event_open",
+    "personality",
+    "pivot_root",
+    "process_vm_readv",
+    "process_vm_writev",
+    "ptrace",
+    "quotactl",
+    "reboot",
+    "request_key",
+    "sethostname",
+    "setdomainname",
+    "setns",
+    "settimeofday",
+    "socket",
+    "swapoff",
+    "swapon",
+    "syslog",
+    "umount2",
+    "unshare",
+    "userfaultfd",
+    "vhangup",
+];
+
+/// Expanded by `--seccomp-deny @default`: kernel, namespace, tracing and
+/// key-management calls that ordinary tools never need.
+pub const DEFAULT_DENY: &[&str] = &[
+    "acct",
+    "add_key",
+    "bpf",
+    "delete_module",
+    "finit_module",
+    "init_module",
+    "kexec_load",
+    "keyctl",
+    "mount",
+    "perf_event_open",
+    "pivot_root",
+    "process_vm_readv",
+    "process_vm_writev",
+    "ptrace",
+    "reboot",
+    "request_key",
+    "setns",
+    "swapoff",
+    "swapon",
+    "umount2",
+    "unshare",
+    "userfaultfd",
+];
+
+const FLAG_NO_NEW_PRIVS: u8 = 0x01;
+const FLAG_STRICT: u8 = 0x02;
+
+/// Containment applied by the stub right before it execs the payload.
+///
+/// Seccomp and Landlock both imply `no_new_privs`. In the default
+/// best-effort mode a feature the kernel lacks is skipped; with `strict`
+/// the stub refuses to run the payload instead.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SandboxPolicy {
+    pub no_new_privs: bool,
+    pub strict: bool,
+    /// Syscall names from `DENYABLE_SYSCALLS`; denied with `EPERM`.
+    pub seccomp_deny: Vec<String>,
+    /// Trees the payload may read and execute (Landlock).
+    pub fs_read: Vec<String>,
+    /// Trees the payload may read, write and execute (Landlock).
+    pub fs_write: Vec<String>,
+}
+
+fn policy_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Expand `@default` and check every name against `DENYABLE_SYSCALLS`.
+pub fn parse_deny_list(list: &str) -> Result<Vec<String>, String> {
+    let mut out: Vec<String> = Vec::new();
+    for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        let names: Vec<&str> = if name == "@default" {
+            DEFAULT_DENY.to_vec()
+        } else if DENYABLE_SYSCALLS.contains(&name) {
+            vec![name]
+        } else {
+            return Err(format!("Syscall '{}' cannot be denied", name));
+        };
+        for n in names {
+            if !out.iter().any(|o| o == n) {
+                out.push(n.to_string());
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn push_list(out: &mut Vec<u8>, items: &[String]) {
+    out.extend_from_slice(&(items.len() as u16).to_le_bytes());
+    for item in items {
+        out.extend_from_slice(&(item.len() as u16).to_le_bytes());
+        out.extend_from_slice(item.as_bytes());
+    }
+}
+
+fn read_list(r: &mut ByteReader<'_>) -> io::Result<Vec<String>> {
+    let count = r.u16()?;
+    let mut items = Vec::new();
+    for _ in 0..count {
+        items.push(r.str16()?.to_string());
+    }
+    Ok(items)
+}
+
+impl SandboxPolicy {
+    pub fn is_empty(&self) -> bool {
+        !self.no_new_privs
+            && self.seccomp_deny.is_empty()
+            && self.fs_read.is_empty()
+            && self.fs_write.is_empty()
+    }
+
+    /// Whether `PR_SET_NO_NEW_PRIVS` will be set.
+    pub fn wants_no_new_privs(&self) -> bool {
+        self.no_new_privs || self.wants_seccomp() || self.wants_landlock()
+    }
+
+    pub fn wants_seccomp(&self) -> bool {
+        !self.seccomp_deny.is_empty()
+    }
+
+    pub fn wants_landlock(&self) -> bool {
+        !self.fs_read.is_empty() || !self.fs_write.is_empty()
+    }
+
+    /// `[flags u8]` then three `[count u16]{[len u16][utf8]}` lists:
+    /// seccomp deny names, read-only trees, writable trees.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.no_new_privs {
+            flags |= FLAG_NO_NEW_PRIVS;
+        }
+        if self.strict {
+            flags |= FLAG_STRICT;
+        }
+        let mut out = vec![flags];
+        push_list(&mut out, &self.seccomp_deny);
+        push_list(&mut out, &self.fs_read);
+        push_list(&mut out, &self.fs_write);
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut r = ByteReader::new(data, "sandbox record");
+        let flags = r.u8()?;
+        if flags & !(FLAG_NO_NEW_PRIVS | FLAG_STRICT) != 0 {
+            return Err(policy_err("Unknown sandbox flags"));
+        }
+        let policy = Self {
+            no_new_privs: flags & FLAG_NO_NEW_PRIVS != 0,
+            strict: flags & FLAG_STRICT != 0,
+            seccomp_deny: read_list(&mut r)?,
+            fs_read: read_list(&mut r)?,
+            fs_write: read_list(&mut r)?,
+        };
+        if !r.is_empty() {
+            return Err(policy_err("Trailing bytes in sandbox record"));
+        }
+        if let Some(bad) = policy
+            .seccomp_deny
+            .iter()
+            .find(|n| !DENYABLE_SYSCALLS.contains(&n.as_str()))
+        {
+            return Err(policy_err(&format!("Syscall '{}' cannot be denied", bad)));
+        }
+        Ok(policy)
+    }
+}
+
+/// Outcome of applying a policy: what took effect and what was skipped.
+#[derive(Debug, Default)]
+pub struct SandboxReport {
+    pub applied: Vec<&'static str>,
+    pub skipped: Vec<(&'static str, io::Error)>,
+}
+
+impl SandboxReport {
+    /// In strict mode a skipped feature is fatal; otherwise record it.
+    fn skip(&mut self, strict: bool, feature: &'static str, err: io::Error) -> io::Result<()> {
+        if strict {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{} unavailable: {}", feature, err),
+            ));
+        }
+        self.skipped.push((feature, err));
+        Ok(())
+    }
+}
+
+/// Apply `policy` to the current process. Must run after everything the
+/// stub itself needs (memfd creation) and right before exec.
+#[cfg(target_os = "linux")]
+pub fn apply(policy: &SandboxPolicy) -> io::Result<SandboxReport> {
+    let mut report = SandboxReport::default();
+    if policy.wants_no_new_privs() {
+        match linux::set_no_new_privs() {
+            Ok(()) => report.applied.push("no_new_privs"),
+            Err(e) => report.skip(policy.strict, "no_new_privs", e)?,
+        }
+    }
+    if policy.wants_landlock() {
+        match linux::landlock_restrict(&policy.fs_read, &policy.fs_write) {
+            Ok(()) => report.applied.push("landlock"),
+            Err(e) => report.skip(policy.strict, "landlock", e)?,
+        }
+    }
+    // Seccomp last: the deny-list could otherwise block the calls above.
+    if policy.wants_seccomp() {
+        match linux::seccomp_deny(&policy.seccomp_deny) {
+            Ok(()) => report.applied.push("seccomp"),
+            Err(e) => report.skip(policy.strict, "seccomp", e)?,
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn apply(policy: &SandboxPolicy) -> io::Result<SandboxReport> {
+    let mut report = SandboxReport::default();
+    if !policy.is_empty() {
+        let err = io::Error::new(io::ErrorKind::Unsupported, "sandboxing requires Linux");
+        report.skip(policy.strict, "sandbox", err)?;
+    }
+    Ok(report)
+}
+
+#[cfg(target_os = "linux")]
+pub use linux::{seccomp_filter, syscall_number};
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::CString;
+    use std::io;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    // Landlock syscall numbers are shared by all architectures we target.
+    const SYS_LANDLOCK_CREATE_RULESET: libc::c_long = 444;
+    const SYS_LANDLOCK_ADD_RULE: libc::c_long = 445;
+    const SYS_LANDLOCK_RESTRICT_SELF: libc::c_long = 446;
+    const LANDLOCK_CREATE_RULESET_VERSION: u32 = 1;
+    const LANDLOCK_RULE_PATH_BENEATH: u32 = 1;
+
+    const FS_EXECUTE: u64 = 1 << 0;
+    const FS_WRITE_FILE: u64 = 1 << 1;
+    const FS_READ_FILE: u64 = 1 << 2;
+    const FS_READ_DIR: u64 = 1 << 3;
+    const FS_REFER: u64 = 1 << 13;
+    const FS_TRUNCATE: u64 = 1 << 14;
+    /// Rights that apply to a regular file (the rest only make sense on dirs).
+    const FS_FILE_RIGHTS: u64 = FS_EXECUTE | FS_WRITE_FILE | FS_READ_FILE | FS_TRUNCATE;
+    const FS_READ_RIGHTS: u64 = FS_EXECUTE | FS_READ_FILE | FS_READ_DIR;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: i32,
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+    /// x32 syscalls share the x86_64 audit arch; deny them wholesale so
+    /// the deny-list cannot be bypassed through the x32 numbering.
+    #[cfg(target_arch = "x86_64")]
+    const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+    const BPF_LD_W_ABS: u16 = 0x20;
+    const BPF_JEQ_K: u16 = 0x15;
+    const BPF_JGE_K: u16 = 0x35;
+    const BPF_RET_K: u16 = 0x06;
+    const SECCOMP_DATA_NR: u32 = 0;
+    const SECCOMP_DATA_ARCH: u32 = 4;
+
+    pub fn set_no_new_privs() -> io::Result<()> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    macro_rules! syscall_table {
+        ($($sys:ident),* $(,)?) => {
+            /// Number of a syscall from `DENYABLE_SYSCALLS` on this architecture.
+            pub fn syscall_number(name: &str) -> Option<u32> {
+                match format!("SYS_{}", name).as_str() {
+                    $(stringify!($sys) => Some(libc::$sys as u32),)*
+                    _ => None,
+                }
+            }
+        };
+    }
+
+    syscall_table!(
+        SYS_accept,
+        SYS_accept4,
+        SYS_acct,
+        SYS_add_key,
+        SYS_bind,
+        SYS_bpf,
+        SYS_chroot,
+        SYS_clock_settime,
+        SYS_connect,
+        SYS_delete_module,
+        SYS_fanotify_init,
+        SYS_finit_module,
+        SYS_init_module,
+        SYS_io_uring_setup,
+        SYS_kcmp,
+        SYS_kexec_load,
+        SYS_keyctl,
+        SYS_listen,
+        SYS_mount,
+        SYS_name_to_handle_at,
+        SYS_open_by_handle_at,
+        SYS_perf_event_open,
+        SYS_personality,
+        SYS_pivot_root,
+        SYS_process_vm_readv,
+        SYS_process_vm_writev,
+        SYS_ptrace,
+        SYS_quotactl,
+        SYS_reboot,
+        SYS_request_key,
+        SYS_sethostname,
+        SYS_setdomainname,
+        SYS_setns,
+        SYS_settimeofday,
+        SYS_socket,
+        SYS_swapoff,
+        SYS_swapon,
+        SYS_syslog,
+        SYS_umount2,
+        SYS_unshare,
+        SYS_userfaultfd,
+        SYS_vhangup,
+    );
+
+    fn stmt(code: u16, k: u32) -> libc::sock_filter {
+        libc::sock_filter {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    fn jump(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    /// Classic BPF program: kill on a foreign architecture, `EPERM` for
+    /// each listed syscall number, allow everything else.
+    pub fn seccomp_filter(numbers: &[u32]) -> Vec<libc::sock_filter> {
+        let deny = libc::SECCOMP_RET_ERRNO | (libc::EPERM as u32 & libc::SECCOMP_RET_DATA);
+        let mut prog = vec![
+            stmt(BPF_LD_W_ABS, SECCOMP_DATA_ARCH),
+            jump(BPF_JEQ_K, AUDIT_ARCH, 1, 0),
+            stmt(BPF_RET_K, libc::SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD_W_ABS, SECCOMP_DATA_NR),
+        ];
+        #[cfg(target_arch = "x86_64")]
+        {
+            prog.push(jump(BPF_JGE_K, X32_SYSCALL_BIT, 0, 1));
+            prog.push(stmt(BPF_RET_K, deny));
+        }
+        for &nr in numbers {
+            prog.push(jump(BPF_JEQ_K, nr, 0, 1));
+            prog.push(stmt(BPF_RET_K, deny));
+        }
+        prog.push(stmt(BPF_RET_K, libc::SECCOMP_RET_ALLOW));
+        prog
+    }
+
+    pub fn seccomp_deny(names: &[String]) -> io::Result<()> {
+        let numbers = names
+            .iter()
+            .map(|n| {
+                syscall_number(n).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("unknown syscall {}", n),
+                    )
+                })
+            })
+            .collect::<io::Result<Vec<u32>>>()?;
+        let filter = seccomp_filter(&numbers);
+        let prog = libc::sock_fprog {
+            len: filter.len() as u16,
+            filter: filter.as_ptr() as *mut libc::sock_filter,
+        };
+        let r = unsafe {
+            libc::prctl(
+                libc::PR_SET_SECCOMP,
+                libc::SECCOMP_MODE_FILTER,
+                &prog as *const libc::sock_fprog,
+            )
+        };
+        if r != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn landlock_abi() -> io::Result<i64> {
+        let r = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                std::ptr::null::<RulesetAttr>(),
+                0usize,
+                LANDLOCK_CREATE_RULESET_VERSION,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(r)
+    }
+
+    fn add_path_rule(ruleset: i32, path: &str, access: u64) -> io::Result<()> {
+        let c_path = CString::new(Path::new(path).as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::open(c_path.as_ptr(), libc::O_PATH | libc::O_CLOEXEC) };
+        if fd < 0 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(err.kind(), format!("{}: {}", path, err)));
+        }
+        let is_dir = std::fs::metadata(path).map(|m| m.is_dir()).unwrap_or(false);
+        let attr = PathBeneathAttr {
+            allowed_access: if is_dir {
+                access
+            } else {
+                access & FS_FILE_RIGHTS
+            },
+            parent_fd: fd,
+        };
+        let r = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_ADD_RULE,
+                ruleset,
+                LANDLOCK_RULE_PATH_BENEATH,
+                &attr as *const PathBeneathAttr,
+                0u32,
+            )
+        };
+        let err = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        if r < 0 {
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    /// Restrict filesystem access to `read` (read + execute) and `write`
+    /// (full access) trees, using every right the running ABI can handle.
+    pub fn landlock_restrict(read: &[String], write: &[String]) -> io::Result<()> {
+        let abi = landlock_abi()?;
+        let mut handled: u64 = (1 << 13) - 1;
+        if abi >= 2 {
+            handled |= FS_REFER;
+        }
+        if abi >= 3 {
+            handled |= FS_TRUNCATE;
+        }
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let ruleset = unsafe {
+            libc::syscall(
+                SYS_LANDLOCK_CREATE_RULESET,
+                &attr as *const RulesetAttr,
+                std::mem::size_of::<RulesetAttr>(),
+                0u32,
+            )
+        };
+        if ruleset < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let ruleset = ruleset as i32;
+        let result = (|| {
+            for path in read {
+                add_path_rule(ruleset, path, FS_READ_RIGHTS & handled)?;
+            }
+            for path in write {
+                add_path_rule(ruleset, path, handled)?;
+            }
+            if unsafe { libc::syscall(SYS_LANDLOCK_RESTRICT_SELF, ruleset, 0u32) } < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        })();
+        unsafe { libc::close(ruleset) };
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SandboxPolicy {
+        SandboxPolicy {
+            no_new_privs: true,
+            strict: true,
+            seccomp_deny: vec!["ptrace".into(), "mount".into()],
+            fs_read: vec!["/usr".into(), "/etc/ssl".into()],
+            fs_write: vec!["/tmp/scratch".into()],
+        }
+    }
+
+    #[test]
+    fn test_policy_roundtrip() {
+        let policy = sample();
+        assert_eq!(
+            SandboxPolicy::from_bytes(&policy.to_bytes()).unwrap(),
+            policy
+        );
+        let empty = SandboxPolicy::default();
+        assert!(empty.is_empty());
+        assert_eq!(SandboxPolicy::from_bytes(&empty.to_bytes()).unwrap(), empty);
+    }
+
+    #[test]
+    fn test_wants_no_new_privs_implied() {
+        let policy = SandboxPolicy {
+            fs_read: vec!["/usr".into()],
+            ..SandboxPolicy::default()
+        };
+        assert!(policy.wants_no_new_privs());
+        assert!(policy.wants_landlock());
+        assert!(!policy.wants_seccomp());
+    }
+
+    #[test]
+    fn test_parse_deny_list() {
+        assert_eq!(
+            parse_deny_list("ptrace, mount,ptrace").unwrap(),
+            vec!["ptrace".to_string(), "mount".to_string()]
+        );
+        let defaults = parse_deny_list("@default,socket").unwrap();
+        assert_eq!(defaults.len(), DEFAULT_DENY.len() + 1);
+        assert!(parse_deny_list("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_default_deny_is_denyable() {
+        for name in DEFAULT_DENY {
+            assert!(DENYABLE_SYSCALLS.contains(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_sec_uc011_exec_not_deniable() {
+        assert!(parse_deny_list("execve").is_err());
+        assert!(parse_deny_list("execveat").is_err());
+        assert!(parse_deny_list("@all").is_err());
+    }
+
+    #[test]
+    fn test_sec_uc011_record_unknown_syscall_rejected() {
+        let policy = SandboxPolicy {
+            seccomp_deny: vec!["execveat".into()],
+            ..SandboxPolicy::default()
+        };
+        assert!(SandboxPolicy::from_bytes(&policy.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_sec_uc011_record_malformed() {
+        assert!(SandboxPolicy::from_bytes(&[]).is_err());
+        assert!(SandboxPolicy::from_bytes(&[0x80, 0, 0, 0, 0, 0, 0]).is_err());
+        assert!(SandboxPolicy::from_bytes(&[0, 1, 0, 5, 0, b'a']).is_err());
+        let mut trailing = SandboxPolicy::default().to_bytes();
+        trailing.push(0);
+        assert!(SandboxPolicy::from_bytes(&trailing).is_err());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_every_denyable_syscall_resolves() {
+        for name in DENYABLE_SYSCALLS {
+            assert!(syscall_number(name).is_some(), "{name}");
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_seccomp_filter_shape() {
+        let nr = syscall_number("ptrace").unwrap();
+        let prog = seccomp_filter(&[nr]);
+        let last = prog.last().unwrap();
+        assert_eq!(last.k, libc::SECCOMP_RET_ALLOW);
+        assert!(prog.iter().any(|i| i.k == nr));
+        assert_eq!(prog[2].k, libc::SECCOMP_RET_KILL_PROCESS);
+    }
+
+    #[test]
+    fn test_apply_empty_policy_is_noop() {
+        let report = apply(&SandboxPolicy::default()).unwrap();
+        assert!(report.applied.is_empty());
+        assert!(report.skipped.is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How the stub found its own executable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfSource {
+    /// `/proc/self/exe` (Linux; also works when running from a memfd).
+    ProcSelfExe,
+    /// The path passed to `execve`, from the `AT_EXECFN` auxv entry.
+    ExecFn,
+    /// `std::env::current_exe`.
+    CurrentExe,
+    /// `argv[0]`, either as a path or looked up in `PATH`.
+    Argv0,
+}
+
+impl SelfSource {
+    pub fn name(self) -> &'static str {
+        match self {
+            SelfSource::ProcSelfExe => "/proc/self/exe",
+            SelfSource::ExecFn => "AT_EXECFN",
+            SelfSource::CurrentExe => "current_exe",
+            SelfSource::Argv0 => "argv[0]",
+        }
+    }
+}
+
+/// The running SFX, opened for reading.
+pub struct SelfExe {
+    pub file: File,
+    /// Best known path of the SFX (for `XSFX_SFX_PATH` and `--workdir sfx-dir`).
+    pub path: PathBuf,
+    pub source: SelfSource,
+}
+
+/// Open the running executable, trying in order `/proc/self/exe`,
+/// `AT_EXECFN`, `current_exe` and `argv[0]` (searched in `PATH` if it has
+/// no slash), so the stub also works in chroots and containers without
+/// `/proc`.
+///
+/// A stub started from a file descriptor (two-stage SFX) has an
+/// `AT_EXECFN` of `/dev/fd/N`; if that cannot be opened the search stops,
+/// because `argv[0]` then names the outer SFX, not this one.
+pub fn locate_self(argv0: Option<&OsStr>) -> io::Result<SelfExe> {
+    let current = env::current_exe();
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(file) = File::open("/proc/self/exe") {
+            let path = current.unwrap_or_else(|_| PathBuf::from("/proc/self/exe"));
+            return Ok(SelfExe {
+                file,
+                path,
+                source: SelfSource::ProcSelfExe,
+            });
+        }
+        if let Some(execfn) = exec_fn() {
+            if let Ok(file) = File::open(&execfn) {
+                return Ok(SelfExe {
+                    file,
+                    path: absolute(execfn),
+                    source: SelfSource::ExecFn,
+                });
+            }
+            if is_fd_path(&execfn) {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "Started from a file descriptor and /proc is not available",
+                ));
+            }
+        }
+    }
+    if let Ok(path) = current {
+        if let Ok(file) = File::open(&path) {
+            return Ok(SelfExe {
+                file,
+                path,
+                source: SelfSource::CurrentExe,
+            });
+        }
+    }
+    let path = argv0
+        .and_then(|a| resolve_argv0(a, env::var_os("PATH").as_deref()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Cannot locate own executable"))?;
+    Ok(SelfExe {
+        file: File::open(&path)?,
+        path: absolute(path),
+        source: SelfSource::Argv0,
+    })
+}
+
+/// `AT_EXECFN` from the auxiliary vector.
+#[cfg(target_os = "linux")]
+fn exec_fn() -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    let ptr = unsafe { libc::getauxval(libc::AT_EXECFN) } as *const libc::c_char;
+    if ptr.is_null() {
+        return None;
+    }
+    let bytes = unsafe { std::ffi::CStr::from_ptr(ptr) }.to_bytes();
+    (!bytes.is_empty()).then(|| PathBuf::from(OsStr::from_bytes(bytes)))
+}
+
+/// `/dev/fd/N` or `/proc/self/fd/N`, as produced by `fexecve`/`execveat`.
+pub fn is_fd_path(path: &Path) -> bool {
+    let fd = path
+        .strip_prefix("/dev/fd")
+        .or_else(|_| path.strip_prefix("/proc/self/fd"));
+    fd.is_ok_and(|n| n.to_str().is_some_and(|n| n.parse::<u32>().is_ok()))
+}
+
+/// `argv[0]` as a path if it contains a separator, otherwise the first
+/// executable file of that name in `path_var`.
+pub fn resolve_argv0(argv0: &OsStr, path_var: Option<&OsStr>) -> Option<PathBuf> {
+    if argv0.is_empty() {
+        return None;
+    }
+    let candidate = Path::new(argv0);
+    if candidate.components().count() > 1 {
+        return candidate.is_file().then(|| candidate.to_path_buf());
+    }
+    env::split_paths(path_var?)
+        .map(|dir| {
+            // An empty PATH entry means the current directory.
+            if dir.as_os_str().is_empty() {
+                PathBuf::from(".").join(argv0)
+            } else {
+                dir.join(argv0)
+            }
+        })
+        .find(|p| is_executable(p))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn absolute(path: PathBuf) -> PathBuf {
+    if path.is_absolute() {
+        return path;
+    }
+    env::current_dir()
+        .map(|cwd| cwd.join(&path))
+        .unwrap_or(path)
+}
+
+/// `argv[0]` of the current process, if any.
+pub fn argv0() -> Option<OsString> {
+    env::args_os().next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn temp_dir(tag: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("xsfx-self-{}-{}", tag, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    fn touch(path: &Path, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::write(path, b"x").unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+    }
+
+    #[test]
+    fn test_locate_self_finds_test_binary() {
+        let me = locate_self(argv0().as_deref()).unwrap();
+        assert!(me.file.metadata().unwrap().len() > 0);
+        #[cfg(target_os = "linux")]
+        assert_eq!(me.source, SelfSource::ProcSelfExe);
+    }
+
+    #[test]
+    fn test_is_fd_path() {
+        assert!(is_fd_path(Path::new("/dev/fd/3")));
+        assert!(is_fd_path(Path::new("/proc/self/fd/12")));
+        assert!(!is_fd_path(Path::new("/dev/fd/x")));
+        assert!(!is_fd_path(Path::new("/usr/bin/app")));
+        assert!(!is_fd_path(Path::new("/dev/fd")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_argv0_path_search() {
+        let dir = temp_dir("path");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::create_dir_all(&a).unwrap();
+        fs::create_dir_all(&b).unwrap();
+        touch(&a.join("app"), 0o644); // not executable: skipped
+        touch(&b.join("app"), 0o755);
+        let path_var = env::join_paths([&a, &b]).unwrap();
+        assert_eq!(
+            resolve_argv0(OsStr::new("app"), Some(&path_var)),
+            Some(b.join("app"))
+        );
+        assert_eq!(resolve_argv0(OsStr::new("missing"), Some(&path_var)), None);
+        assert_eq!(resolve_argv0(OsStr::new("app"), None), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_resolve_argv0_with_slash() {
+        let dir = temp_dir("slash");
+        let file = dir.join("tool");
+        touch(&file, 0o755);
+        let arg = file.clone().into_os_string();
+        assert_eq!(resolve_argv0(&arg, None), Some(file));
+        let missing = dir.join("nope").into_os_string();
+        assert_eq!(resolve_argv0(&missing, None), None);
+        assert_eq!(resolve_argv0(OsStr::new(""), None), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,523 @@
+use std::io;
+
+use crate::elf::{parse_elf, put, section_fields, section_table, Reader, ET_DYN, ET_EXEC};
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOTE: u32 = 7;
+const SHT_NOBITS: u32 = 8;
+const SHT_REL: u32 = 9;
+const SHT_DYNSYM: u32 = 11;
+const SHT_SYMTAB_SHNDX: u32 = 18;
+const SHT_LOPROC: u32 = 0x7000_0000;
+const SHT_HIPROC: u32 = 0x7FFF_FFFF;
+
+const SHF_ALLOC: u64 = 0x2;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const SHN_LORESERVE: u16 = 0xFF00;
+const SHN_ABS: u16 = 0xFFF1;
+const SHN_XINDEX: u16 = 0xFFFF;
+
+/// Which sections `--strip` / `--strip-debug` remove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StripMode {
+    /// Debug sections only (`--strip-debug`).
+    Debug,
+    /// Every section that is not loaded, `.symtab` included. Keeps
+    /// `.shstrtab`, `.gnu_debuglink`, notes and processor attributes.
+    All,
+}
+
+/// A stripped ELF image and what was removed from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stripped {
+    pub data: Vec<u8>,
+    /// Names of the removed sections, in section header order.
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Section {
+    name: String,
+    kind: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    /// The original header, rewritten field by field.
+    header: Vec<u8>,
+}
+
+impl Section {
+    fn file_range(&self) -> Option<(u64, u64)> {
+        (self.kind != SHT_NOBITS && self.size > 0)
+            .then(|| (self.offset, self.offset.saturating_add(self.size)))
+    }
+}
+
+fn strip_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn read_sections(r: &Reader) -> io::Result<(Vec<Section>, usize)> {
+    let (shoff, shentsize, shnum, shstrndx) = section_table(r)?;
+    if shoff == 0 {
+        return Ok((Vec::new(), 0));
+    }
+    if shnum == 0 || shstrndx == SHN_XINDEX {
+        return Err(strip_err("Extended ELF section numbering is not supported"));
+    }
+    let entsize = if r.is_64 { 64 } else { 40 };
+    if shentsize < entsize || shstrndx >= shnum {
+        return Err(strip_err("Invalid ELF section header table"));
+    }
+    let (offset_at, size_at, link_at, info_at, align_at) = section_fields(r.is_64);
+    let mut sections = Vec::with_capacity(usize::from(shnum));
+    let mut name_offsets = Vec::with_capacity(usize::from(shnum));
+    for i in 0..u64::from(shnum) {
+        let at = shoff
+            .checked_add(i * u64::from(shentsize))
+            .ok_or_else(|| strip_err("ELF offset overflow"))?;
+        let section = Section {
+            name: String::new(),
+            kind: r.u32(at + 4)?,
+            flags: r.word(at + 8)?,
+            offset: r.word(at + offset_at as u64)?,
+            size: r.word(at + size_at as u64)?,
+            link: r.u32(at + link_at as u64)?,
+            info: r.u32(at + info_at as u64)?,
+            align: r.word(at + align_at as u64)?,
+            header: r
+                .data
+                .get(at as usize..at as usize + usize::from(entsize))
+                .ok_or_else(|| strip_err("ELF image truncated"))?
+                .to_vec(),
+        };
+        if let Some((_, end)) = section.file_range() {
+            if end > r.data.len() as u64 {
+                return Err(strip_err("ELF section outside the image"));
+            }
+        }
+        name_offsets.push(u64::from(r.u32(at)?));
+        sections.push(section);
+    }
+    let shstrndx = usize::from(shstrndx);
+    let (names, names_end) = (
+        sections[shstrndx].offset,
+        sections[shstrndx].offset + sections[shstrndx].size,
+    );
+    for (section, name) in sections.iter_mut().zip(name_offsets).skip(1) {
+        section.name = r.cstr(names.saturating_add(name), names_end)?;
+    }
+    Ok((sections, shstrndx))
+}
+
+fn is_debug(name: &str) -> bool {
+    [".debug", ".zdebug", ".stab", ".gnu.debuglto_"]
+        .iter()
+        .any(|p| name.starts_with(p))
+        || name == ".line"
+}
+
+/// Unloaded sections `--strip` keeps: a debug link for separate debug
+/// files, notes and processor attributes (`.ARM.attributes`, ...).
+fn keep_unloaded(section: &Section) -> bool {
+    section.name == ".gnu_debuglink"
+        || section.kind == SHT_NOTE
+        || (SHT_LOPROC..=SHT_HIPROC).contains(&section.kind)
+}
+
+fn pad_to(out: &mut Vec<u8>, align: u64) {
+    let align = align.max(1) as usize;
+    out.resize(out.len().next_multiple_of(align), 0);
+}
+
+/// Remove unloaded sections from an ELF executable or shared library.
+///
+/// Everything up to the end of the last segment or loaded section keeps
+/// its offset, so the image loads exactly as before. Removed sections in
+/// that range that no segment covers are zeroed; the kept unloaded
+/// sections, a new `.shstrtab` and the section header table are written
+/// after it. Images without section headers are returned unchanged.
+pub fn strip_elf(data: &[u8], mode: StripMode) -> io::Result<Stripped> {
+    let info = parse_elf(data)?;
+    if !matches!(info.elf_type, ET_EXEC | ET_DYN) {
+        return Err(strip_err(
+            "Only executables and shared libraries can be stripped",
+        ));
+    }
+    let le = info.little_endian;
+    let r = Reader {
+        data,
+        is_64: info.is_64,
+        le,
+    };
+    let (sections, shstrndx) = read_sections(&r)?;
+    let mut remove: Vec<bool> = sections
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            i != 0
+                && i != shstrndx
+                && s.flags & SHF_ALLOC == 0
+                && match mode {
+                    StripMode::Debug => is_debug(&s.name),
+                    StripMode::All => !keep_unloaded(s),
+                }
+        })
+        .collect();
+    // Relocations for a removed section go with it.
+    for i in 0..sections.len() {
+        let s = &sections[i];
+        if matches!(s.kind, SHT_REL | SHT_RELA)
+            && s.flags & SHF_ALLOC == 0
+            && remove.get(s.info as usize) == Some(&true)
+        {
+            remove[i] = true;
+        }
+    }
+    if !remove.contains(&true) {
+        return Ok(Stripped {
+            data: data.to_vec(),
+            removed: Vec::new(),
+        });
+    }
+    let kept = || sections.iter().enumerate().filter(|(i, _)| !remove[*i]);
+    if kept().any(|(_, s)| s.kind == SHT_SYMTAB_SHNDX) {
+        return Err(strip_err(
+            "Extended ELF symbol section indexes are not supported",
+        ));
+    }
+    let mut new_index = vec![None; sections.len()];
+    for (new, (old, _)) in kept().enumerate() {
+        new_index[old] = Some(new as u32);
+    }
+    let remap = |old: u32| new_index.get(old as usize).copied().flatten().unwrap_or(0);
+
+    let (phoff, phentsize) = if r.is_64 {
+        (r.u64(32)?, r.u16(54)?)
+    } else {
+        (u64::from(r.u32(28)?), r.u16(42)?)
+    };
+    let headers_end = phoff + u64::from(phentsize) * info.program_headers.len() as u64;
+    let segments: Vec<(u64, u64)> = info
+        .program_headers
+        .iter()
+        .map(|p| (p.offset, p.offset.saturating_add(p.filesz)))
+        .chain([(0, headers_end.max(if r.is_64 { 64 } else { 52 }))])
+        .collect();
+    let loaded_end = kept()
+        .filter(|(_, s)| s.flags & SHF_ALLOC != 0)
+        .filter_map(|(_, s)| s.file_range())
+        .chain(segments.iter().copied())
+        .map(|(_, end)| end)
+        .max()
+        .unwrap_or(0);
+    if loaded_end > data.len() as u64 {
+        return Err(strip_err("ELF segment outside the image"));
+    }
+    let mut out = data[..loaded_end as usize].to_vec();
+
+    // Zero removed bytes nothing loaded or kept still refers to; they
+    // cannot move, but zeros compress away.
+    let overlaps = |(start, end): (u64, u64), (s, e): (u64, u64)| start < e && s < end;
+    for (_, s) in sections.iter().enumerate().filter(|(i, _)| remove[*i]) {
+        let Some((start, end)) = s.file_range() else {
+            continue;
+        };
+        let range = (start, end.min(loaded_end));
+        if range.0 < range.1
+            && !segments.iter().any(|seg| overlaps(range, *seg))
+            && !kept()
+                .filter_map(|(_, k)| k.file_range())
+                .any(|k| overlaps(range, k))
+        {
+            out[range.0 as usize..range.1 as usize].fill(0);
+        }
+    }
+
+    let mut names = vec![0u8];
+    let mut headers = Vec::new();
+    let (offset_at, size_at, link_at, info_at, _) = section_fields(r.is_64);
+    let word = if r.is_64 { 8 } else { 4 };
+    let mut symbol_tables = Vec::new();
+    for (old, s) in kept() {
+        let mut header = s.header.clone();
+        if old == 0 {
+            headers.push(header);
+            continue;
+        }
+        put(&mut header, 0, names.len() as u64, 4, le);
+        names.extend_from_slice(s.name.as_bytes());
+        names.push(0);
+        if s.link != 0 {
+            put(&mut header, link_at, u64::from(remap(s.link)), 4, le);
+        }
+        if s.info != 0 && (matches!(s.kind, SHT_REL | SHT_RELA) || s.flags & SHF_INFO_LINK != 0) {
+            put(&mut header, info_at, u64::from(remap(s.info)), 4, le);
+        }
+        let mut offset = s.offset as usize;
+        if old != shstrndx {
+            if let Some((start, end)) = s.file_range().filter(|(_, end)| *end > loaded_end) {
+                pad_to(&mut out, s.align);
+                offset = out.len();
+                put(&mut header, offset_at, offset as u64, word, le);
+                out.extend_from_slice(&data[start as usize..end as usize]);
+            }
+        }
+        if matches!(s.kind, SHT_SYMTAB | SHT_DYNSYM) {
+            symbol_tables.push((offset, s.size as usize));
+        }
+        headers.push(header);
+    }
+    let shstrtab = new_index[shstrndx].expect("section name table is kept") as usize;
+    put(
+        &mut headers[shstrtab],
+        offset_at,
+        out.len() as u64,
+        word,
+        le,
+    );
+    put(
+        &mut headers[shstrtab],
+        size_at,
+        names.len() as u64,
+        word,
+        le,
+    );
+    out.extend_from_slice(&names);
+
+    // Symbols keep pointing at their sections under the new numbering.
+    let (sym_size, shndx_at) = if r.is_64 { (24, 6) } else { (16, 14) };
+    for (offset, size) in symbol_tables {
+        for sym in (offset..offset + size).step_by(sym_size) {
+            let Some(b) = out.get(sym + shndx_at..sym + shndx_at + 2) else {
+                break;
+            };
+            let shndx = if le {
+                u16::from_le_bytes([b[0], b[1]])
+            } else {
+                u16::from_be_bytes([b[0], b[1]])
+            };
+            if shndx == 0 || shndx >= SHN_LORESERVE {
+                continue;
+            }
+            let new = new_index
+                .get(usize::from(shndx))
+                .copied()
+                .flatten()
+                .map_or(SHN_ABS, |n| n as u16);
+            put(&mut out, sym + shndx_at, u64::from(new), 2, le);
+        }
+    }
+
+    pad_to(&mut out, word as u64);
+    let shoff = out.len() as u64;
+    for header in &headers {
+        out.extend_from_slice(header);
+    }
+    let (shoff_at, shentsize_at) = if r.is_64 { (40, 58) } else { (32, 46) };
+    let (shnum_at, shstrndx_at) = (shentsize_at + 2, shentsize_at + 4);
+    put(&mut out, shoff_at, shoff, word, le);
+    put(&mut out, shentsize_at, headers[0].len() as u64, 2, le);
+    put(&mut out, shnum_at, headers.len() as u64, 2, le);
+    put(&mut out, shstrndx_at, shstrtab as u64, 2, le);
+
+    let removed = sections
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| remove[*i])
+        .map(|(_, s)| s.name.clone())
+        .collect();
+    Ok(Stripped { data: out, removed })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elf::tests::build_elf;
+
+    const SHT_PROGBITS: u32 = 1;
+    const SHT_STRTAB: u32 = 3;
+
+    /// `(name, sh_type, sh_flags, sh_link, sh_info, contents)`. Loaded
+    /// sections point into the image instead of carrying contents.
+    type Spec<'a> = (&'a str, u32, u64, u32, u32, &'a [u8]);
+
+    /// `build_elf(None, &[])` followed by the contents of `specs`, a
+    /// `.shstrtab` and a section header table.
+    fn build_with_sections(specs: &[Spec]) -> Vec<u8> {
+        let mut out = build_elf(None, &[]);
+        let mut names = vec![0u8];
+        let mut name_offsets = Vec::new();
+        for name in specs.iter().map(|s| s.0).chain([".shstrtab"]) {
+            name_offsets.push(names.len() as u32);
+            names.extend_from_slice(name.as_bytes());
+            names.push(0);
+        }
+        let shstrtab: Spec = (".shstrtab", SHT_STRTAB, 0, 0, 0, &names);
+        let mut headers = vec![0u8; 64];
+        for (spec, name) in specs.iter().copied().chain([shstrtab]).zip(name_offsets) {
+            let (_, kind, flags, link, info, data) = spec;
+            let (offset, size) = if flags & SHF_ALLOC != 0 {
+                (64u64, 8u64)
+            } else {
+                out.extend_from_slice(data);
+                ((out.len() - data.len()) as u64, data.len() as u64)
+            };
+            let mut h = vec![0u8; 64];
+            h[..4].copy_from_slice(&name.to_le_bytes());
+            h[4..8].copy_from_slice(&kind.to_le_bytes());
+            h[8..16].copy_from_slice(&flags.to_le_bytes());
+            h[24..32].copy_from_slice(&offset.to_le_bytes());
+            h[32..40].copy_from_slice(&size.to_le_bytes());
+            h[40..44].copy_from_slice(&link.to_le_bytes());
+            h[44..48].copy_from_slice(&info.to_le_bytes());
+            h[48..56].copy_from_slice(&1u64.to_le_bytes());
+            headers.extend_from_slice(&h);
+        }
+        let shoff = out.len() as u64;
+        let count = headers.len() / 64;
+        out.extend_from_slice(&headers);
+        out[40..48].copy_from_slice(&shoff.to_le_bytes());
+        out[58..60].copy_from_slice(&64u16.to_le_bytes());
+        out[60..62].copy_from_slice(&(count as u16).to_le_bytes());
+        out[62..64].copy_from_slice(&((count - 1) as u16).to_le_bytes());
+        out
+    }
+
+    fn symbol(shndx: u16) -> [u8; 24] {
+        let mut sym = [0u8; 24];
+        sym[6..8].copy_from_slice(&shndx.to_le_bytes());
+        sym
+    }
+
+    fn sample() -> Vec<u8> {
+        let symbols: Vec<u8> = [0, 1, 2, 4].into_iter().flat_map(symbol).collect();
+        build_with_sections(&[
+            (".text", SHT_PROGBITS, SHF_ALLOC, 0, 0, &[]),
+            (".debug_info", SHT_PROGBITS, 0, 0, 0, &[0xDB; 300]),
+            (
+                ".rela.debug_info",
+                SHT_RELA,
+                SHF_INFO_LINK,
+                5,
+                2,
+                &[0x11; 24],
+            ),
+            (".comment", SHT_PROGBITS, 0, 0, 0, b"GCC\0"),
+            (".symtab", SHT_SYMTAB, 0, 6, 1, &symbols),
+            (".strtab", SHT_STRTAB, 0, 0, 0, b"\0main\0"),
+            (
+                ".gnu_debuglink",
+                SHT_PROGBITS,
+                0,
+                0,
+                0,
+                b"app.debug\0\0\0\x01\x02\x03\x04",
+            ),
+        ])
+    }
+
+    fn sections(data: &[u8]) -> Vec<Section> {
+        let r = Reader {
+            data,
+            is_64: true,
+            le: true,
+        };
+        read_sections(&r).unwrap().0
+    }
+
+    fn names(data: &[u8]) -> Vec<String> {
+        sections(data).into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn test_strip_all() {
+        let data = sample();
+        let stripped = strip_elf(&data, StripMode::All).unwrap();
+        assert_eq!(
+            stripped.removed,
+            [
+                ".debug_info",
+                ".rela.debug_info",
+                ".comment",
+                ".symtab",
+                ".strtab"
+            ]
+        );
+        assert_eq!(
+            names(&stripped.data),
+            ["", ".text", ".gnu_debuglink", ".shstrtab"]
+        );
+        assert!(stripped.data.len() < data.len() - 300);
+        let loaded = build_elf(None, &[]).len();
+        assert_eq!(stripped.data[64..loaded], data[64..loaded]);
+        let info = parse_elf(&stripped.data).unwrap();
+        assert_eq!(
+            info.program_headers,
+            parse_elf(&data).unwrap().program_headers
+        );
+        let link = &sections(&stripped.data)[2];
+        assert_eq!(&stripped.data[link.offset as usize..][..4], b"app.");
+    }
+
+    #[test]
+    fn test_strip_debug_renumbers() {
+        let stripped = strip_elf(&sample(), StripMode::Debug).unwrap();
+        assert_eq!(stripped.removed, [".debug_info", ".rela.debug_info"]);
+        let sections = sections(&stripped.data);
+        let names: Vec<&str> = sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "",
+                ".text",
+                ".comment",
+                ".symtab",
+                ".strtab",
+                ".gnu_debuglink",
+                ".shstrtab"
+            ]
+        );
+        let symtab = &sections[3];
+        assert_eq!(symtab.link, 4);
+        let shndx: Vec<u16> = stripped.data[symtab.offset as usize..][..symtab.size as usize]
+            .chunks(24)
+            .map(|s| u16::from_le_bytes([s[6], s[7]]))
+            .collect();
+        assert_eq!(shndx, [0, 1, SHN_ABS, 2]);
+    }
+
+    #[test]
+    fn test_strip_nothing_to_remove() {
+        let plain = build_elf(None, &[]);
+        let stripped = strip_elf(&plain, StripMode::All).unwrap();
+        assert_eq!(stripped.data, plain);
+        assert!(stripped.removed.is_empty());
+        let once = strip_elf(&sample(), StripMode::All).unwrap().data;
+        assert_eq!(strip_elf(&once, StripMode::All).unwrap().data, once);
+    }
+
+    #[test]
+    fn test_sec_strip_rejects_bad_sections() {
+        let mut data = sample();
+        let shoff = u64::from_le_bytes(data[40..48].try_into().unwrap()) as usize;
+        // .debug_info reaching past the end of the image.
+        data[shoff + 2 * 64 + 32..][..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(strip_elf(&data, StripMode::All).is_err());
+
+        let mut data = sample();
+        data[62..64].copy_from_slice(&99u16.to_le_bytes()); // e_shstrndx
+        assert!(strip_elf(&data, StripMode::All).is_err());
+        let truncated = sample();
+        assert!(strip_elf(&truncated[..truncated.len() - 10], StripMode::All).is_err());
+
+        let mut object = sample();
+        object[16..18].copy_from_slice(&1u16.to_le_bytes()); // ET_REL
+        assert!(strip_elf(&object, StripMode::Debug).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use crate::cursor::ByteReader;
+use crate::exit_code::StubExit;
+
+const FLAG_PRE_START: u8 = 0x01;
+const FLAG_POST_EXIT: u8 = 0x02;
+
+pub const DEFAULT_BACKOFF_INITIAL_MS: u32 = 1_000;
+pub const DEFAULT_BACKOFF_MAX_MS: u32 = 30_000;
+
+/// Restart counter passed to both hooks (0 for the first start).
+pub const ENV_RESTARTS: &str = "XSFX_RESTARTS";
+/// Exit code of the payload, passed to the post-exit hook.
+pub const ENV_EXIT_CODE: &str = "XSFX_EXIT_CODE";
+/// Signal that terminated the payload, passed to the post-exit hook.
+pub const ENV_EXIT_SIGNAL: &str = "XSFX_EXIT_SIGNAL";
+
+/// Supervisor mode: the stub forks, runs the payload as its child,
+/// forwards signals and reports the child's exit status as its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupervisorPolicy {
+    /// Shell command run before every start; a failure aborts the launch.
+    pub pre_start: Option<String>,
+    /// Shell command run after every exit of the payload.
+    pub post_exit: Option<String>,
+    /// How many times a crashed payload is restarted (0 = never).
+    pub max_restarts: u32,
+    /// Delay before the first restart, doubled for each further one.
+    pub backoff_initial_ms: u32,
+    /// Upper bound for the restart delay.
+    pub backoff_max_ms: u32,
+}
+
+impl Default for SupervisorPolicy {
+    fn default() -> Self {
+        Self {
+            pre_start: None,
+            post_exit: None,
+            max_restarts: 0,
+            backoff_initial_ms: DEFAULT_BACKOFF_INITIAL_MS,
+            backoff_max_ms: DEFAULT_BACKOFF_MAX_MS,
+        }
+    }
+}
+
+/// How the payload process ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChildStatus {
+    Exited(i32),
+    Signaled(i32),
+}
+
+impl ChildStatus {
+    /// Decode a `waitpid` status; `None` for stopped/continued children.
+    #[cfg(unix)]
+    pub fn from_wait_status(status: libc::c_int) -> Option<Self> {
+        if libc::WIFEXITED(status) {
+            Some(ChildStatus::Exited(libc::WEXITSTATUS(status)))
+        } else if libc::WIFSIGNALED(status) {
+            Some(ChildStatus::Signaled(libc::WTERMSIG(status)))
+        } else {
+            None
+        }
+    }
+
+    /// A non-zero exit or death by signal. Stub exit codes (the child
+    /// failed before the payload ran) are not crashes: restarting would
+    /// only fail the same way again.
+    pub fn is_crash(self) -> bool {
+        match self {
+            ChildStatus::Exited(0) => false,
+            ChildStatus::Exited(code) => StubExit::from_code(code).is_none(),
+            ChildStatus::Signaled(_) => true,
+        }
+    }
+
+    /// Shell-style exit code (`128 + N` for a signal).
+    pub fn shell_code(self) -> i32 {
+        match self {
+            ChildStatus::Exited(code) => code,
+            ChildStatus::Signaled(sig) => 128 + sig,
+        }
+    }
+
+    /// Environment for the post-exit hook.
+    pub fn hook_env(self) -> (&'static str, String) {
+        match self {
+            ChildStatus::Exited(code) => (ENV_EXIT_CODE, code.to_string()),
+            ChildStatus::Signaled(sig) => (ENV_EXIT_SIGNAL, sig.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ChildStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChildStatus::Exited(code) => write!(f, "exited with code {}", code),
+            ChildStatus::Signaled(sig) => write!(f, "killed by signal {}", sig),
+        }
+    }
+}
+
+fn supervise_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Parse a delay such as `500ms`, `2s` or `1m`; a bare number is in
+/// milliseconds.
+pub fn parse_delay_ms(s: &str) -> Option<u32> {
+    let s = s.trim();
+    let (num, unit) = if let Some(n) = s.strip_suffix("ms") {
+        (n, 1)
+    } else if let Some(n) = s.strip_suffix('s') {
+        (n, 1_000)
+    } else if let Some(n) = s.strip_suffix('m') {
+        (n, 60_000)
+    } else {
+        (s, 1)
+    };
+    num.parse::<u32>().ok()?.checked_mul(unit)
+}
+
+/// Parse `<initial>[:<max>]`, e.g. `500ms:10s`.
+pub fn parse_backoff(s: &str) -> Option<(u32, u32)> {
+    let (initial, max) = match s.split_once(':') {
+        Some((i, m)) => (parse_delay_ms(i)?, parse_delay_ms(m)?),
+        None => {
+            let i = parse_delay_ms(s)?;
+            (i, i.max(DEFAULT_BACKOFF_MAX_MS))
+        }
+    };
+    (initial <= max).then_some((initial, max))
+}
+
+impl SupervisorPolicy {
+    /// Delay before restart number `restarts + 1`, or `None` once the
+    /// budget is used up.
+    pub fn restart_delay(&self, restarts: u32) -> Option<Duration> {
+        if restarts >= self.max_restarts {
+            return None;
+        }
+        let factor = 1u64 << restarts.min(31);
+        let ms = (self.backoff_initial_ms as u64)
+            .saturating_mul(factor)
+            .min(self.backoff_max_ms as u64);
+        Some(Duration::from_millis(ms))
+    }
+
+    /// `[flags u8][max_restarts u32][initial u32][max u32]` followed by
+    /// `[len u16][command]` for each hook that is set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.pre_start.is_some() {
+            flags |= FLAG_PRE_START;
+        }
+        if self.post_exit.is_some() {
+            flags |= FLAG_POST_EXIT;
+        }
+        let mut out = vec![flags];
+        out.extend_from_slice(&self.max_restarts.to_le_bytes());
+        out.extend_from_slice(&self.backoff_initial_ms.to_le_bytes());
+        out.extend_from_slice(&self.backoff_max_ms.to_le_bytes());
+        for cmd in [&self.pre_start, &self.post_exit].into_iter().flatten() {
+            out.extend_from_slice(&(cmd.len() as u16).to_le_bytes());
+            out.extend_from_slice(cmd.as_bytes());
+        }
+        out
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut r = ByteReader::new(data, "supervisor policy");
+        let flags = r.u8()?;
+        if flags & !(FLAG_PRE_START | FLAG_POST_EXIT) != 0 {
+            return Err(supervise_err("Unknown supervisor flags"));
+        }
+        let mut policy = Self {
+            max_restarts: r.u32()?,
+            backoff_initial_ms: r.u32()?,
+            backoff_max_ms: r.u32()?,
+            ..Self::default()
+        };
+        if policy.backoff_initial_ms > policy.backoff_max_ms {
+            return Err(supervise_err("Invalid restart backoff"));
+        }
+        if flags & FLAG_PRE_START != 0 {
+            policy.pre_start = Some(read_command(&mut r)?);
+        }
+        if flags & FLAG_POST_EXIT != 0 {
+            policy.post_exit = Some(read_command(&mut r)?);
+        }
+        if !r.is_empty() {
+            return Err(supervise_err("Trailing bytes in supervisor policy"));
+        }
+        Ok(policy)
+    }
+}
+
+/// `[len u16][utf8]`, non-empty and without NUL.
+fn read_command(r: &mut ByteReader<'_>) -> io::Result<String> {
+    let s = r.str16()?;
+    if s.is_empty() || s.contains('\0') {
+        return Err(supervise_err("Invalid hook command"));
+    }
+    Ok(s.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> SupervisorPolicy {
+        SupervisorPolicy {
+            pre_start: Some("mkdir -p /run/app".into()),
+            post_exit: Some("logger \"app exited $XSFX_EXIT_CODE\"".into()),
+            max_restarts: 5,
+            backoff_initial_ms: 250,
+            backoff_max_ms: 2_000,
+        }
+    }
+
+    #[test]
+    fn test_policy_roundtrip() {
+        let p = sample();
+        assert_eq!(SupervisorPolicy::from_bytes(&p.to_bytes()).unwrap(), p);
+        let d = SupervisorPolicy::default();
+        assert_eq!(SupervisorPolicy::from_bytes(&d.to_bytes()).unwrap(), d);
+    }
+
+    #[test]
+    fn test_policy_rejects_malformed() {
+        let bytes = sample().to_bytes();
+        for n in 0..bytes.len() {
+            assert!(SupervisorPolicy::from_bytes(&bytes[..n]).is_err(), "{n}");
+        }
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(SupervisorPolicy::from_bytes(&extra).is_err());
+        let mut flags = bytes.clone();
+        flags[0] |= 0x80;
+        assert!(SupervisorPolicy::from_bytes(&flags).is_err());
+    }
+
+    #[test]
+    fn test_restart_delay_backoff() {
+        let p = sample();
+        let ms = |n| p.restart_delay(n).map(|d| d.as_millis());
+        assert_eq!(ms(0), Some(250));
+        assert_eq!(ms(1), Some(500));
+        assert_eq!(ms(3), Some(2_000));
+        assert_eq!(ms(4), Some(2_000));
+        assert_eq!(ms(5), None);
+        assert_eq!(SupervisorPolicy::default().restart_delay(0), None);
+    }
+
+    #[test]
+    fn test_restart_delay_no_overflow() {
+        let p = SupervisorPolicy {
+            max_restarts: u32::MAX,
+            backoff_initial_ms: u32::MAX,
+            backoff_max_ms: u32::MAX,
+            ..SupervisorPolicy::default()
+        };
+        assert_eq!(
+            p.restart_delay(100),
+            Some(Duration::from_millis(u32::MAX as u64))
+        );
+    }
+
+    #[test]
+    fn test_parse_delay_and_backoff() {
+        assert_eq!(parse_delay_ms("250"), Some(250));
+        assert_eq!(parse_delay_ms("250ms"), Some(250));
+        assert_eq!(parse_delay_ms("2s"), Some(2_000));
+        assert_eq!(parse_delay_ms("1m"), Some(60_000));
+        assert_eq!(parse_delay_ms("1h"), None);
+        assert_eq!(parse_backoff("500ms:10s"), Some((500, 10_000)));
+        assert_eq!(parse_backoff("1s"), Some((1_000, DEFAULT_BACKOFF_MAX_MS)));
+        assert_eq!(parse_backoff("1m"), Some((60_000, 60_000)));
+        assert_eq!(parse_backoff("10s:1s"), None);
+    }
+
+    #[test]
+    fn test_child_status() {
+        assert!(!ChildStatus::Exited(0).is_crash());
+        assert!(ChildStatus::Exited(1).is_crash());
+        assert!(ChildStatus::Signaled(libc::SIGSEGV).is_crash());
+        assert!(!ChildStatus::Exited(StubExit::Sandbox.code()).is_crash());
+        assert_eq!(ChildStatus::Signaled(9).shell_code(), 137);
+        assert_eq!(ChildStatus::Signaled(9).to_string(), "killed by signal 9");
+        assert_eq!(
+            ChildStatus::Exited(3).hook_env(),
+            (ENV_EXIT_CODE, "3".to_string())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_from_wait_status() {
+        use std::os::unix::process::ExitStatusExt;
+        let exited = std::process::ExitStatus::from_raw(7 << 8).into_raw();
+        assert_eq!(
+            ChildStatus::from_wait_status(exited),
+            Some(ChildStatus::Exited(7))
+        );
+        assert_eq!(
+            ChildStatus::from_wait_status(libc::SIGTERM),
+            Some(ChildStatus::Signaled(libc::SIGTERM))
+        );
+        // Stopped by SIGSTOP.
+        assert_eq!(ChildStatus::from_wait_status(0x137f), None);
+    }
+}
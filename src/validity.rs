@@ -0,0 +1,216 @@
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::metadata::Metadata;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// Current time in seconds since the Unix epoch.
+pub fn now_unix() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Parse a pack-time timestamp (UTC):
+/// - `1767225600` — seconds since the Unix epoch
+/// - `+30d`, `+12h`, `+90m`, `+45s` — relative to `now`
+/// - `2026-01-01`, `2026-01-01T12:00`, `2026-01-01T12:00:00Z`
+pub fn parse_time(s: &str, now: u64) -> Option<u64> {
+    let s = s.trim();
+    if let Some(rel) = s.strip_prefix('+') {
+        let unit = match rel.chars().last()? {
+            's' => 1,
+            'm' => 60,
+            'h' => 3_600,
+            'd' => SECS_PER_DAY,
+            _ => return None,
+        };
+        let n: u64 = rel[..rel.len() - 1].parse().ok()?;
+        return now.checked_add(n.checked_mul(unit)?);
+    }
+    if s.bytes().all(|b| b.is_ascii_digit()) {
+        return s.parse().ok();
+    }
+    parse_datetime(s)
+}
+
+fn parse_datetime(s: &str) -> Option<u64> {
+    let s = s.strip_suffix('Z').unwrap_or(s);
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((d, t)) => (d, Some(t)),
+        None => (s, None),
+    };
+    let mut d = date.splitn(3, '-');
+    let year: i64 = d.next()?.parse().ok()?;
+    let month: u32 = d.next()?.parse().ok()?;
+    let day: u32 = d.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    let mut secs = 0u64;
+    if let Some(time) = time {
+        let mut t = time.split(':');
+        let h: u64 = t.next()?.parse().ok()?;
+        let m: u64 = t.next()?.parse().ok()?;
+        let sec: u64 = t.next().map_or(Some(0), |v| v.parse().ok())?;
+        if t.next().is_some() || h > 23 || m > 59 || sec > 59 {
+            return None;
+        }
+        secs = h * 3_600 + m * 60 + sec;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * SECS_PER_DAY + secs)
+}
+
+fn is_leap(y: i64) -> bool {
+    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if is_leap(y) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Howard Hinnant's days-from-civil / civil-from-days algorithms.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + i64::from(m <= 2);
+    (y, m, d)
+}
+
+/// `YYYY-MM-DDTHH:MM:SSZ` for a Unix timestamp.
+pub fn format_utc(secs: u64) -> String {
+    let (y, m, d) = civil_from_days((secs / SECS_PER_DAY) as i64);
+    let rem = secs % SECS_PER_DAY;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        y,
+        m,
+        d,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Refuse to run outside the recorded `[not_before, not_after)` window.
+pub fn check_window(meta: &Metadata, now: u64) -> io::Result<()> {
+    if let Some(nb) = meta.not_before {
+        if now < nb {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("This build is not valid before {}", format_utc(nb)),
+            ));
+        }
+    }
+    if let Some(na) = meta.not_after {
+        if now >= na {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("This build expired at {}", format_utc(na)),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_767_225_600; // 2026-01-01T00:00:00Z
+
+    #[test]
+    fn test_parse_time_epoch_and_relative() {
+        assert_eq!(parse_time("12345", NOW), Some(12345));
+        assert_eq!(parse_time("+30d", NOW), Some(NOW + 30 * SECS_PER_DAY));
+        assert_eq!(parse_time("+2h", NOW), Some(NOW + 7_200));
+        assert_eq!(parse_time("+90m", NOW), Some(NOW + 5_400));
+        assert_eq!(parse_time("+5s", NOW), Some(NOW + 5));
+        assert_eq!(parse_time("+5w", NOW), None);
+        assert_eq!(parse_time("+d", NOW), None);
+    }
+
+    #[test]
+    fn test_parse_time_dates() {
+        assert_eq!(parse_time("2026-01-01", 0), Some(NOW));
+        assert_eq!(parse_time("2026-01-01T01:02", 0), Some(NOW + 3_720));
+        assert_eq!(parse_time("2026-01-01T01:02:03Z", 0), Some(NOW + 3_723));
+        assert_eq!(parse_time("2024-02-29", 0), Some(1_709_164_800));
+        assert_eq!(parse_time("1970-01-01", 0), Some(0));
+    }
+
+    #[test]
+    fn test_parse_time_invalid() {
+        for s in [
+            "2025-02-29",
+            "2026-13-01",
+            "2026-01-00",
+            "2026-01-01T24:00",
+            "2026-01-01T10:00:00:00",
+            "1969-12-31",
+            "yesterday",
+            "",
+        ] {
+            assert_eq!(parse_time(s, NOW), None, "{s}");
+        }
+    }
+
+    #[test]
+    fn test_format_utc_roundtrip() {
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_utc(NOW + 3_723), "2026-01-01T01:02:03Z");
+        for secs in [1_709_164_800, 951_782_400, 4_102_444_799] {
+            assert_eq!(parse_time(&format_utc(secs), 0), Some(secs));
+        }
+    }
+
+    #[test]
+    fn test_check_window() {
+        let meta = Metadata {
+            not_before: Some(NOW),
+            not_after: Some(NOW + 10),
+            ..Metadata::default()
+        };
+        assert!(check_window(&meta, NOW).is_ok());
+        assert!(check_window(&meta, NOW + 9).is_ok());
+        assert!(check_window(&Metadata::default(), 0).is_ok());
+    }
+
+    #[test]
+    fn test_sec_uc010_outside_window_rejected() {
+        let meta = Metadata {
+            not_before: Some(NOW),
+            not_after: Some(NOW + 10),
+            ..Metadata::default()
+        };
+        let early = check_window(&meta, NOW - 1).unwrap_err();
+        assert!(early.to_string().contains("not valid before 2026-01-01"));
+        let late = check_window(&meta, NOW + 10).unwrap_err();
+        assert_eq!(late.kind(), io::ErrorKind::PermissionDenied);
+        assert!(late.to_string().contains("expired"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::io;
+
+/// Run this variant instead of the best one for the CPU.
+pub const ENV_VARIANT: &str = "XSFX_VARIANT";
+
+/// Known variant levels, per architecture from baseline to most demanding.
+/// Each level implies the ones before it.
+pub const LEVELS: [&str; 8] = [
+    "x86-64",
+    "x86-64-v2",
+    "x86-64-v3",
+    "x86-64-v4",
+    "aarch64",
+    "aarch64-lse",
+    "aarch64-sve",
+    "aarch64-sve2",
+];
+
+/// Baseline level for a target triple, if variants exist for its
+/// architecture.
+pub fn baseline_level(target: &str) -> Option<&'static str> {
+    if target.starts_with("x86_64-") {
+        Some("x86-64")
+    } else if target.starts_with("aarch64-") {
+        Some("aarch64")
+    } else {
+        None
+    }
+}
+
+/// Whether `level` belongs to the same architecture as `baseline`.
+pub fn same_arch(level: &str, baseline: &str) -> bool {
+    LEVELS.contains(&level) && level.starts_with(baseline)
+}
+
+/// Parse `--variant <level>=<path>`.
+pub fn parse_variant(s: &str) -> Option<(String, String)> {
+    let (level, path) = s.split_once('=')?;
+    if !LEVELS.contains(&level) || path.is_empty() {
+        return None;
+    }
+    Some((level.to_string(), path.to_string()))
+}
+
+/// Levels the running CPU supports (`cpuid` on x86-64, `HWCAP` on
+/// aarch64), in `LEVELS` order.
+pub fn host_levels() -> Vec<&'static str> {
+    let mut levels = Vec::new();
+    #[cfg(target_arch = "x86_64")]
+    {
+        use std::arch::is_x86_feature_detected as has;
+        levels.push("x86-64");
+        let v2 = has!("cmpxchg16b")
+            && has!("popcnt")
+            && has!("sse3")
+            && has!("ssse3")
+            && has!("sse4.1")
+            && has!("sse4.2");
+        let v3 = v2
+            && has!("avx")
+            && has!("avx2")
+            && has!("bmi1")
+            && has!("bmi2")
+            && has!("f16c")
+            && has!("fma")
+            && has!("lzcnt")
+            && has!("movbe")
+            && has!("xsave");
+        let v4 = v3
+            && has!("avx512f")
+            && has!("avx512bw")
+            && has!("avx512cd")
+            && has!("avx512dq")
+            && has!("avx512vl");
+        for (ok, level) in [(v2, "x86-64-v2"), (v3, "x86-64-v3"), (v4, "x86-64-v4")] {
+            if ok {
+                levels.push(level);
+            }
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        use std::arch::is_aarch64_feature_detected as has;
+        levels.push("aarch64");
+        let lse = has!("lse");
+        let sve = lse && has!("sve");
+        let sve2 = sve && has!("sve2");
+        for (ok, level) in [
+            (lse, "aarch64-lse"),
+            (sve, "aarch64-sve"),
+            (sve2, "aarch64-sve2"),
+        ] {
+            if ok {
+                levels.push(level);
+            }
+        }
+    }
+    levels
+}
+
+/// Index of the variant to run: the one named by `forced`
+/// (`XSFX_VARIANT`), else the highest level in `supported`.
+pub fn choose_variant(
+    variants: &[&str],
+    supported: &[&str],
+    forced: Option<&str>,
+) -> io::Result<usize> {
+    if let Some(name) = forced {
+        return variants.iter().position(|v| *v == name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}={} is not packed; variants: {}",
+                    ENV_VARIANT,
+                    name,
+                    variants.join(", ")
+                ),
+            )
+        });
+    }
+    let rank = |v: &str| LEVELS.iter().position(|l| *l == v);
+    variants
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| supported.contains(v))
+        .max_by_key(|(_, v)| rank(v))
+        .map(|(i, _)| i)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "No variant runs on this CPU (packed: {}; supported: {})",
+                    variants.join(", "),
+                    supported.join(", ")
+                ),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_choose_best_supported() {
+        let packed = ["x86-64", "x86-64-v3", "x86-64-v4"];
+        let v3_cpu = ["x86-64", "x86-64-v2", "x86-64-v3"];
+        assert_eq!(choose_variant(&packed, &v3_cpu, None).unwrap(), 1);
+        assert_eq!(choose_variant(&packed, &["x86-64"], None).unwrap(), 0);
+        // Order in the archive does not matter.
+        let shuffled = ["x86-64-v3", "x86-64"];
+        assert_eq!(choose_variant(&shuffled, &v3_cpu, None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_choose_forced() {
+        let packed = ["x86-64", "x86-64-v3"];
+        assert_eq!(
+            choose_variant(&packed, &["x86-64"], Some("x86-64-v3")).unwrap(),
+            1
+        );
+        let err = choose_variant(&packed, &["x86-64"], Some("x86-64-v4")).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_choose_none_supported() {
+        let err = choose_variant(&["x86-64-v4"], &["x86-64"], None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_host_levels_start_with_baseline() {
+        let levels = host_levels();
+        #[cfg(target_arch = "x86_64")]
+        assert_eq!(levels.first(), Some(&"x86-64"));
+        #[cfg(target_arch = "aarch64")]
+        assert_eq!(levels.first(), Some(&"aarch64"));
+        assert!(levels.iter().all(|l| LEVELS.contains(l)));
+    }
+
+    #[test]
+    fn test_parse_variant() {
+        assert_eq!(
+            parse_variant("x86-64-v3=build/v3/app"),
+            Some(("x86-64-v3".into(), "build/v3/app".into()))
+        );
+        assert_eq!(parse_variant("x86-64-v5=app"), None);
+        assert_eq!(parse_variant("x86-64-v3="), None);
+        assert_eq!(parse_variant("app"), None);
+        assert!(same_arch("x86-64-v3", "x86-64"));
+        assert!(!same_arch("aarch64-sve", "x86-64"));
+        assert_eq!(
+            baseline_level("aarch64-unknown-linux-musl"),
+            Some("aarch64")
+        );
+        assert_eq!(baseline_level("riscv64gc-unknown-linux-gnu"), None);
+    }
+}
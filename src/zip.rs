@@ -0,0 +1,208 @@
+use std::io;
+
+/// Size of a local file header without the name.
+pub const LOCAL_HEADER_SIZE: u64 = 30;
+/// Size of the end-of-central-directory record without the comment.
+pub const EOCD_SIZE: u64 = 22;
+
+const LOCAL_SIG: u32 = 0x0403_4b50;
+const CENTRAL_SIG: u32 = 0x0201_4b50;
+const EOCD_SIG: u32 = 0x0605_4b50;
+const VERSION_NEEDED: u16 = 20;
+/// Made by Unix (3), so the external attributes carry a mode.
+const VERSION_MADE_BY: u16 = (3 << 8) | 20;
+const METHOD_DEFLATE: u16 = 8;
+/// General purpose flag: the name is UTF-8.
+const FLAG_UTF8: u16 = 1 << 11;
+/// 1980-01-01 00:00, so identical inputs produce identical archives.
+const DOS_TIME: u16 = 0;
+const DOS_DATE: u16 = 0x21;
+const UNIX_MODE: u32 = 0o100755;
+
+fn zip_err(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// CRC-32 (IEEE), as used by ZIP.
+pub fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut c = i as u32;
+            let mut k = 0;
+            while k < 8 {
+                c = if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                };
+                k += 1;
+            }
+            table[i] = c;
+            i += 1;
+        }
+        table
+    };
+    let mut crc = !0u32;
+    for &b in data {
+        crc = TABLE[((crc ^ b as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// A single Deflate-compressed entry.
+#[derive(Debug, Clone)]
+pub struct ZipEntry {
+    pub name: String,
+    pub crc32: u32,
+    pub compressed_len: u32,
+    pub uncompressed_len: u32,
+}
+
+impl ZipEntry {
+    /// Describe `data` compressed to `compressed_len` bytes. Fails if
+    /// either size needs ZIP64 or the name is not a plain file name.
+    pub fn new(name: &str, data: &[u8], compressed_len: usize) -> io::Result<Self> {
+        if name.is_empty() || name.len() > u16::MAX as usize || name.contains(['/', '\\', '\0']) {
+            return Err(zip_err("Invalid ZIP entry name"));
+        }
+        let too_big = || zip_err("Payload too large for a ZIP entry (4 GiB limit)");
+        Ok(Self {
+            name: name.to_string(),
+            crc32: crc32(data),
+            compressed_len: u32::try_from(compressed_len).map_err(|_| too_big())?,
+            uncompressed_len: u32::try_from(data.len()).map_err(|_| too_big())?,
+        })
+    }
+
+    /// Local file header; the compressed data follows it directly.
+    pub fn local_header(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(LOCAL_HEADER_SIZE as usize + self.name.len());
+        out.extend_from_slice(&LOCAL_SIG.to_le_bytes());
+        out.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        self.push_common(&mut out);
+        out.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        out.extend_from_slice(self.name.as_bytes());
+        out
+    }
+
+    /// Central directory header for an entry whose local header starts at
+    /// `local_offset`.
+    pub fn central_header(&self, local_offset: u32) -> Vec<u8> {
+        let mut out = Vec::with_capacity(46 + self.name.len());
+        out.extend_from_slice(&CENTRAL_SIG.to_le_bytes());
+        out.extend_from_slice(&VERSION_MADE_BY.to_le_bytes());
+        out.extend_from_slice(&VERSION_NEEDED.to_le_bytes());
+        self.push_common(&mut out);
+        out.extend_from_slice(&0u16.to_le_bytes()); // extra field length
+        out.extend_from_slice(&0u16.to_le_bytes()); // comment length
+        out.extend_from_slice(&0u16.to_le_bytes()); // disk number
+        out.extend_from_slice(&0u16.to_le_bytes()); // internal attributes
+        out.extend_from_slice(&(UNIX_MODE << 16).to_le_bytes());
+        out.extend_from_slice(&local_offset.to_le_bytes());
+        out.extend_from_slice(self.name.as_bytes());
+        out
+    }
+
+    /// Fields shared by both headers, from the flags to the name length.
+    fn push_common(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&FLAG_UTF8.to_le_bytes());
+        out.extend_from_slice(&METHOD_DEFLATE.to_le_bytes());
+        out.extend_from_slice(&DOS_TIME.to_le_bytes());
+        out.extend_from_slice(&DOS_DATE.to_le_bytes());
+        out.extend_from_slice(&self.crc32.to_le_bytes());
+        out.extend_from_slice(&self.compressed_len.to_le_bytes());
+        out.extend_from_slice(&self.uncompressed_len.to_le_bytes());
+        out.extend_from_slice(&(self.name.len() as u16).to_le_bytes());
+    }
+}
+
+/// End-of-central-directory record for a single-entry archive whose
+/// central directory of `cd_len` bytes starts at `cd_offset`. The
+/// `comment_len` bytes of comment (the SFX metadata and trailer) are
+/// written by the caller after it. Offsets are absolute from the start of
+/// the file, stub included; ZIP readers accept such prefixed archives.
+pub fn end_of_central_directory(
+    cd_len: u32,
+    cd_offset: u32,
+    comment_len: usize,
+) -> io::Result<Vec<u8>> {
+    let comment_len = u16::try_from(comment_len)
+        .map_err(|_| zip_err("SFX metadata too large for a ZIP comment"))?;
+    let mut out = Vec::with_capacity(EOCD_SIZE as usize);
+    out.extend_from_slice(&EOCD_SIG.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes()); // this disk
+    out.extend_from_slice(&0u16.to_le_bytes()); // disk with the central directory
+    out.extend_from_slice(&1u16.to_le_bytes()); // entries on this disk
+    out.extend_from_slice(&1u16.to_le_bytes()); // entries in total
+    out.extend_from_slice(&cd_len.to_le_bytes());
+    out.extend_from_slice(&cd_offset.to_le_bytes());
+    out.extend_from_slice(&comment_len.to_le_bytes());
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn u16_at(b: &[u8], i: usize) -> u16 {
+        u16::from_le_bytes([b[i], b[i + 1]])
+    }
+
+    fn u32_at(b: &[u8], i: usize) -> u32 {
+        u32::from_le_bytes(b[i..i + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_local_header_layout() {
+        let entry = ZipEntry::new("app", b"hello", 7).unwrap();
+        let h = entry.local_header();
+        assert_eq!(h.len() as u64, LOCAL_HEADER_SIZE + 3);
+        assert_eq!(u32_at(&h, 0), LOCAL_SIG);
+        assert_eq!(u16_at(&h, 8), METHOD_DEFLATE);
+        assert_eq!(u32_at(&h, 14), crc32(b"hello"));
+        assert_eq!(u32_at(&h, 18), 7);
+        assert_eq!(u32_at(&h, 22), 5);
+        assert_eq!(u16_at(&h, 26), 3);
+        assert_eq!(&h[30..], b"app");
+    }
+
+    #[test]
+    fn test_central_header_layout() {
+        let entry = ZipEntry::new("app", b"hello", 7).unwrap();
+        let h = entry.central_header(1234);
+        assert_eq!(h.len(), 46 + 3);
+        assert_eq!(u32_at(&h, 0), CENTRAL_SIG);
+        assert_eq!(u32_at(&h, 20), 7);
+        assert_eq!(u32_at(&h, 24), 5);
+        assert_eq!(u32_at(&h, 38) >> 16, UNIX_MODE);
+        assert_eq!(u32_at(&h, 42), 1234);
+        assert_eq!(&h[46..], b"app");
+    }
+
+    #[test]
+    fn test_end_of_central_directory() {
+        let e = end_of_central_directory(49, 5000, 300).unwrap();
+        assert_eq!(e.len() as u64, EOCD_SIZE);
+        assert_eq!(u32_at(&e, 0), EOCD_SIG);
+        assert_eq!(u16_at(&e, 10), 1);
+        assert_eq!(u32_at(&e, 12), 49);
+        assert_eq!(u32_at(&e, 16), 5000);
+        assert_eq!(u16_at(&e, 20), 300);
+    }
+
+    #[test]
+    fn test_sec_zip_limits() {
+        assert!(end_of_central_directory(0, 0, 65_536).is_err());
+        assert!(ZipEntry::new("", b"x", 1).is_err());
+        assert!(ZipEntry::new("../x", b"x", 1).is_err());
+        assert!(ZipEntry::new("x", b"x", u32::MAX as usize + 1).is_err());
+    }
+}
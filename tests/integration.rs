@@ -1,8 +1,16 @@
-use std::io::{BufReader, Cursor, Write};
-
-use xsfx::common::{Trailer, MAGIC, TRAILER_SIZE};
-use xsfx::compress::compress_lzma;
-use xsfx::decompress::decompress_payload;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+
+use xsfx::common::{Trailer, MAGIC, MAGIC_META, TRAILER_SIZE};
+use xsfx::compress::{compress_deflate, compress_lzma};
+use xsfx::crypto::Encryption;
+use xsfx::decompress::{decompress_payload, inflate_payload_exact};
+use xsfx::digest::sha256;
+use xsfx::elf::{is_elf, parse_elf};
+use xsfx::layout::read_layout;
+use xsfx::metadata::{Codec, Metadata};
+use xsfx::multiarch::{build_launcher, read_launcher, LAUNCHER_ARCHES};
+use xsfx::strip::{strip_elf, StripMode};
+use xsfx::zip::{end_of_central_directory, ZipEntry};
 
 /// Assemble an SFX into a writer (mirrors packer write_sfx logic).
 fn assemble_sfx(stub: &[u8], payload: &[u8], writer: &mut dyn Write) -> u64 {
@@ -100,6 +108,196 @@ fn test_trailer_preserves_stub_offset() {
     assert_eq!(&sfx[..stub_end], &stub);
 }
 
+#[test]
+fn test_sfx_with_metadata_block() {
+    let stub = b"STUB";
+    let payload = b"payload with metadata";
+    let meta = Metadata {
+        expose_env: true,
+        payload_version: Some("2.0".into()),
+        payload_sha256: Some(sha256(payload)),
+        payload_len: Some(payload.len() as u64),
+        ..Metadata::default()
+    };
+    let compressed = compress_lzma(payload).unwrap();
+
+    // Assemble SFX: [stub][compressed][metadata][trailer]
+    let mut sfx = Vec::new();
+    sfx.extend_from_slice(stub);
+    sfx.extend_from_slice(&compressed);
+    sfx.extend_from_slice(&meta.to_bytes().unwrap());
+    sfx.extend_from_slice(&Trailer::with_metadata(compressed.len() as u64).to_bytes());
+
+    let t_off = sfx.len() as u64 - TRAILER_SIZE;
+    let parsed = Trailer::from_reader(Cursor::new(&sfx[t_off as usize..])).unwrap();
+    assert_eq!(parsed.magic, MAGIC_META);
+    let (read_meta, meta_len) = Metadata::read_before(&mut Cursor::new(&sfx), t_off).unwrap();
+    assert_eq!(read_meta, meta);
+
+    let p_end = (t_off - meta_len) as usize;
+    let p_start = p_end - parsed.payload_len as usize;
+    assert_eq!(p_start, stub.len());
+    let mut reader = BufReader::new(Cursor::new(&sfx[p_start..p_end]));
+    let result = decompress_payload(&mut reader).unwrap();
+    assert_eq!(result, payload);
+    assert_eq!(read_meta.payload_sha256.unwrap(), sha256(&result));
+}
+
+#[test]
+fn test_sfx_with_appended_overlay() {
+    let stub = b"STUB-with-some-length";
+    let payload = b"payload followed by a signature blob";
+    let meta = Metadata {
+        payload_sha256: Some(sha256(payload)),
+        payload_len: Some(payload.len() as u64),
+        payload_offset: Some(stub.len() as u64),
+        ..Metadata::default()
+    };
+    let compressed = compress_lzma(payload).unwrap();
+    let mut sfx = Vec::new();
+    sfx.extend_from_slice(stub);
+    sfx.extend_from_slice(&compressed);
+    sfx.extend_from_slice(&meta.to_bytes().unwrap());
+    sfx.extend_from_slice(&Trailer::with_metadata(compressed.len() as u64).to_bytes());
+    let sfx_len = sfx.len();
+    sfx.extend_from_slice(&[0x5A; 4096]);
+
+    let layout = read_layout(&mut Cursor::new(&sfx)).unwrap();
+    assert_eq!(layout.payload_offset, stub.len() as u64);
+    assert_eq!(layout.overlay_len, (sfx.len() - sfx_len) as u64);
+    let start = layout.payload_offset as usize;
+    let end = start + layout.payload_len as usize;
+    let mut reader = BufReader::new(Cursor::new(&sfx[start..end]));
+    assert_eq!(decompress_payload(&mut reader).unwrap(), payload);
+}
+
+#[test]
+fn test_sfx_zip_layout() {
+    let stub = b"STUB-zip";
+    let payload = b"payload stored as a zip entry ".repeat(20);
+    let compressed = compress_deflate(&payload);
+    let entry = ZipEntry::new("app", &payload, compressed.len()).unwrap();
+    let local = entry.local_header();
+    let meta = Metadata {
+        payload_sha256: Some(sha256(&payload)),
+        payload_len: Some(payload.len() as u64),
+        payload_offset: Some((stub.len() + local.len()) as u64),
+        codec: Codec::Deflate,
+        ..Metadata::default()
+    };
+    let central = entry.central_header(stub.len() as u32);
+    let meta_bytes = meta.to_bytes().unwrap();
+    let cd_offset = (stub.len() + local.len() + compressed.len()) as u32;
+    let eocd =
+        end_of_central_directory(central.len() as u32, cd_offset, meta_bytes.len() + 16).unwrap();
+    let mut sfx = stub.to_vec();
+    for part in [&local, &compressed, &central, &eocd, &meta_bytes] {
+        sfx.extend_from_slice(part);
+    }
+    sfx.extend_from_slice(&Trailer::with_metadata(compressed.len() as u64).to_bytes());
+
+    let layout = read_layout(&mut Cursor::new(&sfx)).unwrap();
+    assert_eq!(layout.metadata.codec, Codec::Deflate);
+    let start = layout.payload_offset as usize;
+    let end = start + layout.payload_len as usize;
+    assert_eq!(&sfx[start..end], &compressed[..]);
+    let inflated = inflate_payload_exact(&sfx[start..end], payload.len() as u64).unwrap();
+    assert_eq!(inflated, payload);
+    // The EOCD comment runs exactly to the end of the file.
+    let eocd_at = sfx.len() - meta_bytes.len() - 16 - eocd.len();
+    assert_eq!(&sfx[eocd_at..eocd_at + 4], b"PK\x05\x06");
+}
+
+#[test]
+fn test_multiarch_launcher_images() {
+    let image = |stub: &[u8], payload: &[u8]| {
+        let compressed = compress_lzma(payload).unwrap();
+        let meta = Metadata {
+            payload_sha256: Some(sha256(payload)),
+            payload_offset: Some(stub.len() as u64),
+            ..Metadata::default()
+        };
+        let mut sfx = stub.to_vec();
+        sfx.extend_from_slice(&compressed);
+        sfx.extend_from_slice(&meta.to_bytes().unwrap());
+        sfx.extend_from_slice(&Trailer::with_metadata(compressed.len() as u64).to_bytes());
+        sfx
+    };
+    let x86 = image(b"STUB-x86_64", b"x86_64 payload");
+    let arm = image(b"STUB-aarch64", b"aarch64 payload");
+    let file = build_launcher(&[(LAUNCHER_ARCHES[0], &x86), (LAUNCHER_ARCHES[1], &arm)]).unwrap();
+
+    let mut cursor = Cursor::new(&file);
+    let images = read_launcher(&mut cursor).unwrap().unwrap();
+    let names: Vec<_> = images.iter().map(|i| i.arch.as_str()).collect();
+    assert_eq!(names, ["x86_64", "aarch64"]);
+    let expected: [&[u8]; 2] = [b"x86_64 payload", b"aarch64 payload"];
+    for (img, expected) in images.iter().zip(expected) {
+        // Each image is a complete SFX for the existing parsing code.
+        let mut region = img.reader(&mut cursor);
+        let layout = read_layout(&mut region).unwrap();
+        region.seek(SeekFrom::Start(layout.payload_offset)).unwrap();
+        let mut reader = BufReader::new((&mut region).take(layout.payload_len));
+        assert_eq!(decompress_payload(&mut reader).unwrap(), expected);
+    }
+    // A plain SFX is not a launcher.
+    assert!(read_launcher(&mut Cursor::new(&x86)).unwrap().is_none());
+}
+
+#[test]
+fn test_strip_test_binary() {
+    // On Linux the test binary is an ELF image with symbols.
+    let exe = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+    if !is_elf(&exe) {
+        return;
+    }
+    let original = parse_elf(&exe).unwrap();
+    let debug = strip_elf(&exe, StripMode::Debug).unwrap();
+    let all = strip_elf(&debug.data, StripMode::All).unwrap();
+    assert!(all.removed.iter().any(|name| name == ".symtab"));
+    assert!(debug.data.len() <= exe.len() && all.data.len() < debug.data.len());
+    for data in [&debug.data, &all.data] {
+        let info = parse_elf(data).unwrap();
+        assert_eq!(info.program_headers, original.program_headers);
+        assert_eq!(info.build_id, original.build_id);
+    }
+}
+
+#[test]
+fn test_sfx_encrypted_payload() {
+    let payload = b"proprietary model weights";
+    let enc = Encryption::generate(false).unwrap();
+    let key = enc.derive_key(&[0x5A; 32]).unwrap();
+    let meta = Metadata {
+        payload_sha256: Some(sha256(payload)),
+        payload_len: Some(payload.len() as u64),
+        encryption: Some(enc.clone()),
+        ..Metadata::default()
+    };
+    let compressed = compress_lzma(payload).unwrap();
+    let sealed = enc
+        .encrypt(&key, &meta.aad().unwrap(), &compressed)
+        .unwrap();
+    assert!(!sealed.windows(6).any(|w| w == b"\xFD7zXZ\0"));
+
+    let mut sfx = b"STUB".to_vec();
+    sfx.extend_from_slice(&sealed);
+    sfx.extend_from_slice(&meta.to_bytes().unwrap());
+    sfx.extend_from_slice(&Trailer::with_metadata(sealed.len() as u64).to_bytes());
+
+    let t_off = sfx.len() as u64 - TRAILER_SIZE;
+    let parsed = Trailer::from_reader(Cursor::new(&sfx[t_off as usize..])).unwrap();
+    let (read_meta, meta_len) = Metadata::read_before(&mut Cursor::new(&sfx), t_off).unwrap();
+    let p_end = (t_off - meta_len) as usize;
+    let p_start = p_end - parsed.payload_len as usize;
+    let read_enc = read_meta.encryption.clone().unwrap();
+    let opened = read_enc
+        .decrypt(&key, &read_meta.aad().unwrap(), &sfx[p_start..p_end])
+        .unwrap();
+    let mut reader = BufReader::new(Cursor::new(&opened[..]));
+    assert_eq!(decompress_payload(&mut reader).unwrap(), payload);
+}
+
 // --- [SEC] Security / adversarial tests ---
 
 #[test]
@@ -405,3 +603,25 @@ fn test_sec_uc001_write_sfx_repeated_no_leak() {
         assert!(!buf.is_empty());
     }
 }
+
+#[test]
+fn test_sec_uc009_encrypted_metadata_tamper_detected() {
+    let enc = Encryption::generate(false).unwrap();
+    let key = enc.derive_key(&[0x5A; 32]).unwrap();
+    let meta = Metadata {
+        encryption: Some(enc.clone()),
+        ..Metadata::default()
+    };
+    let compressed = compress_lzma(b"data").unwrap();
+    let sealed = enc
+        .encrypt(&key, &meta.aad().unwrap(), &compressed)
+        .unwrap();
+    // Flipping a recorded setting (e.g. enabling debug) must break decryption.
+    let tampered = Metadata {
+        debug: true,
+        ..meta
+    };
+    assert!(enc
+        .decrypt(&key, &tampered.aad().unwrap(), &sealed)
+        .is_err());
+}